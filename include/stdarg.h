@@ -0,0 +1,8 @@
+#ifndef __STDARG_H
+#define __STDARG_H
+
+// va_start, va_arg, va_end and va_copy are builtins of the compiler
+typedef __builtin_va_list va_list;
+typedef __builtin_va_list __gnuc_va_list;
+
+#endif
@@ -1,4 +1,4 @@
-extern int putchar(int c);
+extern int puts(char *s);
 
 static int main(void)
 {
@@ -55,20 +55,7 @@ static int main(void)
 
     y *= 20;
 
-    putchar(72);
-    putchar(101);
-    putchar(108);
-    putchar(108);
-    putchar(111);
-    putchar(44);
-    putchar(32);
-    putchar(87);
-    putchar(111);
-    putchar(114);
-    putchar(108);
-    putchar(100);
-    putchar(33);
-    putchar(10);
+    puts("Hello, World!");
 
     return y;
 }
\ No newline at end of file
@@ -1,25 +1,52 @@
+use crate::parser::Const;
 use crate::tacker;
+use crate::type_checker::{get_struct_entry, IdentifierAttr, StaticInit, SymbolTable, Type};
 use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct Program {
     pub functions: Vec<FunctionDefinition>,
+    pub variables: Vec<StaticVariable>,
+    pub constants: Vec<StaticConstant>,
+}
+
+#[derive(Debug)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub read_only: bool,
+    pub alignment: i64,
+    pub init: Vec<StaticInit>,
+}
+
+#[derive(Debug)]
+pub struct StaticConstant {
+    pub name: String,
+    pub alignment: i64,
+    pub init: StaticInit,
 }
 
 #[derive(Debug)]
 pub struct FunctionDefinition {
     pub name: String,
+    pub global: bool,
     pub instructions: Vec<Instruction>,
 }
 
 #[derive(Debug)]
 pub enum Instruction {
-    Mov(Operand, Operand),
-    Unary(UnaryOperator, Operand),
-    Binary(BinaryOperator, Operand, Operand),
-    Cmp(Operand, Operand),
-    Idiv(Operand),
-    Cdq,
+    Mov(AssemblyType, Operand, Operand),
+    Movsx(AssemblyType, AssemblyType, Operand, Operand), // src type, dst type, src, dst
+    MovZeroExtend(AssemblyType, AssemblyType, Operand, Operand), // src type, dst type, src, dst
+    Unary(UnaryOperator, AssemblyType, Operand),
+    Binary(BinaryOperator, AssemblyType, Operand, Operand),
+    Cmp(AssemblyType, Operand, Operand),
+    Idiv(AssemblyType, Operand),
+    Div(AssemblyType, Operand),
+    Cdq(AssemblyType),
+    Cvttsd2si(AssemblyType, Operand, Operand), // dst type, src, dst
+    Cvtsi2sd(AssemblyType, Operand, Operand),  // src type, src, dst
+    Lea(Operand, Operand),                     // src, dst
     Jmp(String),
     JmpCC(CondCode, String),
     SetCC(CondCode, Operand),
@@ -29,6 +56,16 @@ pub enum Instruction {
     DeallocateStack(i64),
     Push(Operand),
     Call(String),
+    CallIndirect(Operand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssemblyType {
+    Byte,
+    Longword,
+    Quadword,
+    Double,
+    ByteArray { size: i64, alignment: i64 },
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +84,8 @@ pub enum BinaryOperator {
     Xor,
     LeftShift,
     RightShift,
+    ArithmeticRightShift,
+    DivDouble,
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +94,10 @@ pub enum Operand {
     Reg(Reg),
     Pseudo(String),
     Stack(i64),
+    Memory(Reg, i64), // base register, offset
+    Data(String, i64),       // symbol, offset
+    PseudoMem(String, i64),  // aggregate identifier, offset
+    Indexed(Reg, Reg, i64), // base register, index register, scale
 }
 
 #[derive(Debug, Clone)]
@@ -68,6 +111,17 @@ pub enum Reg {
     R9,
     R10,
     R11,
+    SP,
+    XMM0,
+    XMM1,
+    XMM2,
+    XMM3,
+    XMM4,
+    XMM5,
+    XMM6,
+    XMM7,
+    XMM14,
+    XMM15,
 }
 
 #[derive(Debug, Clone)]
@@ -78,58 +132,836 @@ pub enum CondCode {
     GE,
     L,
     LE,
+    A,
+    AE,
+    B,
+    BE,
+    P, // unordered, a double comparison with NaN
+}
+
+const INT_ARG_REGISTERS: [Reg; 6] = [Reg::DI, Reg::SI, Reg::DX, Reg::CX, Reg::R8, Reg::R9];
+const DOUBLE_ARG_REGISTERS: [Reg; 8] = [
+    Reg::XMM0,
+    Reg::XMM1,
+    Reg::XMM2,
+    Reg::XMM3,
+    Reg::XMM4,
+    Reg::XMM5,
+    Reg::XMM6,
+    Reg::XMM7,
+];
+
+// The six integer argument registers followed by the eight xmm ones, 16 bytes apart
+const REG_SAVE_AREA_SIZE: i64 = 176;
+const INT_RETURN_REGISTERS: [Reg; 2] = [Reg::AX, Reg::DX];
+const DOUBLE_RETURN_REGISTERS: [Reg; 2] = [Reg::XMM0, Reg::XMM1];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParameterClass {
+    Memory,
+    Sse,
+    Integer,
+}
+
+// Classifies each eightbyte of a structure, anything larger than 16 bytes goes through memory
+fn classify_structure(tag: &str, symbol_table: &SymbolTable) -> Vec<ParameterClass> {
+    let size = get_struct_entry(tag, symbol_table).size;
+    let eightbytes = (size + 7) / 8;
+    if size > 16 {
+        return vec![ParameterClass::Memory; eightbytes as usize];
+    }
+    let mut scalars = Vec::new();
+    flatten_scalar_types(&Type::Structure(tag.to_string()), 0, &mut scalars, symbol_table);
+    (0..eightbytes)
+        .map(|i| {
+            if scalars
+                .iter()
+                .filter(|(offset, _)| offset / 8 == i)
+                .all(|(_, scalar_type)| *scalar_type == Type::Double)
+            {
+                ParameterClass::Sse
+            } else {
+                ParameterClass::Integer
+            }
+        })
+        .collect()
+}
+
+fn flatten_scalar_types(
+    object_type: &Type,
+    offset: i64,
+    scalars: &mut Vec<(i64, Type)>,
+    symbol_table: &SymbolTable,
+) {
+    match object_type.unqualified() {
+        Type::Structure(tag) => {
+            for member in &get_struct_entry(tag, symbol_table).members {
+                flatten_scalar_types(
+                    &member.member_type,
+                    offset + member.offset,
+                    scalars,
+                    symbol_table,
+                );
+            }
+        }
+        Type::Array(element_type, size) => {
+            let element_size = element_type.size(symbol_table);
+            for i in 0..*size {
+                flatten_scalar_types(element_type, offset + i * element_size, scalars, symbol_table);
+            }
+        }
+        scalar => scalars.push((offset, scalar.clone())),
+    }
+}
+
+fn get_struct_tag(val: &tacker::Val, symbol_table: &SymbolTable) -> Option<String> {
+    match val {
+        tacker::Val::Var(name) => match symbol_table[name].sym_type.unqualified() {
+            Type::Structure(tag) => Some(tag.clone()),
+            _ => None,
+        },
+        tacker::Val::Constant(_) => None,
+    }
+}
+
+// A structure returned in memory is written through a pointer the caller passes in rdi
+fn returns_in_memory(function_type: &Type, symbol_table: &SymbolTable) -> bool {
+    match function_type {
+        Type::Function(_, return_type, _) => match return_type.unqualified() {
+            Type::Structure(tag) => {
+                classify_structure(tag, symbol_table)[0] == ParameterClass::Memory
+            }
+            _ => false,
+        },
+        _ => panic!("{:?} isn't a function type", function_type),
+    }
+}
+
+fn is_variadic(function_type: &Type) -> bool {
+    match function_type {
+        Type::Function(_, _, variadic) => *variadic,
+        _ => panic!("{:?} isn't a function type", function_type),
+    }
+}
+
+// A variadic function keeps its argument registers right below the return pointer slot
+fn reg_save_area_offset(function_name: &str, symbol_table: &SymbolTable) -> i64 {
+    let reserved = if returns_in_memory(&symbol_table[function_name].sym_type, symbol_table) {
+        8
+    } else {
+        0
+    };
+    -reserved - REG_SAVE_AREA_SIZE
+}
+
+// Splits a structure into eightbytes, the last one might only be partially filled
+fn get_eightbytes(operand: &Operand, size: i64) -> Vec<(AssemblyType, Operand)> {
+    (0..(size + 7) / 8)
+        .map(|i| {
+            let assembly_type = match size - i * 8 {
+                8.. => AssemblyType::Quadword,
+                4 => AssemblyType::Longword,
+                1 => AssemblyType::Byte,
+                remaining => AssemblyType::ByteArray {
+                    size: remaining,
+                    alignment: 8,
+                },
+            };
+            (assembly_type, offset_operand(operand, i * 8))
+        })
+        .collect()
+}
+
+type ClassifiedParameters = (
+    Vec<(AssemblyType, Operand)>,
+    Vec<Operand>,
+    Vec<(AssemblyType, Operand)>,
+);
+
+// Splits values into those passed in general purpose registers, in xmm registers and on the stack
+fn classify_parameters(
+    values: Vec<tacker::Val>,
+    return_in_memory: bool,
+    symbol_table: &SymbolTable,
+    constants: &mut Vec<StaticConstant>,
+) -> ClassifiedParameters {
+    let int_registers_available = if return_in_memory {
+        INT_ARG_REGISTERS.len() - 1
+    } else {
+        INT_ARG_REGISTERS.len()
+    };
+    let mut int_args = Vec::new();
+    let mut double_args = Vec::new();
+    let mut stack_args = Vec::new();
+    for value in values {
+        let assembly_type = get_assembly_type(&value, symbol_table);
+        if let Some(tag) = get_struct_tag(&value, symbol_table) {
+            let classes = classify_structure(&tag, symbol_table);
+            let eightbytes = get_eightbytes(
+                &val_to_operand(value, constants),
+                get_type_size(assembly_type),
+            );
+            // A structure either fits entirely in registers or goes on the stack
+            let int_count = classes
+                .iter()
+                .filter(|class| **class == ParameterClass::Integer)
+                .count();
+            let double_count = classes
+                .iter()
+                .filter(|class| **class == ParameterClass::Sse)
+                .count();
+            if classes[0] != ParameterClass::Memory
+                && int_args.len() + int_count <= int_registers_available
+                && double_args.len() + double_count <= DOUBLE_ARG_REGISTERS.len()
+            {
+                for (class, (eightbyte_type, operand)) in classes.into_iter().zip(eightbytes) {
+                    if class == ParameterClass::Sse {
+                        double_args.push(operand);
+                    } else {
+                        int_args.push((eightbyte_type, operand));
+                    }
+                }
+            } else {
+                stack_args.extend(eightbytes);
+            }
+        } else if assembly_type == AssemblyType::Double {
+            if double_args.len() < DOUBLE_ARG_REGISTERS.len() {
+                double_args.push(val_to_operand(value, constants));
+            } else {
+                stack_args.push((assembly_type, val_to_operand(value, constants)));
+            }
+        } else if int_args.len() < int_registers_available {
+            int_args.push((assembly_type, val_to_operand(value, constants)));
+        } else {
+            stack_args.push((assembly_type, val_to_operand(value, constants)));
+        }
+    }
+    (int_args, double_args, stack_args)
 }
 
-pub fn tacky_function_to_assembly(function: tacker::FunctionDefinition) -> Vec<Instruction> {
+// Moves any object, byte arrays are copied piece by piece since there's no instruction for them
+fn emit_move(
+    assembly_type: AssemblyType,
+    src: Operand,
+    dst: Operand,
+    instructions: &mut Vec<Instruction>,
+) {
+    let size = match assembly_type {
+        AssemblyType::ByteArray { size, .. } => size,
+        scalar => {
+            instructions.push(Instruction::Mov(scalar, src, dst));
+            return;
+        }
+    };
+    match (src, dst) {
+        // Only the low byte of a register can be moved, shift the rest into place
+        (src, Operand::Reg(reg)) => {
+            for i in (0..size).rev() {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Byte,
+                    offset_operand(&src, i),
+                    Operand::Reg(reg.clone()),
+                ));
+                if i > 0 {
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::LeftShift,
+                        AssemblyType::Quadword,
+                        Operand::Imm(8),
+                        Operand::Reg(reg.clone()),
+                    ));
+                }
+            }
+        }
+        (Operand::Reg(reg), dst) => {
+            for i in 0..size {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Byte,
+                    Operand::Reg(reg.clone()),
+                    offset_operand(&dst, i),
+                ));
+                if i < size - 1 {
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::RightShift,
+                        AssemblyType::Quadword,
+                        Operand::Imm(8),
+                        Operand::Reg(reg.clone()),
+                    ));
+                }
+            }
+        }
+        (src, dst) => {
+            let mut offset = 0;
+            while offset < size {
+                let chunk_type = match size - offset {
+                    8.. => AssemblyType::Quadword,
+                    4..=7 => AssemblyType::Longword,
+                    _ => AssemblyType::Byte,
+                };
+                instructions.push(Instruction::Mov(
+                    chunk_type,
+                    offset_operand(&src, offset),
+                    offset_operand(&dst, offset),
+                ));
+                offset += get_type_size(chunk_type);
+            }
+        }
+    }
+}
+
+fn offset_operand(operand: &Operand, delta: i64) -> Operand {
+    match operand {
+        Operand::Pseudo(name) => Operand::PseudoMem(name.clone(), delta),
+        Operand::PseudoMem(name, offset) => Operand::PseudoMem(name.clone(), offset + delta),
+        Operand::Stack(offset) => Operand::Stack(offset + delta),
+        Operand::Memory(reg, offset) => Operand::Memory(reg.clone(), offset + delta),
+        _ => panic!("Can't offset into {:?}", operand),
+    }
+}
+
+pub fn tacky_function_to_assembly(
+    name: &str,
+    params: Vec<String>,
+    tacky_instructions: Vec<tacker::Instruction>,
+    symbol_table: &SymbolTable,
+    constants: &mut Vec<StaticConstant>,
+) -> Vec<Instruction> {
     let mut instructions: Vec<Instruction> = Vec::new();
-    let arg_registers = [Reg::DI, Reg::SI, Reg::DX, Reg::CX, Reg::R8, Reg::R9];
-    let mut register_args = function.params;
-    let stack_args = if register_args.len() >= 6 {
-        register_args.split_off(6)
+    let function_type = &symbol_table[name].sym_type;
+    let return_in_memory = returns_in_memory(function_type, symbol_table);
+    let int_registers = if return_in_memory {
+        // Keep the return pointer in the slot reserved right below rbp
+        instructions.push(Instruction::Mov(
+            AssemblyType::Quadword,
+            Operand::Reg(Reg::DI),
+            Operand::Stack(-8),
+        ));
+        &INT_ARG_REGISTERS[1..]
     } else {
-        Vec::new()
+        &INT_ARG_REGISTERS[..]
     };
-    for (i, param) in register_args.iter().enumerate() {
-        if i < arg_registers.len() {
+    // Saved before the parameters are copied out, copying a structure can shift its register
+    if is_variadic(function_type) {
+        let save_area = reg_save_area_offset(name, symbol_table);
+        for (i, reg) in INT_ARG_REGISTERS.iter().enumerate() {
+            instructions.push(Instruction::Mov(
+                AssemblyType::Quadword,
+                Operand::Reg(reg.clone()),
+                Operand::Stack(save_area + i as i64 * 8),
+            ));
+        }
+        for (i, reg) in DOUBLE_ARG_REGISTERS.iter().enumerate() {
             instructions.push(Instruction::Mov(
-                Operand::Reg(arg_registers[i].clone()),
-                Operand::Pseudo(param.clone()),
+                AssemblyType::Double,
+                Operand::Reg(reg.clone()),
+                Operand::Stack(save_area + 48 + i as i64 * 16),
             ));
         }
     }
-    for (i, param) in stack_args.iter().enumerate() {
+    let (int_params, double_params, stack_params) = classify_parameters(
+        params.into_iter().map(tacker::Val::Var).collect(),
+        return_in_memory,
+        symbol_table,
+        constants,
+    );
+    // Where va_start finds the first variable argument of each kind
+    let gp_offset = 8 * (int_params.len() + return_in_memory as usize) as i64;
+    let fp_offset = 48 + 16 * double_params.len() as i64;
+    let overflow_arg_area = 16 + 8 * stack_params.len() as i64;
+    for (reg, (assembly_type, param)) in int_registers.iter().zip(int_params) {
+        emit_move(assembly_type, Operand::Reg(reg.clone()), param, &mut instructions);
+    }
+    for (reg, param) in DOUBLE_ARG_REGISTERS.iter().zip(double_params) {
         instructions.push(Instruction::Mov(
-            Operand::Stack((i as i64 + 1) * 8),
-            Operand::Pseudo(param.clone()),
+            AssemblyType::Double,
+            Operand::Reg(reg.clone()),
+            param,
         ));
     }
-    for instruction in function.instructions {
+    // The caller's stack arguments start above the saved rbp and the return address
+    for (i, (assembly_type, param)) in stack_params.into_iter().enumerate() {
+        emit_move(
+            assembly_type,
+            Operand::Stack(16 + i as i64 * 8),
+            param,
+            &mut instructions,
+        );
+    }
+    for instruction in tacky_instructions {
         match instruction {
-            tacker::Instruction::Return(val) => {
-                println!("return");
-                instructions.push(Instruction::Mov(val_to_operand(val), Operand::Reg(Reg::AX)));
+            tacker::Instruction::Return(Some(val))
+                if get_struct_tag(&val, symbol_table).is_some() =>
+            {
+                let tag = get_struct_tag(&val, symbol_table).unwrap();
+                let assembly_type = get_assembly_type(&val, symbol_table);
+                let operand = val_to_operand(val, constants);
+                if return_in_memory {
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Quadword,
+                        Operand::Stack(-8),
+                        Operand::Reg(Reg::AX),
+                    ));
+                    emit_move(
+                        assembly_type,
+                        operand,
+                        Operand::Memory(Reg::AX, 0),
+                        &mut instructions,
+                    );
+                } else {
+                    let classes = classify_structure(&tag, symbol_table);
+                    let eightbytes = get_eightbytes(&operand, get_type_size(assembly_type));
+                    let mut int_registers = INT_RETURN_REGISTERS.iter();
+                    let mut double_registers = DOUBLE_RETURN_REGISTERS.iter();
+                    for (class, (eightbyte_type, eightbyte)) in classes.into_iter().zip(eightbytes) {
+                        if class == ParameterClass::Sse {
+                            instructions.push(Instruction::Mov(
+                                AssemblyType::Double,
+                                eightbyte,
+                                Operand::Reg(double_registers.next().unwrap().clone()),
+                            ));
+                        } else {
+                            emit_move(
+                                eightbyte_type,
+                                eightbyte,
+                                Operand::Reg(int_registers.next().unwrap().clone()),
+                                &mut instructions,
+                            );
+                        }
+                    }
+                }
+                instructions.push(Instruction::Ret);
+            }
+            tacker::Instruction::Return(Some(val)) => {
+                let assembly_type = get_assembly_type(&val, symbol_table);
+                let reg = if assembly_type == AssemblyType::Double {
+                    Reg::XMM0
+                } else {
+                    Reg::AX
+                };
+                instructions.push(Instruction::Mov(
+                    assembly_type,
+                    val_to_operand(val, constants),
+                    Operand::Reg(reg),
+                ));
                 instructions.push(Instruction::Ret);
             }
+            tacker::Instruction::Return(None) => instructions.push(Instruction::Ret),
             tacker::Instruction::Jump(target) => {
                 instructions.push(Instruction::Jmp(target));
             }
             tacker::Instruction::Copy(src, dst) => {
-                println!("copy");
-                println!("{:#?} {:#?}", src, dst);
-                println!("{:#?} {:#?}", val_to_operand(src.clone()), val_to_operand(dst.clone()));
-                instructions.push(Instruction::Mov(val_to_operand(src), val_to_operand(dst)));
+                emit_move(
+                    get_assembly_type(&src, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                    &mut instructions,
+                );
             }
             tacker::Instruction::Label(identifier) => {
                 instructions.push(Instruction::Label(identifier));
             }
+            tacker::Instruction::SignExtend(src, dst) => {
+                instructions.push(Instruction::Movsx(
+                    get_assembly_type(&src, symbol_table),
+                    get_assembly_type(&dst, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::ZeroExtend(src, dst) => {
+                instructions.push(Instruction::MovZeroExtend(
+                    get_assembly_type(&src, symbol_table),
+                    get_assembly_type(&dst, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::Truncate(src, dst) => {
+                instructions.push(Instruction::Mov(
+                    get_assembly_type(&dst, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            // A function from another object file can only be reached through the GOT
+            tacker::Instruction::GetAddress(tacker::Val::Var(name), dst)
+                if matches!(
+                    symbol_table[&name].identifier_attrs,
+                    IdentifierAttr::FunAttr(false, _)
+                ) =>
+            {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    Operand::Data(format!("{}@GOTPCREL", name), 0),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::GetAddress(tacker::Val::Var(name), dst)
+                if matches!(symbol_table[&name].sym_type, Type::Function(..)) =>
+            {
+                instructions.push(Instruction::Lea(
+                    Operand::Data(name, 0),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::GetAddress(src, dst) => {
+                instructions.push(Instruction::Lea(
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::CopyToOffset(src, dst, offset) => {
+                emit_move(
+                    get_assembly_type(&src, symbol_table),
+                    val_to_operand(src, constants),
+                    Operand::PseudoMem(dst, offset),
+                    &mut instructions,
+                );
+            }
+            tacker::Instruction::CopyFromOffset(src, offset, dst) => {
+                emit_move(
+                    get_assembly_type(&dst, symbol_table),
+                    Operand::PseudoMem(src, offset),
+                    val_to_operand(dst, constants),
+                    &mut instructions,
+                );
+            }
+            tacker::Instruction::AddPtr(ptr, index, scale, dst) => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    val_to_operand(ptr, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                if let tacker::Val::Constant(Const::Long(index)) = index {
+                    instructions.push(Instruction::Lea(
+                        Operand::Memory(Reg::AX, index * scale),
+                        val_to_operand(dst, constants),
+                    ));
+                } else {
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Quadword,
+                        val_to_operand(index, constants),
+                        Operand::Reg(Reg::DX),
+                    ));
+                    // An indexed operand can only scale by 1, 2, 4 or 8
+                    let scale = if matches!(scale, 1 | 2 | 4 | 8) {
+                        scale
+                    } else {
+                        instructions.push(Instruction::Binary(
+                            BinaryOperator::Mult,
+                            AssemblyType::Quadword,
+                            Operand::Imm(scale),
+                            Operand::Reg(Reg::DX),
+                        ));
+                        1
+                    };
+                    instructions.push(Instruction::Lea(
+                        Operand::Indexed(Reg::AX, Reg::DX, scale),
+                        val_to_operand(dst, constants),
+                    ));
+                }
+            }
+            tacker::Instruction::VaStart(va_list) => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    val_to_operand(va_list, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Longword,
+                    Operand::Imm(gp_offset),
+                    Operand::Memory(Reg::AX, 0),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Longword,
+                    Operand::Imm(fp_offset),
+                    Operand::Memory(Reg::AX, 4),
+                ));
+                instructions.push(Instruction::Lea(
+                    Operand::Stack(overflow_arg_area),
+                    Operand::Reg(Reg::DX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::DX),
+                    Operand::Memory(Reg::AX, 8),
+                ));
+                instructions.push(Instruction::Lea(
+                    Operand::Stack(reg_save_area_offset(name, symbol_table)),
+                    Operand::Reg(Reg::DX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::DX),
+                    Operand::Memory(Reg::AX, 16),
+                ));
+            }
+            tacker::Instruction::VaArg(va_list, dst) => {
+                let assembly_type = get_assembly_type(&dst, symbol_table);
+                let classes = match get_struct_tag(&dst, symbol_table) {
+                    Some(tag) => classify_structure(&tag, symbol_table),
+                    None if assembly_type == AssemblyType::Double => vec![ParameterClass::Sse],
+                    None => vec![ParameterClass::Integer],
+                };
+                emit_va_arg(
+                    val_to_operand(va_list, constants),
+                    val_to_operand(dst, constants),
+                    assembly_type,
+                    classes,
+                    &mut instructions,
+                );
+            }
+            tacker::Instruction::Load(ptr, dst) => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    val_to_operand(ptr, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                emit_move(
+                    get_assembly_type(&dst, symbol_table),
+                    Operand::Memory(Reg::AX, 0),
+                    val_to_operand(dst, constants),
+                    &mut instructions,
+                );
+            }
+            tacker::Instruction::Store(src, ptr) => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    val_to_operand(ptr, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                emit_move(
+                    get_assembly_type(&src, symbol_table),
+                    val_to_operand(src, constants),
+                    Operand::Memory(Reg::AX, 0),
+                    &mut instructions,
+                );
+            }
+            // There are no byte sized conversions, go through a longword in AX
+            tacker::Instruction::IntToDouble(src, dst)
+                if get_assembly_type(&src, symbol_table) == AssemblyType::Byte =>
+            {
+                instructions.push(Instruction::Movsx(
+                    AssemblyType::Byte,
+                    AssemblyType::Longword,
+                    val_to_operand(src, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Cvtsi2sd(
+                    AssemblyType::Longword,
+                    Operand::Reg(Reg::AX),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::UIntToDouble(src, dst)
+                if get_assembly_type(&src, symbol_table) == AssemblyType::Byte =>
+            {
+                instructions.push(Instruction::MovZeroExtend(
+                    AssemblyType::Byte,
+                    AssemblyType::Longword,
+                    val_to_operand(src, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Cvtsi2sd(
+                    AssemblyType::Longword,
+                    Operand::Reg(Reg::AX),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::DoubleToInt(src, dst) | tacker::Instruction::DoubleToUInt(src, dst)
+                if get_assembly_type(&dst, symbol_table) == AssemblyType::Byte =>
+            {
+                instructions.push(Instruction::Cvttsd2si(
+                    AssemblyType::Longword,
+                    val_to_operand(src, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Byte,
+                    Operand::Reg(Reg::AX),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::IntToDouble(src, dst) => {
+                instructions.push(Instruction::Cvtsi2sd(
+                    get_assembly_type(&src, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::DoubleToInt(src, dst) => {
+                instructions.push(Instruction::Cvttsd2si(
+                    get_assembly_type(&dst, symbol_table),
+                    val_to_operand(src, constants),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::UIntToDouble(src, dst)
+                if get_assembly_type(&src, symbol_table) == AssemblyType::Longword =>
+            {
+                // Every unsigned int fits in a signed long
+                instructions.push(Instruction::MovZeroExtend(
+                    AssemblyType::Longword,
+                    AssemblyType::Quadword,
+                    val_to_operand(src, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Cvtsi2sd(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::AX),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::UIntToDouble(src, dst) => {
+                let out_of_range_label = make_label_name("ulong_to_double_out_of_range");
+                let end_label = make_label_name("ulong_to_double_end");
+                let src = val_to_operand(src, constants);
+                let dst = val_to_operand(dst, constants);
+                instructions.push(Instruction::Cmp(
+                    AssemblyType::Quadword,
+                    Operand::Imm(0),
+                    src.clone(),
+                ));
+                instructions.push(Instruction::JmpCC(CondCode::L, out_of_range_label.clone()));
+                instructions.push(Instruction::Cvtsi2sd(
+                    AssemblyType::Quadword,
+                    src.clone(),
+                    dst.clone(),
+                ));
+                instructions.push(Instruction::Jmp(end_label.clone()));
+                // Halve the value, keeping the lowest bit so it still rounds correctly, then double it
+                instructions.push(Instruction::Label(out_of_range_label));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    src,
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::AX),
+                    Operand::Reg(Reg::DX),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::RightShift,
+                    AssemblyType::Quadword,
+                    Operand::Imm(1),
+                    Operand::Reg(Reg::DX),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::And,
+                    AssemblyType::Quadword,
+                    Operand::Imm(1),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Or,
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::AX),
+                    Operand::Reg(Reg::DX),
+                ));
+                instructions.push(Instruction::Cvtsi2sd(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::DX),
+                    dst.clone(),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Add,
+                    AssemblyType::Double,
+                    dst.clone(),
+                    dst,
+                ));
+                instructions.push(Instruction::Label(end_label));
+            }
+            tacker::Instruction::DoubleToUInt(src, dst)
+                if get_assembly_type(&dst, symbol_table) == AssemblyType::Longword =>
+            {
+                // Convert to a long and keep the lower half
+                instructions.push(Instruction::Cvttsd2si(
+                    AssemblyType::Quadword,
+                    val_to_operand(src, constants),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Longword,
+                    Operand::Reg(Reg::AX),
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::DoubleToUInt(src, dst) => {
+                let upper_bound = get_double_constant(9223372036854775808.0, 8, constants);
+                let out_of_range_label = make_label_name("double_to_ulong_out_of_range");
+                let end_label = make_label_name("double_to_ulong_end");
+                let src = val_to_operand(src, constants);
+                let dst = val_to_operand(dst, constants);
+                instructions.push(Instruction::Cmp(
+                    AssemblyType::Double,
+                    upper_bound.clone(),
+                    src.clone(),
+                ));
+                instructions.push(Instruction::JmpCC(CondCode::AE, out_of_range_label.clone()));
+                instructions.push(Instruction::Cvttsd2si(
+                    AssemblyType::Quadword,
+                    src.clone(),
+                    dst.clone(),
+                ));
+                instructions.push(Instruction::Jmp(end_label.clone()));
+                // Subtract 2^63 before converting and add it back afterwards
+                instructions.push(Instruction::Label(out_of_range_label));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    src,
+                    Operand::Reg(Reg::XMM1),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Sub,
+                    AssemblyType::Double,
+                    upper_bound,
+                    Operand::Reg(Reg::XMM1),
+                ));
+                instructions.push(Instruction::Cvttsd2si(
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::XMM1),
+                    dst.clone(),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Quadword,
+                    Operand::Imm(i64::MIN),
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Add,
+                    AssemblyType::Quadword,
+                    Operand::Reg(Reg::AX),
+                    dst,
+                ));
+                instructions.push(Instruction::Label(end_label));
+            }
+            // Flip the sign bit
+            tacker::Instruction::Unary(tacker::UnaryOperator::Negate, src, dst)
+                if get_assembly_type(&src, symbol_table) == AssemblyType::Double =>
+            {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    val_to_operand(src, constants),
+                    val_to_operand(dst.clone(), constants),
+                ));
+                let negative_zero = get_double_constant(-0.0, 16, constants);
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Xor,
+                    AssemblyType::Double,
+                    negative_zero,
+                    val_to_operand(dst, constants),
+                ));
+            }
             tacker::Instruction::Unary(
                 operator @ (tacker::UnaryOperator::Complement | tacker::UnaryOperator::Negate),
                 src,
                 dst,
             ) => {
+                let assembly_type = get_assembly_type(&src, symbol_table);
                 instructions.push(Instruction::Mov(
-                    val_to_operand(src),
-                    val_to_operand(dst.clone()),
+                    assembly_type,
+                    val_to_operand(src, constants),
+                    val_to_operand(dst.clone(), constants),
                 ));
                 instructions.push(Instruction::Unary(
                     match operator {
@@ -139,7 +971,71 @@ pub fn tacky_function_to_assembly(function: tacker::FunctionDefinition) -> Vec<I
                             panic!("Only checked for these")
                         }
                     },
-                    val_to_operand(dst),
+                    assembly_type,
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::Binary(
+                op @ (tacker::BinaryOperator::LeftShift | tacker::BinaryOperator::RightShift),
+                src1,
+                src2,
+                dst,
+            ) => {
+                let assembly_type = get_assembly_type(&src1, symbol_table);
+                let signed = is_signed(&src1, symbol_table);
+                instructions.push(Instruction::Mov(
+                    assembly_type,
+                    val_to_operand(src1, constants),
+                    val_to_operand(dst.clone(), constants),
+                ));
+                // A variable shift count can only be read from cl
+                let count = match src2 {
+                    tacker::Val::Constant(_) => val_to_operand(src2, constants),
+                    tacker::Val::Var(_) => {
+                        instructions.push(Instruction::Mov(
+                            get_assembly_type(&src2, symbol_table),
+                            val_to_operand(src2, constants),
+                            Operand::Reg(Reg::CX),
+                        ));
+                        Operand::Reg(Reg::CX)
+                    }
+                };
+                instructions.push(Instruction::Binary(
+                    match op {
+                        tacker::BinaryOperator::LeftShift => BinaryOperator::LeftShift,
+                        _ if signed => BinaryOperator::ArithmeticRightShift,
+                        _ => BinaryOperator::RightShift,
+                    },
+                    assembly_type,
+                    count,
+                    val_to_operand(dst, constants),
+                ));
+            }
+            tacker::Instruction::Binary(
+                op @ (tacker::BinaryOperator::Add
+                | tacker::BinaryOperator::Subtract
+                | tacker::BinaryOperator::Multiply
+                | tacker::BinaryOperator::Divide),
+                src1,
+                src2,
+                dst,
+            ) if get_assembly_type(&src1, symbol_table) == AssemblyType::Double => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    val_to_operand(src1, constants),
+                    val_to_operand(dst.clone(), constants),
+                ));
+                instructions.push(Instruction::Binary(
+                    match op {
+                        tacker::BinaryOperator::Add => BinaryOperator::Add,
+                        tacker::BinaryOperator::Subtract => BinaryOperator::Sub,
+                        tacker::BinaryOperator::Multiply => BinaryOperator::Mult,
+                        tacker::BinaryOperator::Divide => BinaryOperator::DivDouble,
+                        _ => panic!("Checked only for these"),
+                    },
+                    AssemblyType::Double,
+                    val_to_operand(src2, constants),
+                    val_to_operand(dst, constants),
                 ));
             }
             tacker::Instruction::Binary(
@@ -148,17 +1044,16 @@ pub fn tacky_function_to_assembly(function: tacker::FunctionDefinition) -> Vec<I
                 | tacker::BinaryOperator::Multiply
                 | tacker::BinaryOperator::And
                 | tacker::BinaryOperator::Or
-                | tacker::BinaryOperator::Xor
-                | tacker::BinaryOperator::LeftShift
-                | tacker::BinaryOperator::RightShift),
+                | tacker::BinaryOperator::Xor),
                 src1,
                 src2,
                 dst,
             ) => {
-                println!("binary");
+                let assembly_type = get_assembly_type(&src1, symbol_table);
                 instructions.push(Instruction::Mov(
-                    val_to_operand(src1),
-                    val_to_operand(dst.clone()),
+                    assembly_type,
+                    val_to_operand(src1, constants),
+                    val_to_operand(dst.clone(), constants),
                 ));
                 instructions.push(Instruction::Binary(
                     match op {
@@ -168,51 +1063,94 @@ pub fn tacky_function_to_assembly(function: tacker::FunctionDefinition) -> Vec<I
                         tacker::BinaryOperator::And => BinaryOperator::And,
                         tacker::BinaryOperator::Or => BinaryOperator::Or,
                         tacker::BinaryOperator::Xor => BinaryOperator::Xor,
-                        tacker::BinaryOperator::LeftShift => BinaryOperator::LeftShift,
-                        tacker::BinaryOperator::RightShift => BinaryOperator::RightShift,
                         _ => panic!("Checked only for these"),
                     },
-                    val_to_operand(src2),
-                    val_to_operand(dst),
+                    assembly_type,
+                    val_to_operand(src2, constants),
+                    val_to_operand(dst, constants),
                 ));
             }
-            tacker::Instruction::Binary(tacker::BinaryOperator::Divide, src1, src2, dst) => {
+            tacker::Instruction::Binary(
+                op @ (tacker::BinaryOperator::Divide | tacker::BinaryOperator::Remainder),
+                src1,
+                src2,
+                dst,
+            ) => {
+                let assembly_type = get_assembly_type(&src1, symbol_table);
+                let signed = is_signed(&src1, symbol_table);
                 instructions.push(Instruction::Mov(
-                    val_to_operand(src1),
+                    assembly_type,
+                    val_to_operand(src1, constants),
                     Operand::Reg(Reg::AX),
                 ));
-                instructions.push(Instruction::Cdq);
-                instructions.push(Instruction::Idiv(val_to_operand(src2)));
-                instructions.push(Instruction::Mov(Operand::Reg(Reg::AX), val_to_operand(dst)));
-            }
-            tacker::Instruction::Binary(tacker::BinaryOperator::Remainder, src1, src2, dst) => {
+                if signed {
+                    instructions.push(Instruction::Cdq(assembly_type));
+                    instructions.push(Instruction::Idiv(assembly_type, val_to_operand(src2, constants)));
+                } else {
+                    // Unsigned division takes its upper half from edx, zero it instead of sign extending
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        Operand::Imm(0),
+                        Operand::Reg(Reg::DX),
+                    ));
+                    instructions.push(Instruction::Div(assembly_type, val_to_operand(src2, constants)));
+                }
+                let result = match op {
+                    tacker::BinaryOperator::Divide => Reg::AX,
+                    _ => Reg::DX,
+                };
                 instructions.push(Instruction::Mov(
-                    val_to_operand(src1),
-                    Operand::Reg(Reg::AX),
+                    assembly_type,
+                    Operand::Reg(result),
+                    val_to_operand(dst, constants),
                 ));
-                instructions.push(Instruction::Cdq);
-                instructions.push(Instruction::Idiv(val_to_operand(src2)));
-                instructions.push(Instruction::Mov(Operand::Reg(Reg::DX), val_to_operand(dst)));
             }
+            // NaN isn't zero, but comparing it sets ZF along with PF
             tacker::Instruction::JumpIfZero(val, target) => {
-                instructions.push(Instruction::Cmp(Operand::Imm(0), val_to_operand(val)));
-                instructions.push(Instruction::JmpCC(CondCode::E, target));
+                let double = get_assembly_type(&val, symbol_table) == AssemblyType::Double;
+                emit_compare_to_zero(val, &mut instructions, symbol_table, constants);
+                if double {
+                    let nan_label = make_label_name("nan");
+                    instructions.push(Instruction::JmpCC(CondCode::P, nan_label.clone()));
+                    instructions.push(Instruction::JmpCC(CondCode::E, target));
+                    instructions.push(Instruction::Label(nan_label));
+                } else {
+                    instructions.push(Instruction::JmpCC(CondCode::E, target));
+                }
             }
             tacker::Instruction::JumpIfNotZero(val, target) => {
-                instructions.push(Instruction::Cmp(Operand::Imm(0), val_to_operand(val)));
-                instructions.push(Instruction::JmpCC(CondCode::NE, target));
+                let double = get_assembly_type(&val, symbol_table) == AssemblyType::Double;
+                emit_compare_to_zero(val, &mut instructions, symbol_table, constants);
+                instructions.push(Instruction::JmpCC(CondCode::NE, target.clone()));
+                if double {
+                    instructions.push(Instruction::JmpCC(CondCode::P, target));
+                }
             }
             tacker::Instruction::JumpIfEqual(val1, val2, target) => {
-                instructions.push(Instruction::Cmp(val_to_operand(val1), val_to_operand(val2)));
+                instructions.push(Instruction::Cmp(
+                    get_assembly_type(&val1, symbol_table),
+                    val_to_operand(val1, constants),
+                    val_to_operand(val2, constants),
+                ));
                 instructions.push(Instruction::JmpCC(CondCode::E, target));
             }
             tacker::Instruction::Unary(tacker::UnaryOperator::Not, src, dst) => {
-                instructions.push(Instruction::Cmp(Operand::Imm(0), val_to_operand(src)));
+                let double = get_assembly_type(&src, symbol_table) == AssemblyType::Double;
+                emit_compare_to_zero(src, &mut instructions, symbol_table, constants);
                 instructions.push(Instruction::Mov(
+                    get_assembly_type(&dst, symbol_table),
                     Operand::Imm(0),
-                    val_to_operand(dst.clone()),
+                    val_to_operand(dst.clone(), constants),
                 ));
-                instructions.push(Instruction::SetCC(CondCode::E, val_to_operand(dst)));
+                if double {
+                    emit_unless_unordered(
+                        Instruction::SetCC(CondCode::E, val_to_operand(dst, constants)),
+                        &mut instructions,
+                    );
+                } else {
+                    let dst = val_to_operand(dst, constants);
+                    instructions.push(Instruction::SetCC(CondCode::E, dst));
+                }
             }
             tacker::Instruction::Binary(
                 operator @ (tacker::BinaryOperator::EqualTo
@@ -225,191 +1163,516 @@ pub fn tacky_function_to_assembly(function: tacker::FunctionDefinition) -> Vec<I
                 src2,
                 dst,
             ) => {
-                instructions.push(Instruction::Cmp(val_to_operand(src2), val_to_operand(src1)));
+                let signed = is_signed(&src1, symbol_table);
+                let assembly_type = get_assembly_type(&src1, symbol_table);
+                if assembly_type == AssemblyType::Double {
+                    let dst_type = get_assembly_type(&dst, symbol_table);
+                    emit_double_comparison(
+                        operator,
+                        val_to_operand(src1, constants),
+                        val_to_operand(src2, constants),
+                        val_to_operand(dst, constants),
+                        dst_type,
+                        &mut instructions,
+                    );
+                    continue;
+                }
+                instructions.push(Instruction::Cmp(
+                    assembly_type,
+                    val_to_operand(src2, constants),
+                    val_to_operand(src1, constants),
+                ));
                 instructions.push(Instruction::Mov(
+                    get_assembly_type(&dst, symbol_table),
                     Operand::Imm(0),
-                    val_to_operand(dst.clone()),
+                    val_to_operand(dst.clone(), constants),
                 ));
                 instructions.push(Instruction::SetCC(
                     match operator {
                         tacker::BinaryOperator::EqualTo => CondCode::E,
                         tacker::BinaryOperator::NotEqual => CondCode::NE,
-                        tacker::BinaryOperator::LessThan => CondCode::L,
-                        tacker::BinaryOperator::LessOrEqual => CondCode::LE,
-                        tacker::BinaryOperator::GreaterThan => CondCode::G,
-                        tacker::BinaryOperator::GreaterOrEqual => CondCode::GE,
+                        tacker::BinaryOperator::LessThan if signed => CondCode::L,
+                        tacker::BinaryOperator::LessOrEqual if signed => CondCode::LE,
+                        tacker::BinaryOperator::GreaterThan if signed => CondCode::G,
+                        tacker::BinaryOperator::GreaterOrEqual if signed => CondCode::GE,
+                        tacker::BinaryOperator::LessThan => CondCode::B,
+                        tacker::BinaryOperator::LessOrEqual => CondCode::BE,
+                        tacker::BinaryOperator::GreaterThan => CondCode::A,
+                        tacker::BinaryOperator::GreaterOrEqual => CondCode::AE,
                         _ => {
                             panic!("Only checked for these")
                         }
                     },
-                    val_to_operand(dst),
+                    val_to_operand(dst, constants),
                 ));
             }
             tacker::Instruction::FunctionCall(func_name, args, dest) => {
-                let arg_registers = [Reg::DI, Reg::SI, Reg::DX, Reg::CX, Reg::R8, Reg::R9];
-
-                let mut register_args = args;
-                let stack_args = if register_args.len() >= 6 {
-                    register_args.split_off(6)
-                } else {
-                    Vec::new()
+                let function_type = symbol_table[&func_name].sym_type.clone();
+                emit_function_call(
+                    CallTarget::Direct(func_name),
+                    &function_type,
+                    args,
+                    dest,
+                    &mut instructions,
+                    symbol_table,
+                    constants,
+                );
+            }
+            tacker::Instruction::IndirectCall(pointer, args, dest) => {
+                let function_type = match &pointer {
+                    tacker::Val::Var(name) => match symbol_table[name].sym_type.unqualified() {
+                        Type::Pointer(referenced) => *referenced.clone(),
+                        _ => panic!("Indirect call through a non-pointer: {}", name),
+                    },
+                    tacker::Val::Constant(_) => panic!("Indirect call through a constant"),
                 };
+                emit_function_call(
+                    CallTarget::Indirect(pointer),
+                    &function_type,
+                    args,
+                    dest,
+                    &mut instructions,
+                    symbol_table,
+                    constants,
+                );
+            }
+        }
+    }
 
-                let stack_padding = if (stack_args.len() & 1) == 1 { 8 } else { 0 };
+    instructions
+}
 
-                if stack_padding != 0 {
-                    instructions.push(Instruction::AllocateStack(stack_padding));
-                }
+enum CallTarget {
+    Direct(String),
+    Indirect(tacker::Val), // function pointer
+}
 
-                let mut reg_index = 0;
-                for tacky_arg in register_args {
-                    let reg = &arg_registers[reg_index];
-                    let assembly_arg = val_to_operand(tacky_arg);
-                    instructions.push(Instruction::Mov(assembly_arg, Operand::Reg(reg.clone())));
-                    reg_index += 1;
-                }
+fn emit_function_call(
+    target: CallTarget,
+    function_type: &Type,
+    args: Vec<tacker::Val>,
+    dest: Option<tacker::Val>,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &SymbolTable,
+    constants: &mut Vec<StaticConstant>,
+) {
+    let return_in_memory = returns_in_memory(function_type, symbol_table);
+    let int_registers = if return_in_memory {
+        instructions.push(Instruction::Lea(
+            val_to_operand(dest.clone().unwrap(), constants),
+            Operand::Reg(Reg::DI),
+        ));
+        &INT_ARG_REGISTERS[1..]
+    } else {
+        &INT_ARG_REGISTERS[..]
+    };
+    let (int_args, double_args, stack_args) =
+        classify_parameters(args, return_in_memory, symbol_table, constants);
 
-                let stack_args_len = stack_args.len() as i64;
-                for tacky_arg in stack_args {
-                    let assembly_arg = val_to_operand(tacky_arg);
-                    match assembly_arg {
-                        Operand::Imm(_) | Operand::Reg(_) => {
-                            instructions.push(Instruction::Push(assembly_arg));
-                        }
-                        _ => {
-                            instructions
-                                .push(Instruction::Mov(assembly_arg, Operand::Reg(Reg::AX)));
-                            instructions.push(Instruction::Push(Operand::Reg(Reg::AX)));
-                        }
-                    }
-                }
+    let stack_padding = if (stack_args.len() & 1) == 1 { 8 } else { 0 };
+    let vector_registers_used = double_args.len() as i64;
+
+    if stack_padding != 0 {
+        instructions.push(Instruction::AllocateStack(stack_padding));
+    }
+
+    for (reg, (assembly_type, assembly_arg)) in int_registers.iter().zip(int_args) {
+        emit_move(
+            assembly_type,
+            assembly_arg,
+            Operand::Reg(reg.clone()),
+            instructions,
+        );
+    }
+
+    for (reg, assembly_arg) in DOUBLE_ARG_REGISTERS.iter().zip(double_args) {
+        instructions.push(Instruction::Mov(
+            AssemblyType::Double,
+            assembly_arg,
+            Operand::Reg(reg.clone()),
+        ));
+    }
+
+    let stack_args_len = stack_args.len() as i64;
+    for (assembly_type, assembly_arg) in stack_args.into_iter().rev() {
+        match (assembly_arg, assembly_type) {
+            (assembly_arg @ (Operand::Imm(_) | Operand::Reg(_)), _)
+            | (assembly_arg, AssemblyType::Quadword | AssemblyType::Double) => {
+                instructions.push(Instruction::Push(assembly_arg));
+            }
+            // Pushing a smaller value from memory would read past its end
+            (assembly_arg, assembly_type @ (AssemblyType::Byte | AssemblyType::Longword)) => {
+                instructions.push(Instruction::Mov(
+                    assembly_type,
+                    assembly_arg,
+                    Operand::Reg(Reg::AX),
+                ));
+                instructions.push(Instruction::Push(Operand::Reg(Reg::AX)));
+            }
+            // The last piece of a structure, make room and copy it in
+            (assembly_arg, assembly_type @ AssemblyType::ByteArray { .. }) => {
+                instructions.push(Instruction::Binary(
+                    BinaryOperator::Sub,
+                    AssemblyType::Quadword,
+                    Operand::Imm(8),
+                    Operand::Reg(Reg::SP),
+                ));
+                emit_move(
+                    assembly_type,
+                    assembly_arg,
+                    Operand::Memory(Reg::SP, 0),
+                    instructions,
+                );
+            }
+        }
+    }
+
+    // A variadic callee learns from al how many vector registers hold arguments
+    if is_variadic(function_type) {
+        instructions.push(Instruction::Mov(
+            AssemblyType::Byte,
+            Operand::Imm(vector_registers_used),
+            Operand::Reg(Reg::AX),
+        ));
+    }
+    match target {
+        CallTarget::Direct(name) => instructions.push(Instruction::Call(name)),
+        // Every argument register may be taken by now, r11 never holds one
+        CallTarget::Indirect(pointer) => {
+            instructions.push(Instruction::Mov(
+                AssemblyType::Quadword,
+                val_to_operand(pointer, constants),
+                Operand::Reg(Reg::R11),
+            ));
+            instructions.push(Instruction::CallIndirect(Operand::Reg(Reg::R11)));
+        }
+    }
 
-                instructions.push(Instruction::Call(func_name));
+    let bytes_to_remove: i64 = 8 * stack_args_len + stack_padding;
+    if bytes_to_remove != 0 {
+        instructions.push(Instruction::DeallocateStack(bytes_to_remove));
+    }
 
-                let bytes_to_remove: i64 = 8 * stack_args_len + stack_padding;
-                if bytes_to_remove != 0 {
-                    instructions.push(Instruction::DeallocateStack(bytes_to_remove));
+    // A void function leaves nothing to copy out
+    let dest = match dest {
+        Some(dest) => dest,
+        None => return,
+    };
+    let assembly_type = get_assembly_type(&dest, symbol_table);
+    let struct_tag = get_struct_tag(&dest, symbol_table);
+    let assembly_dest = val_to_operand(dest, constants);
+    if let Some(tag) = struct_tag {
+        if !return_in_memory {
+            let classes = classify_structure(&tag, symbol_table);
+            let eightbytes =
+                get_eightbytes(&assembly_dest, get_type_size(assembly_type));
+            let mut int_registers = INT_RETURN_REGISTERS.iter();
+            let mut double_registers = DOUBLE_RETURN_REGISTERS.iter();
+            for (class, (eightbyte_type, eightbyte)) in
+                classes.into_iter().zip(eightbytes)
+            {
+                if class == ParameterClass::Sse {
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Double,
+                        Operand::Reg(double_registers.next().unwrap().clone()),
+                        eightbyte,
+                    ));
+                } else {
+                    emit_move(
+                        eightbyte_type,
+                        Operand::Reg(int_registers.next().unwrap().clone()),
+                        eightbyte,
+                        instructions,
+                    );
                 }
+            }
+        }
+    } else {
+        let reg = if assembly_type == AssemblyType::Double {
+            Reg::XMM0
+        } else {
+            Reg::AX
+        };
+        instructions.push(Instruction::Mov(
+            assembly_type,
+            Operand::Reg(reg),
+            assembly_dest,
+        ));
+    }
+}
 
-                let assembly_dest = val_to_operand(dest);
-                instructions.push(Instruction::Mov(Operand::Reg(Reg::AX), assembly_dest));
+// Takes the next argument from the register save area if all of its eightbytes are still there,
+// otherwise from the overflow area on the stack
+fn emit_va_arg(
+    va_list: Operand,
+    dst: Operand,
+    assembly_type: AssemblyType,
+    classes: Vec<ParameterClass>,
+    instructions: &mut Vec<Instruction>,
+) {
+    let overflow_label = make_label_name("va_arg_overflow");
+    let end_label = make_label_name("va_arg_end");
+    let size = get_type_size(assembly_type);
+    instructions.push(Instruction::Mov(
+        AssemblyType::Quadword,
+        va_list,
+        Operand::Reg(Reg::AX),
+    ));
+    if classes[0] != ParameterClass::Memory {
+        // gp_offset and fp_offset, how many eightbytes each takes and where its registers end
+        let areas = [
+            (0, ParameterClass::Integer, 8, 48),
+            (4, ParameterClass::Sse, 16, REG_SAVE_AREA_SIZE),
+        ];
+        for (field, class, step, limit) in areas {
+            let count = classes.iter().filter(|&c| *c == class).count() as i64;
+            if count > 0 {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Longword,
+                    Operand::Memory(Reg::AX, field),
+                    Operand::Reg(Reg::CX),
+                ));
+                instructions.push(Instruction::Cmp(
+                    AssemblyType::Longword,
+                    Operand::Imm(limit - count * step),
+                    Operand::Reg(Reg::CX),
+                ));
+                instructions.push(Instruction::JmpCC(CondCode::A, overflow_label.clone()));
             }
         }
+        let eightbytes = if classes.len() == 1 {
+            vec![(assembly_type, dst.clone())]
+        } else {
+            get_eightbytes(&dst, size)
+        };
+        for (class, (eightbyte_type, eightbyte)) in classes.into_iter().zip(eightbytes) {
+            let (field, step) = if class == ParameterClass::Sse {
+                (4, 16)
+            } else {
+                (0, 8)
+            };
+            instructions.push(Instruction::Mov(
+                AssemblyType::Longword,
+                Operand::Memory(Reg::AX, field),
+                Operand::Reg(Reg::CX),
+            ));
+            instructions.push(Instruction::Mov(
+                AssemblyType::Quadword,
+                Operand::Memory(Reg::AX, 16),
+                Operand::Reg(Reg::DX),
+            ));
+            instructions.push(Instruction::Binary(
+                BinaryOperator::Add,
+                AssemblyType::Quadword,
+                Operand::Reg(Reg::CX),
+                Operand::Reg(Reg::DX),
+            ));
+            emit_move(eightbyte_type, Operand::Memory(Reg::DX, 0), eightbyte, instructions);
+            instructions.push(Instruction::Binary(
+                BinaryOperator::Add,
+                AssemblyType::Longword,
+                Operand::Imm(step),
+                Operand::Memory(Reg::AX, field),
+            ));
+        }
+        instructions.push(Instruction::Jmp(end_label.clone()));
     }
+    instructions.push(Instruction::Label(overflow_label));
+    instructions.push(Instruction::Mov(
+        AssemblyType::Quadword,
+        Operand::Memory(Reg::AX, 8),
+        Operand::Reg(Reg::DX),
+    ));
+    emit_move(assembly_type, Operand::Memory(Reg::DX, 0), dst, instructions);
+    instructions.push(Instruction::Binary(
+        BinaryOperator::Add,
+        AssemblyType::Quadword,
+        Operand::Imm((size + 7) / 8 * 8),
+        Operand::Memory(Reg::AX, 8),
+    ));
+    instructions.push(Instruction::Label(end_label));
+}
 
-    instructions
+fn emit_compare_to_zero(
+    val: tacker::Val,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &SymbolTable,
+    constants: &mut Vec<StaticConstant>,
+) {
+    let assembly_type = get_assembly_type(&val, symbol_table);
+    if assembly_type == AssemblyType::Double {
+        instructions.push(Instruction::Binary(
+            BinaryOperator::Xor,
+            AssemblyType::Double,
+            Operand::Reg(Reg::XMM0),
+            Operand::Reg(Reg::XMM0),
+        ));
+        instructions.push(Instruction::Cmp(
+            AssemblyType::Double,
+            val_to_operand(val, constants),
+            Operand::Reg(Reg::XMM0),
+        ));
+    } else {
+        instructions.push(Instruction::Cmp(
+            assembly_type,
+            Operand::Imm(0),
+            val_to_operand(val, constants),
+        ));
+    }
+}
+
+// An unordered comparison sets ZF, PF and CF. So < and <= are done as > and >= with the operands
+// swapped, == also needs PF clear and != is true when PF is set
+fn emit_double_comparison(
+    operator: tacker::BinaryOperator,
+    src1: Operand,
+    src2: Operand,
+    dst: Operand,
+    dst_type: AssemblyType,
+    instructions: &mut Vec<Instruction>,
+) {
+    let (left, right, cond_code) = match operator {
+        tacker::BinaryOperator::LessThan => (src2, src1, CondCode::A),
+        tacker::BinaryOperator::LessOrEqual => (src2, src1, CondCode::AE),
+        tacker::BinaryOperator::GreaterThan => (src1, src2, CondCode::A),
+        tacker::BinaryOperator::GreaterOrEqual => (src1, src2, CondCode::AE),
+        tacker::BinaryOperator::EqualTo => (src1, src2, CondCode::E),
+        _ => (src1, src2, CondCode::NE),
+    };
+    instructions.push(Instruction::Cmp(AssemblyType::Double, right, left));
+    let unordered_result = match operator {
+        tacker::BinaryOperator::NotEqual => 1,
+        _ => 0,
+    };
+    instructions.push(Instruction::Mov(dst_type, Operand::Imm(unordered_result), dst.clone()));
+    match cond_code {
+        CondCode::E | CondCode::NE => {
+            emit_unless_unordered(Instruction::SetCC(cond_code, dst), instructions)
+        }
+        _ => instructions.push(Instruction::SetCC(cond_code, dst)),
+    }
+}
+
+// Moves don't change the flags, so the skipped instruction can come after a mov of the default
+fn emit_unless_unordered(instruction: Instruction, instructions: &mut Vec<Instruction>) {
+    let nan_label = make_label_name("nan");
+    instructions.push(Instruction::JmpCC(CondCode::P, nan_label.clone()));
+    instructions.push(instruction);
+    instructions.push(Instruction::Label(nan_label));
+}
+
+fn get_type_size(assembly_type: AssemblyType) -> i64 {
+    match assembly_type {
+        AssemblyType::Byte => 1,
+        AssemblyType::Longword => 4,
+        AssemblyType::Quadword | AssemblyType::Double => 8,
+        AssemblyType::ByteArray { size, .. } => size,
+    }
+}
+
+fn get_type_alignment(assembly_type: AssemblyType) -> i64 {
+    match assembly_type {
+        AssemblyType::ByteArray { alignment, .. } => alignment,
+        scalar => get_type_size(scalar),
+    }
 }
 
 fn get_identifier_offset(
     identifiers: &mut HashMap<String, i64>,
     stack_size: &mut i64,
     name: &str,
+    symbol_table: &SymbolTable,
 ) -> i64 {
     if !identifiers.contains_key(name) {
-        *stack_size += 4;
+        let assembly_type = get_symbol_assembly_type(name, symbol_table);
+        let alignment = get_type_alignment(assembly_type);
+        *stack_size += get_type_size(assembly_type);
+        *stack_size = (*stack_size + alignment - 1) / alignment * alignment;
         identifiers.insert(name.to_string(), -*stack_size);
     }
     identifiers[name]
 }
 
 fn replace_psuedo_operand_if_needed(
-    operand: Operand,
+    operand: &mut Operand,
     identifiers: &mut HashMap<String, i64>,
     stack_size: &mut i64,
-) -> Operand {
+    symbol_table: &SymbolTable,
+) {
     match operand {
+        // Constants and variables with static storage are addressed relative to the instruction
+        Operand::Pseudo(name) if has_static_storage(name, symbol_table) => {
+            *operand = Operand::Data(name.clone(), 0);
+        }
+        Operand::PseudoMem(name, offset) if has_static_storage(name, symbol_table) => {
+            *operand = Operand::Data(name.clone(), *offset);
+        }
         Operand::Pseudo(name) => {
-            Operand::Stack(get_identifier_offset(identifiers, stack_size, &name))
+            *operand = Operand::Stack(get_identifier_offset(
+                identifiers,
+                stack_size,
+                name,
+                symbol_table,
+            ));
         }
-        _ => operand,
+        Operand::PseudoMem(name, offset) => {
+            *operand = Operand::Stack(
+                get_identifier_offset(identifiers, stack_size, name, symbol_table) + *offset,
+            );
+        }
+        _ => {}
     }
 }
 
-fn replace_pseudo_operands(instructions: &mut Vec<Instruction>) -> i64 {
+fn has_static_storage(name: &str, symbol_table: &SymbolTable) -> bool {
+    matches!(
+        symbol_table.get(name).map(|entry| &entry.identifier_attrs),
+        Some(IdentifierAttr::ConstantAttr(_) | IdentifierAttr::StaticAttr(..))
+    )
+}
+
+fn replace_pseudo_operands(
+    instructions: &mut [Instruction],
+    reserved_stack: i64,
+    symbol_table: &SymbolTable,
+) -> i64 {
     let mut identifiers: HashMap<String, i64> = HashMap::new();
-    let mut stack_size = 0;
+    let mut stack_size = reserved_stack;
 
     for instruction in instructions.iter_mut() {
-        println!("{:?}", instruction);
         match instruction {
-            Instruction::Mov(src, dst) => {
-                println!("changing mov");
-                *instruction = Instruction::Mov(
-                    replace_psuedo_operand_if_needed(
-                        src.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
-                    replace_psuedo_operand_if_needed(
-                        dst.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
-                );
-            }
-            Instruction::Unary(op, Operand::Pseudo(name)) => {
-                *instruction = Instruction::Unary(
-                    op.clone(),
-                    Operand::Stack(get_identifier_offset(
-                        &mut identifiers,
-                        &mut stack_size,
-                        name,
-                    )),
-                );
-            }
-            Instruction::Binary(op, src, dst) => {
-                println!("changing bin");
-                *instruction = Instruction::Binary(
-                    op.clone(),
-                    replace_psuedo_operand_if_needed(
-                        src.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
-                    replace_psuedo_operand_if_needed(
-                        dst.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
-                );
-            }
-            Instruction::Idiv(Operand::Pseudo(name)) => {
-                *instruction = Instruction::Idiv(Operand::Stack(get_identifier_offset(
+            Instruction::Mov(_, src, dst)
+            | Instruction::Movsx(_, _, src, dst)
+            | Instruction::MovZeroExtend(_, _, src, dst)
+            | Instruction::Binary(_, _, src, dst)
+            | Instruction::Cmp(_, src, dst)
+            | Instruction::Cvttsd2si(_, src, dst)
+            | Instruction::Cvtsi2sd(_, src, dst)
+            | Instruction::Lea(src, dst) => {
+                replace_psuedo_operand_if_needed(
+                    src,
                     &mut identifiers,
                     &mut stack_size,
-                    name,
-                )));
-            }
-            Instruction::Cmp(operand1, operand2) => {
-                *instruction = Instruction::Cmp(
-                    replace_psuedo_operand_if_needed(
-                        operand1.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
-                    replace_psuedo_operand_if_needed(
-                        operand2.clone(),
-                        &mut identifiers,
-                        &mut stack_size,
-                    ),
+                    symbol_table,
                 );
-            }
-            Instruction::SetCC(cc, Operand::Pseudo(name)) => {
-                *instruction = Instruction::SetCC(
-                    cc.clone(),
-                    Operand::Stack(get_identifier_offset(
-                        &mut identifiers,
-                        &mut stack_size,
-                        name,
-                    )),
+                replace_psuedo_operand_if_needed(
+                    dst,
+                    &mut identifiers,
+                    &mut stack_size,
+                    symbol_table,
                 );
             }
-            Instruction::Push(Operand::Pseudo(name)) => {
-                *instruction = Instruction::Push(Operand::Stack(get_identifier_offset(
+            Instruction::Unary(_, _, operand)
+            | Instruction::Idiv(_, operand)
+            | Instruction::Div(_, operand)
+            | Instruction::SetCC(_, operand)
+            | Instruction::Push(operand) => {
+                replace_psuedo_operand_if_needed(
+                    operand,
                     &mut identifiers,
                     &mut stack_size,
-                    name,
-                )));
+                    symbol_table,
+                );
             }
             _ => {}
         }
@@ -418,79 +1681,342 @@ fn replace_pseudo_operands(instructions: &mut Vec<Instruction>) -> i64 {
     stack_size
 }
 
+fn is_memory(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Stack(_) | Operand::Memory(_, _) | Operand::Data(_, _) | Operand::Indexed(_, _, _)
+    )
+}
+
+fn is_register(operand: &Operand) -> bool {
+    matches!(operand, Operand::Reg(_))
+}
+
+// Most instructions can only encode immediates that fit in a sign extended 32 bits
+fn is_large_imm(operand: &Operand) -> bool {
+    matches!(operand, Operand::Imm(value) if i32::try_from(*value).is_err())
+}
+
 fn fix_up(orig_instructions: Vec<Instruction>, stack_size: i64) -> Vec<Instruction> {
     let mut instructions: Vec<Instruction> = Vec::new();
     instructions.push(Instruction::AllocateStack(((stack_size + 15) / 16) * 16));
 
     for instruction in orig_instructions {
         match instruction {
-            // Can't move from memory address to memory address
-            Instruction::Mov(Operand::Stack(src), Operand::Stack(dst)) => {
+            // A 32 bit move only keeps the lower bytes anyway, truncate to avoid assembler warnings
+            Instruction::Mov(AssemblyType::Longword, Operand::Imm(value), dst)
+                if is_large_imm(&Operand::Imm(value)) =>
+            {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Longword,
+                    Operand::Imm(value as i32 as i64),
+                    dst,
+                ));
+            }
+            Instruction::Mov(AssemblyType::Byte, Operand::Imm(value), dst)
+                if i8::try_from(value).is_err() =>
+            {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Byte,
+                    Operand::Imm(value as i8 as i64),
+                    dst,
+                ));
+            }
+            Instruction::Mov(AssemblyType::Double, src, dst)
+                if is_memory(&src) && is_memory(&dst) =>
+            {
                 instructions.push(Instruction::Mov(
-                    Operand::Stack(src),
+                    AssemblyType::Double,
+                    src,
+                    Operand::Reg(Reg::XMM14),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    Operand::Reg(Reg::XMM14),
+                    dst,
+                ));
+            }
+            // Can't move from memory address to memory address, or a large immediate to memory
+            Instruction::Mov(assembly_type, src, dst)
+                if (is_memory(&src) || is_large_imm(&src)) && is_memory(&dst) =>
+            {
+                instructions.push(Instruction::Mov(
+                    assembly_type,
+                    src,
                     Operand::Reg(Reg::R10),
                 ));
                 instructions.push(Instruction::Mov(
+                    assembly_type,
                     Operand::Reg(Reg::R10),
-                    Operand::Stack(dst),
+                    dst,
                 ));
             }
+            // Movsx can't take an immediate source or a memory destination
+            Instruction::Movsx(src_type, dst_type, src, dst) => {
+                let src = if let Operand::Imm(_) = src {
+                    instructions.push(Instruction::Mov(src_type, src, Operand::Reg(Reg::R10)));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+                if is_memory(&dst) {
+                    instructions.push(Instruction::Movsx(
+                        src_type,
+                        dst_type,
+                        src,
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Mov(dst_type, Operand::Reg(Reg::R11), dst));
+                } else {
+                    instructions.push(Instruction::Movsx(src_type, dst_type, src, dst));
+                }
+            }
+            // Same restrictions as movsx for bytes
+            Instruction::MovZeroExtend(AssemblyType::Byte, dst_type, src, dst) => {
+                let src = if let Operand::Imm(_) = src {
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Byte,
+                        src,
+                        Operand::Reg(Reg::R10),
+                    ));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+                if is_memory(&dst) {
+                    instructions.push(Instruction::MovZeroExtend(
+                        AssemblyType::Byte,
+                        dst_type,
+                        src,
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Mov(dst_type, Operand::Reg(Reg::R11), dst));
+                } else {
+                    instructions.push(Instruction::MovZeroExtend(
+                        AssemblyType::Byte,
+                        dst_type,
+                        src,
+                        dst,
+                    ));
+                }
+            }
+            // A 32 bit move into a register zeroes its upper half
+            Instruction::MovZeroExtend(_, _, src, dst) => {
+                if is_memory(&dst) {
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Longword,
+                        src,
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Quadword,
+                        Operand::Reg(Reg::R11),
+                        dst,
+                    ));
+                } else {
+                    instructions.push(Instruction::Mov(AssemblyType::Longword, src, dst));
+                }
+            }
             // Can't divide by an immediate value
-            Instruction::Idiv(Operand::Imm(value)) => {
+            Instruction::Idiv(assembly_type, Operand::Imm(value)) => {
                 instructions.push(Instruction::Mov(
+                    assembly_type,
                     Operand::Imm(value),
                     Operand::Reg(Reg::R10),
                 ));
-                instructions.push(Instruction::Idiv(Operand::Reg(Reg::R10)));
+                instructions.push(Instruction::Idiv(assembly_type, Operand::Reg(Reg::R10)));
+            }
+            Instruction::Div(assembly_type, Operand::Imm(value)) => {
+                instructions.push(Instruction::Mov(
+                    assembly_type,
+                    Operand::Imm(value),
+                    Operand::Reg(Reg::R10),
+                ));
+                instructions.push(Instruction::Div(assembly_type, Operand::Reg(Reg::R10)));
+            }
+            // SSE arithmetic only writes to registers
+            Instruction::Binary(op, AssemblyType::Double, src, dst) if !is_register(&dst) => {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    dst.clone(),
+                    Operand::Reg(Reg::XMM15),
+                ));
+                instructions.push(Instruction::Binary(
+                    op,
+                    AssemblyType::Double,
+                    src,
+                    Operand::Reg(Reg::XMM15),
+                ));
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    Operand::Reg(Reg::XMM15),
+                    dst,
+                ));
+            }
+            // Can't use a memory address as its destination
+            Instruction::Binary(BinaryOperator::Mult, assembly_type, src, dst)
+                if is_memory(&dst) || is_large_imm(&src) =>
+            {
+                let src = if is_large_imm(&src) {
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        src,
+                        Operand::Reg(Reg::R10),
+                    ));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+                if is_memory(&dst) {
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        dst.clone(),
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::Mult,
+                        assembly_type,
+                        src,
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        Operand::Reg(Reg::R11),
+                        dst,
+                    ));
+                } else {
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::Mult,
+                        assembly_type,
+                        src,
+                        dst,
+                    ));
+                }
             }
             // Can't use memory addresses as both the src and destination
             Instruction::Binary(
-                op @ (BinaryOperator::Add | BinaryOperator::Sub),
-                Operand::Stack(src),
-                Operand::Stack(dst),
-            ) => {
+                op @ (BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::And
+                | BinaryOperator::Or
+                | BinaryOperator::Xor),
+                assembly_type,
+                src,
+                dst,
+            ) if (is_memory(&src) && is_memory(&dst)) || is_large_imm(&src) => {
                 instructions.push(Instruction::Mov(
-                    Operand::Stack(src),
+                    assembly_type,
+                    src,
                     Operand::Reg(Reg::R10),
                 ));
                 instructions.push(Instruction::Binary(
                     op,
+                    assembly_type,
                     Operand::Reg(Reg::R10),
-                    Operand::Stack(dst),
+                    dst,
                 ));
             }
-            // Can't use a memory address as its destination
-            Instruction::Binary(BinaryOperator::Mult, src, Operand::Stack(dst)) => {
+            Instruction::Cmp(AssemblyType::Double, operand1, operand2)
+                if !is_register(&operand2) =>
+            {
+                instructions.push(Instruction::Mov(
+                    AssemblyType::Double,
+                    operand2,
+                    Operand::Reg(Reg::XMM15),
+                ));
+                instructions.push(Instruction::Cmp(
+                    AssemblyType::Double,
+                    operand1,
+                    Operand::Reg(Reg::XMM15),
+                ));
+            }
+            Instruction::Cmp(AssemblyType::Double, operand1, operand2) => {
+                instructions.push(Instruction::Cmp(AssemblyType::Double, operand1, operand2));
+            }
+            Instruction::Cmp(assembly_type, operand1, operand2) => {
+                let operand1 = if (is_memory(&operand1) && is_memory(&operand2))
+                    || is_large_imm(&operand1)
+                {
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        operand1,
+                        Operand::Reg(Reg::R10),
+                    ));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    operand1
+                };
+                if let Operand::Imm(_) = operand2 {
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        operand2,
+                        Operand::Reg(Reg::R11),
+                    ));
+                    instructions.push(Instruction::Cmp(
+                        assembly_type,
+                        operand1,
+                        Operand::Reg(Reg::R11),
+                    ));
+                } else {
+                    instructions.push(Instruction::Cmp(assembly_type, operand1, operand2));
+                }
+            }
+            // The destination of lea has to be a register
+            Instruction::Lea(src, dst) if !is_register(&dst) => {
+                instructions.push(Instruction::Lea(src, Operand::Reg(Reg::R11)));
                 instructions.push(Instruction::Mov(
-                    Operand::Stack(dst),
+                    AssemblyType::Quadword,
                     Operand::Reg(Reg::R11),
+                    dst,
                 ));
-                instructions.push(Instruction::Binary(
-                    BinaryOperator::Mult,
+            }
+            // The destination of cvttsd2si has to be a register
+            Instruction::Cvttsd2si(assembly_type, src, dst) if !is_register(&dst) => {
+                instructions.push(Instruction::Cvttsd2si(
+                    assembly_type,
                     src,
                     Operand::Reg(Reg::R11),
                 ));
                 instructions.push(Instruction::Mov(
+                    assembly_type,
                     Operand::Reg(Reg::R11),
-                    Operand::Stack(dst),
+                    dst,
                 ));
             }
-            Instruction::Cmp(Operand::Stack(offset1), Operand::Stack(offset2)) => {
-                instructions.push(Instruction::Mov(
-                    Operand::Stack(offset1),
-                    Operand::Reg(Reg::R10),
-                ));
-                instructions.push(Instruction::Cmp(
-                    Operand::Reg(Reg::R10),
-                    Operand::Stack(offset2),
-                ));
+            // cvtsi2sd can't take an immediate source and has to write to a register
+            Instruction::Cvtsi2sd(assembly_type, src, dst) => {
+                let src = if let Operand::Imm(_) = src {
+                    instructions.push(Instruction::Mov(
+                        assembly_type,
+                        src,
+                        Operand::Reg(Reg::R10),
+                    ));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+                if is_register(&dst) {
+                    instructions.push(Instruction::Cvtsi2sd(assembly_type, src, dst));
+                } else {
+                    instructions.push(Instruction::Cvtsi2sd(
+                        assembly_type,
+                        src,
+                        Operand::Reg(Reg::XMM15),
+                    ));
+                    instructions.push(Instruction::Mov(
+                        AssemblyType::Double,
+                        Operand::Reg(Reg::XMM15),
+                        dst,
+                    ));
+                }
             }
-            Instruction::Cmp(operand1, Operand::Imm(constant2)) => {
+            Instruction::Push(operand) if is_large_imm(&operand) => {
                 instructions.push(Instruction::Mov(
-                    Operand::Imm(constant2),
-                    Operand::Reg(Reg::R11),
+                    AssemblyType::Quadword,
+                    operand,
+                    Operand::Reg(Reg::R10),
                 ));
-                instructions.push(Instruction::Cmp(operand1, Operand::Reg(Reg::R11)));
+                instructions.push(Instruction::Push(Operand::Reg(Reg::R10)));
             }
             _ => instructions.push(instruction),
         }
@@ -499,31 +2025,189 @@ fn fix_up(orig_instructions: Vec<Instruction>, stack_size: i64) -> Vec<Instructi
     instructions
 }
 
-pub fn assemble(program: tacker::Program) -> Program {
+pub fn assemble(program: tacker::Program, symbol_table: &SymbolTable) -> Program {
     let mut functions: Vec<FunctionDefinition> = Vec::new();
-    for function in program.functions {
-        functions.push(FunctionDefinition {
-            name: function.identifier.clone(),
-            instructions: tacky_function_to_assembly(function),
-        });
+    let mut variables: Vec<StaticVariable> = Vec::new();
+    let mut constants: Vec<StaticConstant> = Vec::new();
+    for declaration in program.declarations {
+        match declaration {
+            tacker::TopLevel::Function {
+                identifier,
+                global,
+                params,
+                instructions,
+            } => {
+                let instructions = tacky_function_to_assembly(
+                    &identifier,
+                    params,
+                    instructions,
+                    symbol_table,
+                    &mut constants,
+                );
+                functions.push(FunctionDefinition {
+                    name: identifier,
+                    global,
+                    instructions,
+                });
+            }
+            tacker::TopLevel::StaticConstant {
+                identifier,
+                constant_type,
+                init,
+            } => {
+                constants.push(StaticConstant {
+                    name: identifier,
+                    alignment: get_type_alignment(get_assembly_type_of(&constant_type, symbol_table)),
+                    init,
+                });
+            }
+            tacker::TopLevel::StaticVariable {
+                identifier,
+                global,
+                var_type,
+                init,
+            } => {
+                variables.push(StaticVariable {
+                    name: identifier,
+                    global,
+                    read_only: var_type.is_const(),
+                    alignment: get_type_alignment(get_assembly_type_of(&var_type, symbol_table)),
+                    init,
+                });
+            }
+        }
     }
     let mut fixed_up_functions: Vec<FunctionDefinition> = Vec::new();
     for mut function in functions {
-        let stack_size = replace_pseudo_operands(&mut function.instructions);
+        let function_type = &symbol_table[&function.name].sym_type;
+        let mut reserved_stack = if returns_in_memory(function_type, symbol_table) {
+            8
+        } else {
+            0
+        };
+        if is_variadic(function_type) {
+            reserved_stack += REG_SAVE_AREA_SIZE;
+        }
+        let stack_size =
+            replace_pseudo_operands(&mut function.instructions, reserved_stack, symbol_table);
         fixed_up_functions.push(FunctionDefinition {
             name: function.name,
+            global: function.global,
             instructions: fix_up(function.instructions, stack_size),
         });
     }
 
     Program {
         functions: fixed_up_functions,
+        variables,
+        constants,
+    }
+}
+
+fn get_symbol_assembly_type(name: &str, symbol_table: &SymbolTable) -> AssemblyType {
+    match symbol_table.get(name) {
+        Some(entry) => get_assembly_type_of(&entry.sym_type, symbol_table),
+        None => panic!("No object type for symbol: {}", name),
+    }
+}
+
+fn get_assembly_type_of(object_type: &Type, symbol_table: &SymbolTable) -> AssemblyType {
+    match object_type {
+        Type::Long | Type::ULong | Type::Pointer(_) => AssemblyType::Quadword,
+        Type::Char | Type::SChar | Type::UChar => AssemblyType::Byte,
+        Type::Int | Type::UInt => AssemblyType::Longword,
+        Type::Double => AssemblyType::Double,
+        Type::Array(element_type, _) => {
+            let size = object_type.size(symbol_table);
+            // Arrays of 16 bytes or more are 16 byte aligned, like the ABI asks for
+            let alignment = if size >= 16 {
+                16
+            } else {
+                get_type_alignment(get_assembly_type_of(element_type, symbol_table))
+            };
+            AssemblyType::ByteArray { size, alignment }
+        }
+        Type::Structure(_) => AssemblyType::ByteArray {
+            size: object_type.size(symbol_table),
+            alignment: object_type.alignment(symbol_table),
+        },
+        Type::Function(..) => panic!("Functions aren't objects"),
+        Type::IncompleteArray(_) => panic!("Incomplete arrays don't have a size"),
+        Type::UnresolvedArray(..) => panic!("Array sizes are evaluated by the resolver"),
+        Type::Void => panic!("Void isn't an object type"),
+        Type::Qualified(inner, ..) => get_assembly_type_of(inner, symbol_table),
     }
 }
 
-fn val_to_operand(val: tacker::Val) -> Operand {
+fn get_assembly_type(val: &tacker::Val, symbol_table: &SymbolTable) -> AssemblyType {
     match val {
-        tacker::Val::Constant(value) => Operand::Imm(value),
+        tacker::Val::Constant(Const::Char(_) | Const::UChar(_)) => AssemblyType::Byte,
+        tacker::Val::Constant(Const::Int(_) | Const::UInt(_)) => AssemblyType::Longword,
+        tacker::Val::Constant(Const::Long(_) | Const::ULong(_)) => AssemblyType::Quadword,
+        tacker::Val::Constant(Const::Double(_)) => AssemblyType::Double,
+        tacker::Val::Var(name) => get_symbol_assembly_type(name, symbol_table),
+    }
+}
+
+fn is_signed(val: &tacker::Val, symbol_table: &SymbolTable) -> bool {
+    match val {
+        tacker::Val::Constant(Const::Char(_) | Const::Int(_) | Const::Long(_)) => true,
+        tacker::Val::Constant(
+            Const::UChar(_) | Const::UInt(_) | Const::ULong(_) | Const::Double(_),
+        ) => false,
+        tacker::Val::Var(name) => symbol_table[name].sym_type.is_signed(),
+    }
+}
+
+fn val_to_operand(val: tacker::Val, constants: &mut Vec<StaticConstant>) -> Operand {
+    match val {
+        tacker::Val::Constant(Const::Char(value)) => Operand::Imm(value as i64),
+        tacker::Val::Constant(Const::UChar(value)) => Operand::Imm(value as i8 as i64),
+        tacker::Val::Constant(Const::Int(value)) => Operand::Imm(value as i64),
+        tacker::Val::Constant(Const::Long(value)) => Operand::Imm(value),
+        // Keep the bit pattern, the instruction's operand size decides how it's read
+        tacker::Val::Constant(Const::UInt(value)) => Operand::Imm(value as i32 as i64),
+        tacker::Val::Constant(Const::ULong(value)) => Operand::Imm(value as i64),
+        tacker::Val::Constant(Const::Double(value)) => get_double_constant(value, 8, constants),
         tacker::Val::Var(name) => Operand::Pseudo(name),
     }
 }
+
+// Doubles can't be immediates, they are read from read only constants instead
+fn get_double_constant(
+    value: f64,
+    alignment: i64,
+    constants: &mut Vec<StaticConstant>,
+) -> Operand {
+    let existing = constants.iter().find(|constant| {
+        matches!(constant.init, StaticInit::DoubleInit(init) if init.to_bits() == value.to_bits())
+            && constant.alignment == alignment
+    });
+    let name = match existing {
+        Some(constant) => constant.name.clone(),
+        None => {
+            let name = format!(".Lconst_double.{}", unsafe {
+                CONSTANT_COUNTER += 1;
+                CONSTANT_COUNTER
+            });
+            constants.push(StaticConstant {
+                name: name.clone(),
+                alignment,
+                init: StaticInit::DoubleInit(value),
+            });
+            name
+        }
+    };
+    Operand::Data(name, 0)
+}
+
+static mut CONSTANT_COUNTER: i64 = -1;
+
+static mut LABEL_COUNTER: i64 = -1;
+
+fn make_label_name(prefix: &str) -> String {
+    format!("{}.{}", prefix, unsafe {
+        LABEL_COUNTER += 1;
+        LABEL_COUNTER
+    })
+}
@@ -0,0 +1,245 @@
+use crate::parser::*;
+use crate::type_checker::{get_common_type, is_complete, SymbolTable, Type};
+
+// Evaluates a constant expression at compile time with the conversions the program would do at
+// run time. The size of an expression is only known once it's type checked.
+pub fn evaluate_constant(
+    expression: &Expression,
+    symbol_table: &SymbolTable,
+) -> Result<Const, String> {
+    match &expression.kind {
+        ExpressionKind::Constant(constant) => Ok(*constant),
+        ExpressionKind::Cast(target_type, inner) => {
+            let value = evaluate_constant(inner, symbol_table)?;
+            if !target_type.is_arithmetic() {
+                return Err("Only casts to arithmetic types are constant".to_string());
+            }
+            Ok(convert_constant(value, target_type))
+        }
+        ExpressionKind::Unary(op, inner) => {
+            evaluate_unary(op, evaluate_constant(inner, symbol_table)?)
+        }
+        // The operand that isn't evaluated doesn't need a value
+        ExpressionKind::Binary(BinaryOperator::LAnd, left, right) => {
+            if is_zero(evaluate_constant(left, symbol_table)?) {
+                Ok(Const::Int(0))
+            } else {
+                Ok(Const::Int(!is_zero(evaluate_constant(right, symbol_table)?) as i32))
+            }
+        }
+        ExpressionKind::Binary(BinaryOperator::LOr, left, right) => {
+            if !is_zero(evaluate_constant(left, symbol_table)?) {
+                Ok(Const::Int(1))
+            } else {
+                Ok(Const::Int(!is_zero(evaluate_constant(right, symbol_table)?) as i32))
+            }
+        }
+        ExpressionKind::Binary(op, left, right) => evaluate_binary(
+            op,
+            evaluate_constant(left, symbol_table)?,
+            evaluate_constant(right, symbol_table)?,
+        ),
+        ExpressionKind::Conditional(condition, then, otherwise) => {
+            let (chosen, other) = if is_zero(evaluate_constant(condition, symbol_table)?) {
+                (otherwise, then)
+            } else {
+                (then, otherwise)
+            };
+            let value = evaluate_constant(chosen, symbol_table)?;
+            let result_type = match evaluate_constant(other, symbol_table) {
+                Ok(other_value) => get_common_type(&type_of(value), &type_of(other_value)),
+                Err(_) => type_of(value).promote(),
+            };
+            Ok(convert_constant(value, &result_type))
+        }
+        ExpressionKind::SizeOfType(target_type) => size_of(target_type, symbol_table),
+        ExpressionKind::SizeOf(inner) => match &inner.exp_type {
+            Some(inner_type) => size_of(inner_type, symbol_table),
+            None => Err("Expected a constant expression".to_string()),
+        },
+        _ => Err("Expected a constant expression".to_string()),
+    }
+}
+
+// Case labels, enumerators and array sizes
+pub fn evaluate_integer_constant(
+    expression: &Expression,
+    symbol_table: &SymbolTable,
+) -> Result<i64, String> {
+    match evaluate_constant(expression, symbol_table)? {
+        Const::Double(_) => Err("Expected an integer constant".to_string()),
+        constant => Ok(integer_value(constant) as i64),
+    }
+}
+
+// The value an integer has once converted to an integer type, like a case value is
+pub fn convert_integer(value: i64, target_type: &Type) -> i64 {
+    integer_value(convert_constant(Const::Long(value), target_type)) as i64
+}
+
+pub fn convert_constant(constant: Const, target_type: &Type) -> Const {
+    let value = match constant {
+        Const::Double(value) if *target_type.unqualified() == Type::Double => {
+            return Const::Double(value)
+        }
+        // Out of range values are undefined behavior, saturate like Rust does
+        Const::Double(value) if target_type.is_signed() => value as i64 as i128,
+        Const::Double(value) => value as u64 as i128,
+        integer => integer_value(integer),
+    };
+    match target_type.unqualified() {
+        Type::Char | Type::SChar => Const::Char(value as i8),
+        Type::UChar => Const::UChar(value as u8),
+        Type::Int => Const::Int(value as i32),
+        Type::UInt => Const::UInt(value as u32),
+        Type::Long => Const::Long(value as i64),
+        Type::Double => Const::Double(value as f64),
+        _ => Const::ULong(value as u64),
+    }
+}
+
+fn evaluate_unary(op: &UnaryOperator, value: Const) -> Result<Const, String> {
+    match (op, value) {
+        (UnaryOperator::Not, value) => Ok(Const::Int(is_zero(value) as i32)),
+        (UnaryOperator::Negate, Const::Double(value)) => Ok(Const::Double(-value)),
+        (UnaryOperator::Complement, Const::Double(_)) => {
+            Err("Can't take the complement of a double".to_string())
+        }
+        (UnaryOperator::Negate, value) => {
+            make_integer(-integer_value(value), &type_of(value).promote())
+        }
+        (UnaryOperator::Complement, value) => {
+            make_integer(!integer_value(value), &type_of(value).promote())
+        }
+    }
+}
+
+fn evaluate_binary(op: &BinaryOperator, left: Const, right: Const) -> Result<Const, String> {
+    // Shifts don't convert their operands to a common type
+    if let BinaryOperator::LeftShift | BinaryOperator::RightShift = op {
+        let result_type = type_of(left).promote();
+        let (left, count) = match (left, right) {
+            (Const::Double(_), _) | (_, Const::Double(_)) => {
+                return Err("Shift operands must be integers".to_string())
+            }
+            (left, right) => (integer_value(left), integer_value(right)),
+        };
+        if count < 0 || count >= arithmetic_bits(&result_type) {
+            return Err(format!("Invalid shift count in constant expression: {}", count));
+        }
+        if result_type.is_signed() && left < 0 && *op == BinaryOperator::LeftShift {
+            return Err("Left shift of a negative value in constant expression".to_string());
+        }
+        return match op {
+            BinaryOperator::LeftShift => make_integer(left << count, &result_type),
+            _ => make_integer(left >> count, &result_type),
+        };
+    }
+
+    let common_type = get_common_type(&type_of(left), &type_of(right));
+    if common_type == Type::Double {
+        let (left, right) = (double_value(left), double_value(right));
+        return match op {
+            BinaryOperator::Add => Ok(Const::Double(left + right)),
+            BinaryOperator::Subtract => Ok(Const::Double(left - right)),
+            BinaryOperator::Multiply => Ok(Const::Double(left * right)),
+            BinaryOperator::Divide => Ok(Const::Double(left / right)),
+            BinaryOperator::EqualTo => Ok(Const::Int((left == right) as i32)),
+            BinaryOperator::NotEqualTo => Ok(Const::Int((left != right) as i32)),
+            BinaryOperator::LessThan => Ok(Const::Int((left < right) as i32)),
+            BinaryOperator::LessOrEqual => Ok(Const::Int((left <= right) as i32)),
+            BinaryOperator::GreaterThan => Ok(Const::Int((left > right) as i32)),
+            BinaryOperator::GreaterOrEqual => Ok(Const::Int((left >= right) as i32)),
+            _ => Err(format!("Invalid operands to {:?} in constant expression", op)),
+        };
+    }
+
+    let left = integer_value(convert_constant(left, &common_type));
+    let right = integer_value(convert_constant(right, &common_type));
+    let result = match op {
+        BinaryOperator::Add => left + right,
+        BinaryOperator::Subtract => left - right,
+        // Only the low bits of an unsigned long product are kept
+        BinaryOperator::Multiply => left.wrapping_mul(right),
+        BinaryOperator::Divide | BinaryOperator::Modulo if right == 0 => {
+            return Err("Division by zero in constant expression".to_string())
+        }
+        BinaryOperator::Divide => left / right,
+        BinaryOperator::Modulo => left % right,
+        BinaryOperator::And => left & right,
+        BinaryOperator::Or => left | right,
+        BinaryOperator::Xor => left ^ right,
+        BinaryOperator::EqualTo => return Ok(Const::Int((left == right) as i32)),
+        BinaryOperator::NotEqualTo => return Ok(Const::Int((left != right) as i32)),
+        BinaryOperator::LessThan => return Ok(Const::Int((left < right) as i32)),
+        BinaryOperator::LessOrEqual => return Ok(Const::Int((left <= right) as i32)),
+        BinaryOperator::GreaterThan => return Ok(Const::Int((left > right) as i32)),
+        BinaryOperator::GreaterOrEqual => return Ok(Const::Int((left >= right) as i32)),
+        _ => return Err(format!("Invalid operator in constant expression: {:?}", op)),
+    };
+    make_integer(result, &common_type)
+}
+
+// Signed results must be representable, unsigned ones wrap around
+fn make_integer(value: i128, result_type: &Type) -> Result<Const, String> {
+    let bits = arithmetic_bits(result_type);
+    if result_type.is_signed() && (value < -(1 << (bits - 1)) || value >= 1 << (bits - 1)) {
+        return Err("Integer overflow in constant expression".to_string());
+    }
+    Ok(convert_constant(Const::ULong(value as u64), result_type))
+}
+
+fn integer_value(constant: Const) -> i128 {
+    match constant {
+        Const::Char(value) => value as i128,
+        Const::UChar(value) => value as i128,
+        Const::Int(value) => value as i128,
+        Const::Long(value) => value as i128,
+        Const::UInt(value) => value as i128,
+        Const::ULong(value) => value as i128,
+        Const::Double(value) => value as i128,
+    }
+}
+
+fn double_value(constant: Const) -> f64 {
+    match constant {
+        Const::Double(value) => value,
+        integer => integer_value(integer) as f64,
+    }
+}
+
+fn is_zero(constant: Const) -> bool {
+    match constant {
+        Const::Double(value) => value == 0.0,
+        integer => integer_value(integer) == 0,
+    }
+}
+
+fn type_of(constant: Const) -> Type {
+    match constant {
+        Const::Char(_) => Type::Char,
+        Const::UChar(_) => Type::UChar,
+        Const::Int(_) => Type::Int,
+        Const::Long(_) => Type::Long,
+        Const::UInt(_) => Type::UInt,
+        Const::ULong(_) => Type::ULong,
+        Const::Double(_) => Type::Double,
+    }
+}
+
+fn arithmetic_bits(integer_type: &Type) -> i128 {
+    match integer_type {
+        Type::Int | Type::UInt => 32,
+        _ => 64,
+    }
+}
+
+fn size_of(target_type: &Type, symbol_table: &SymbolTable) -> Result<Const, String> {
+    if matches!(target_type, Type::Function(..)) {
+        return Err("Can't take the size of a function".to_string());
+    }
+    if !is_complete(target_type, symbol_table) {
+        return Err(format!("Size of an incomplete type: {:?}", target_type));
+    }
+    Ok(Const::ULong(target_type.size(symbol_table) as u64))
+}
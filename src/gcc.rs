@@ -1,16 +1,6 @@
 use std::io;
 use std::process::Command;
 
-// pub fn preprocess(input_file: &str, preprocessed_file: &str) -> io::Result<()> {
-//     let status = Command::new("gcc")
-//         .args(["-E", "-P", input_file, "-o", preprocessed_file])
-//         .status()?;
-//     if !status.success() {
-//         return Err(io::Error::new(io::ErrorKind::Other, "Preprocessing failed"));
-//     }
-//     Ok(())
-// }
-
 // pub fn generate_assembly(preprocessed_file: &str, assembly_file: &str) -> io::Result<()> {
 //     let status = Command::new("gcc")
 //         .args([
@@ -44,12 +34,14 @@ pub fn compile_executable(
 
     if no_main {
         args.push("-c".into());
+    } else {
+        args.push("-lm".into());
     }
 
     let status = Command::new("gcc").args(&args).status()?;
 
     if !status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "Compilation failed"));
+        return Err(io::Error::other("Compilation failed"));
     }
     Ok(())
 }
@@ -1,7 +1,7 @@
 use crate::assembler;
 use std::fs::File;
 use std::io::{self, Write};
-use crate::type_checker::SymbolTable;
+use crate::type_checker::{StaticInit, SymbolTable};
 
 fn write_reg(file: &mut File, reg: assembler::Reg) -> io::Result<()> {
     match reg {
@@ -14,6 +14,17 @@ fn write_reg(file: &mut File, reg: assembler::Reg) -> io::Result<()> {
         assembler::Reg::R9 => write!(file, "%r9")?,
         assembler::Reg::R10 => write!(file, "%r10")?,
         assembler::Reg::R11 => write!(file, "%r11")?,
+        assembler::Reg::SP => write!(file, "%rsp")?,
+        assembler::Reg::XMM0 => write!(file, "%xmm0")?,
+        assembler::Reg::XMM1 => write!(file, "%xmm1")?,
+        assembler::Reg::XMM2 => write!(file, "%xmm2")?,
+        assembler::Reg::XMM3 => write!(file, "%xmm3")?,
+        assembler::Reg::XMM4 => write!(file, "%xmm4")?,
+        assembler::Reg::XMM5 => write!(file, "%xmm5")?,
+        assembler::Reg::XMM6 => write!(file, "%xmm6")?,
+        assembler::Reg::XMM7 => write!(file, "%xmm7")?,
+        assembler::Reg::XMM14 => write!(file, "%xmm14")?,
+        assembler::Reg::XMM15 => write!(file, "%xmm15")?,
     }
     Ok(())
 }
@@ -29,6 +40,8 @@ fn write_reg_double(file: &mut File, reg: assembler::Reg) -> io::Result<()> {
         assembler::Reg::R9 => write!(file, "%r9d")?,
         assembler::Reg::R10 => write!(file, "%r10d")?,
         assembler::Reg::R11 => write!(file, "%r11d")?,
+        // xmm registers have no smaller names
+        xmm => write_reg(file, xmm)?,
     }
     Ok(())
 }
@@ -44,6 +57,7 @@ fn write_reg_byte(file: &mut File, reg: assembler::Reg) -> io::Result<()> {
         assembler::Reg::R9 => write!(file, "%r9b")?,
         assembler::Reg::R10 => write!(file, "%r10b")?,
         assembler::Reg::R11 => write!(file, "%r11b")?,
+        xmm => write_reg(file, xmm)?,
     }
     Ok(())
 }
@@ -52,6 +66,33 @@ fn write_stack(file: &mut File, offset: i64) -> io::Result<()> {
     write!(file, "{}(%rbp)", offset)
 }
 
+fn write_memory(file: &mut File, reg: assembler::Reg, offset: i64) -> io::Result<()> {
+    write!(file, "{}(", offset)?;
+    write_reg(file, reg)?;
+    write!(file, ")")
+}
+
+fn write_indexed(
+    file: &mut File,
+    base: assembler::Reg,
+    index: assembler::Reg,
+    scale: i64,
+) -> io::Result<()> {
+    write!(file, "(")?;
+    write_reg(file, base)?;
+    write!(file, ",")?;
+    write_reg(file, index)?;
+    write!(file, ",{})", scale)
+}
+
+fn write_data(file: &mut File, name: String, offset: i64) -> io::Result<()> {
+    if offset == 0 {
+        write!(file, "{}(%rip)", name)
+    } else {
+        write!(file, "{}+{}(%rip)", name, offset)
+    }
+}
+
 fn write_imm(file: &mut File, value: i64) -> io::Result<()> {
     write!(file, "${}", value)
 }
@@ -61,7 +102,14 @@ fn write_operand_double(file: &mut File, operand: assembler::Operand) -> io::Res
         assembler::Operand::Imm(value) => write_imm(file, value)?,
         assembler::Operand::Reg(reg) => write_reg_double(file, reg)?,
         assembler::Operand::Stack(offset) => write_stack(file, offset)?,
-        assembler::Operand::Pseudo(_) => panic!("Shouldn't have a pseudo register at this stage."),
+        assembler::Operand::Memory(reg, offset) => write_memory(file, reg, offset)?,
+        assembler::Operand::Data(name, offset) => write_data(file, name, offset)?,
+        assembler::Operand::Indexed(base, index, scale) => {
+            write_indexed(file, base, index, scale)?
+        }
+        assembler::Operand::Pseudo(_) | assembler::Operand::PseudoMem(_, _) => {
+            panic!("Shouldn't have a pseudo register at this stage.")
+        }
     }
     Ok(())
 }
@@ -71,7 +119,14 @@ fn write_operand(file: &mut File, operand: assembler::Operand) -> io::Result<()>
         assembler::Operand::Imm(value) => write_imm(file, value)?,
         assembler::Operand::Reg(reg) => write_reg(file, reg)?,
         assembler::Operand::Stack(offset) => write_stack(file, offset)?,
-        assembler::Operand::Pseudo(_) => panic!("Shouldn't have a pseudo register at this stage."),
+        assembler::Operand::Memory(reg, offset) => write_memory(file, reg, offset)?,
+        assembler::Operand::Data(name, offset) => write_data(file, name, offset)?,
+        assembler::Operand::Indexed(base, index, scale) => {
+            write_indexed(file, base, index, scale)?
+        }
+        assembler::Operand::Pseudo(_) | assembler::Operand::PseudoMem(_, _) => {
+            panic!("Shouldn't have a pseudo register at this stage.")
+        }
     }
     Ok(())
 }
@@ -81,23 +136,40 @@ fn write_operand_byte(file: &mut File, operand: assembler::Operand) -> io::Resul
         assembler::Operand::Imm(value) => write_imm(file, value)?,
         assembler::Operand::Reg(reg) => write_reg_byte(file, reg)?,
         assembler::Operand::Stack(offset) => write_stack(file, offset)?,
-        assembler::Operand::Pseudo(_) => panic!("Shouldn't have a pseudo register at this stage."),
+        assembler::Operand::Memory(reg, offset) => write_memory(file, reg, offset)?,
+        assembler::Operand::Data(name, offset) => write_data(file, name, offset)?,
+        assembler::Operand::Indexed(base, index, scale) => {
+            write_indexed(file, base, index, scale)?
+        }
+        assembler::Operand::Pseudo(_) | assembler::Operand::PseudoMem(_, _) => {
+            panic!("Shouldn't have a pseudo register at this stage.")
+        }
     }
     Ok(())
 }
 
-fn write_operand_not_imm(file: &mut File, operand: assembler::Operand) -> io::Result<()> {
-    match operand {
-        assembler::Operand::Imm(_) => {
-            panic!("Invalid operand imm.");
-        }
-        assembler::Operand::Reg(reg) => write_reg_double(file, reg)?,
-        assembler::Operand::Stack(offset) => write_stack(file, offset)?,
-        assembler::Operand::Pseudo(_) => {
-            panic!("Shouldn't have a pseudo register at this stage.");
-        }
+fn write_operand_sized(
+    file: &mut File,
+    assembly_type: assembler::AssemblyType,
+    operand: assembler::Operand,
+) -> io::Result<()> {
+    match assembly_type {
+        assembler::AssemblyType::Byte => write_operand_byte(file, operand),
+        assembler::AssemblyType::Longword => write_operand_double(file, operand),
+        assembler::AssemblyType::Quadword
+        | assembler::AssemblyType::Double
+        | assembler::AssemblyType::ByteArray { .. } => write_operand(file, operand),
+    }
+}
+
+fn type_suffix<'a>(assembly_type: assembler::AssemblyType) -> &'a str {
+    match assembly_type {
+        assembler::AssemblyType::Byte => "b",
+        assembler::AssemblyType::Longword => "l",
+        assembler::AssemblyType::Quadword => "q",
+        assembler::AssemblyType::Double => "sd",
+        assembler::AssemblyType::ByteArray { .. } => panic!("Byte arrays have no instruction suffix"),
     }
-    Ok(())
 }
 
 fn seperate(file: &mut File) -> io::Result<()> {
@@ -117,6 +189,11 @@ fn cond_code_to_letters<'a>(cc: assembler::CondCode) -> &'a str {
         assembler::CondCode::LE => "le",
         assembler::CondCode::G => "g",
         assembler::CondCode::GE => "ge",
+        assembler::CondCode::A => "a",
+        assembler::CondCode::AE => "ae",
+        assembler::CondCode::B => "b",
+        assembler::CondCode::BE => "be",
+        assembler::CondCode::P => "p",
     }
 }
 
@@ -127,13 +204,114 @@ pub fn generate(file_path: &str, symbol_table: SymbolTable, program: assembler::
         generate_function(&mut file, &symbol_table, function)?;
     }
 
+    for variable in program.variables {
+        generate_variable(&mut file, variable)?;
+    }
+
+    for constant in program.constants {
+        generate_constant(&mut file, constant)?;
+    }
+
     writeln!(file, ".section .note.GNU-stack,\"\",@progbits")?;
 
     Ok(())
 }
 
+// Zero initialized variables go to .bss, const ones to .rodata unless the dynamic linker has to
+// fill in an address
+fn generate_variable(file: &mut File, variable: assembler::StaticVariable) -> io::Result<()> {
+    let has_address = variable
+        .init
+        .iter()
+        .any(|init| matches!(init, StaticInit::PointerInit(..)));
+    let section = if variable.read_only && has_address {
+        ".section .data.rel.ro,\"aw\""
+    } else if variable.read_only {
+        ".section .rodata"
+    } else if variable.init.iter().all(is_zero_init) {
+        ".bss"
+    } else {
+        ".data"
+    };
+    if variable.global {
+        writeln!(file, "\t.globl {}", variable.name)?;
+    }
+    writeln!(file, "\t{}", section)?;
+    writeln!(file, "\t.balign {}", variable.alignment)?;
+    writeln!(file, "{}:", variable.name)?;
+    for init in variable.init {
+        match init {
+            StaticInit::CharInit(value) => writeln!(file, "\t.byte {}", value)?,
+            StaticInit::UCharInit(value) => writeln!(file, "\t.byte {}", value)?,
+            StaticInit::IntInit(value) => writeln!(file, "\t.long {}", value)?,
+            StaticInit::UIntInit(value) => writeln!(file, "\t.long {}", value)?,
+            StaticInit::LongInit(value) => writeln!(file, "\t.quad {}", value)?,
+            StaticInit::ULongInit(value) => writeln!(file, "\t.quad {}", value)?,
+            StaticInit::DoubleInit(value) => writeln!(file, "\t.quad {}", value.to_bits())?,
+            StaticInit::ZeroInit(bytes) => writeln!(file, "\t.zero {}", bytes)?,
+            StaticInit::StringInit(bytes, null_terminated) => {
+                let directive = if null_terminated { "asciz" } else { "ascii" };
+                writeln!(file, "\t.{} \"{}\"", directive, escape_string(&bytes))?;
+            }
+            StaticInit::PointerInit(name, 0) => writeln!(file, "\t.quad {}", name)?,
+            StaticInit::PointerInit(name, offset) => {
+                writeln!(file, "\t.quad {}{:+}", name, offset)?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_zero_init(init: &StaticInit) -> bool {
+    match init {
+        StaticInit::CharInit(value) => *value == 0,
+        StaticInit::UCharInit(value) => *value == 0,
+        StaticInit::IntInit(value) => *value == 0,
+        StaticInit::UIntInit(value) => *value == 0,
+        StaticInit::LongInit(value) => *value == 0,
+        StaticInit::ULongInit(value) => *value == 0,
+        // Negative zero isn't all zero bits
+        StaticInit::DoubleInit(value) => value.to_bits() == 0,
+        StaticInit::ZeroInit(_) => true,
+        StaticInit::StringInit(..) | StaticInit::PointerInit(..) => false,
+    }
+}
+
+fn generate_constant(file: &mut File, constant: assembler::StaticConstant) -> io::Result<()> {
+    writeln!(file, "\t.section .rodata")?;
+    writeln!(file, "\t.balign {}", constant.alignment)?;
+    writeln!(file, "{}:", constant.name)?;
+    match constant.init {
+        StaticInit::DoubleInit(value) => writeln!(file, "\t.quad {}", value.to_bits())?,
+        StaticInit::StringInit(bytes, null_terminated) => {
+            let directive = if null_terminated { "asciz" } else { "ascii" };
+            writeln!(file, "\t.{} \"{}\"", directive, escape_string(&bytes))?;
+        }
+        init => panic!("Unexpected read only constant: {:?}", init),
+    }
+    Ok(())
+}
+
+// Anything that isn't printable is written as an octal escape
+fn escape_string(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
 fn generate_function(file: &mut File, symbol_table: &SymbolTable, function: assembler::FunctionDefinition) -> io::Result<()> {
-    writeln!(file, "\t.globl {}", function.name)?;
+    if function.global {
+        writeln!(file, "\t.globl {}", function.name)?;
+    }
     writeln!(file, "{}:", function.name)?;
     writeln!(file, "\tpushq %rbp")?;
     writeln!(file, "\tmovq %rsp, %rbp")?;
@@ -142,72 +320,167 @@ fn generate_function(file: &mut File, symbol_table: &SymbolTable, function: asse
             assembler::Instruction::AllocateStack(size) => {
                 writeln!(file, "\tsubq ${}, %rsp", size)?;
             }
-            assembler::Instruction::Mov(src, dst) => {
-                write!(file, "\tmovl ")?;
-                write_operand_double(file, src)?;
+            assembler::Instruction::Mov(assembly_type, src, dst) => {
+                write!(file, "\tmov{} ", type_suffix(assembly_type))?;
+                write_operand_sized(file, assembly_type, src)?;
+                seperate(file)?;
+                write_operand_sized(file, assembly_type, dst)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Movsx(src_type, dst_type, src, dst) => {
+                write!(
+                    file,
+                    "\tmovs{}{} ",
+                    type_suffix(src_type),
+                    type_suffix(dst_type)
+                )?;
+                write_operand_sized(file, src_type, src)?;
                 seperate(file)?;
-                write_operand_not_imm(file, dst)?;
+                write_operand_sized(file, dst_type, dst)?;
                 newline(file)?;
             }
-            assembler::Instruction::Unary(operator, operand) => {
+            assembler::Instruction::MovZeroExtend(
+                assembler::AssemblyType::Byte,
+                dst_type,
+                src,
+                dst,
+            ) => {
+                write!(file, "\tmovzb{} ", type_suffix(dst_type))?;
+                write_operand_byte(file, src)?;
+                seperate(file)?;
+                write_operand_sized(file, dst_type, dst)?;
+                newline(file)?;
+            }
+            assembler::Instruction::MovZeroExtend(_, _, _, _) => {
+                panic!("MovZeroExtend should have been replaced by fix up")
+            }
+            assembler::Instruction::Unary(operator, assembly_type, operand) => {
                 match operator {
                     assembler::UnaryOperator::Neg => {
-                        write!(file, "\tnegl ")?;
+                        write!(file, "\tneg{} ", type_suffix(assembly_type))?;
                     }
                     assembler::UnaryOperator::Not => {
-                        write!(file, "\tnotl ")?;
+                        write!(file, "\tnot{} ", type_suffix(assembly_type))?;
                     }
                 }
 
-                write_operand_not_imm(file, operand)?;
+                write_operand_sized(file, assembly_type, operand)?;
                 newline(file)?;
             }
-            assembler::Instruction::Binary(operator, operand1, operand2) => {
+            assembler::Instruction::Binary(operator, assembly_type, operand1, operand2) => {
+                let suffix = type_suffix(assembly_type);
                 match operator {
+                    assembler::BinaryOperator::Mult
+                        if assembly_type == assembler::AssemblyType::Double =>
+                    {
+                        write!(file, "\tmulsd ")?;
+                    }
+                    assembler::BinaryOperator::Xor
+                        if assembly_type == assembler::AssemblyType::Double =>
+                    {
+                        write!(file, "\txorpd ")?;
+                    }
+                    assembler::BinaryOperator::DivDouble => {
+                        write!(file, "\tdivsd ")?;
+                    }
                     assembler::BinaryOperator::Add => {
-                        write!(file, "\taddl ")?;
+                        write!(file, "\tadd{} ", suffix)?;
                     }
                     assembler::BinaryOperator::Sub => {
-                        write!(file, "\tsubl ")?;
+                        write!(file, "\tsub{} ", suffix)?;
                     }
                     assembler::BinaryOperator::Mult => {
-                        write!(file, "\timull ")?;
+                        write!(file, "\timul{} ", suffix)?;
                     }
                     assembler::BinaryOperator::And => {
-                        write!(file, "\tandl ")?;
+                        write!(file, "\tand{} ", suffix)?;
                     }
                     assembler::BinaryOperator::Or => {
-                        write!(file, "\torl ")?;
+                        write!(file, "\tor{} ", suffix)?;
                     }
                     assembler::BinaryOperator::Xor => {
-                        write!(file, "\txorl ")?;
+                        write!(file, "\txor{} ", suffix)?;
                     }
                     assembler::BinaryOperator::LeftShift => {
-                        write!(file, "\tshl ")?;
+                        write!(file, "\tshl{} ", suffix)?;
                     }
                     assembler::BinaryOperator::RightShift => {
-                        write!(file, "\tshr ")?;
+                        write!(file, "\tshr{} ", suffix)?;
+                    }
+                    assembler::BinaryOperator::ArithmeticRightShift => {
+                        write!(file, "\tsar{} ", suffix)?;
                     }
                 }
 
-                write_operand_double(file, operand1)?;
+                match operator {
+                    // The shift count is always a byte
+                    assembler::BinaryOperator::LeftShift
+                    | assembler::BinaryOperator::RightShift
+                    | assembler::BinaryOperator::ArithmeticRightShift => {
+                        write_operand_byte(file, operand1)?
+                    }
+                    _ => write_operand_sized(file, assembly_type, operand1)?,
+                }
                 seperate(file)?;
-                write_operand_not_imm(file, operand2)?;
+                write_operand_sized(file, assembly_type, operand2)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Idiv(assembly_type, operand) => {
+                write!(file, "\tidiv{} ", type_suffix(assembly_type))?;
+                write_operand_sized(file, assembly_type, operand)?;
                 newline(file)?;
             }
-            assembler::Instruction::Idiv(operand) => {
-                write!(file, "\tidivl ")?;
-                write_operand_not_imm(file, operand)?;
+            assembler::Instruction::Div(assembly_type, operand) => {
+                write!(file, "\tdiv{} ", type_suffix(assembly_type))?;
+                write_operand_sized(file, assembly_type, operand)?;
                 newline(file)?;
             }
-            assembler::Instruction::Cdq => {
+            assembler::Instruction::Cdq(assembler::AssemblyType::Longword) => {
                 writeln!(file, "\tcdq")?;
             }
-            assembler::Instruction::Cmp(operand1, operand2) => {
-                write!(file, "\tcmpl ")?;
-                write_operand_double(file, operand1)?;
+            assembler::Instruction::Cdq(assembler::AssemblyType::Quadword) => {
+                writeln!(file, "\tcqo")?;
+            }
+            assembler::Instruction::Cdq(
+                assembler::AssemblyType::Byte
+                | assembler::AssemblyType::Double
+                | assembler::AssemblyType::ByteArray { .. },
+            ) => {
+                panic!("Can't sign extend a double")
+            }
+            assembler::Instruction::Lea(src, dst) => {
+                write!(file, "\tleaq ")?;
+                write_operand(file, src)?;
+                seperate(file)?;
+                write_operand(file, dst)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Cvttsd2si(assembly_type, src, dst) => {
+                write!(file, "\tcvttsd2si{} ", type_suffix(assembly_type))?;
+                write_operand(file, src)?;
+                seperate(file)?;
+                write_operand_sized(file, assembly_type, dst)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Cvtsi2sd(assembly_type, src, dst) => {
+                write!(file, "\tcvtsi2sd{} ", type_suffix(assembly_type))?;
+                write_operand_sized(file, assembly_type, src)?;
+                seperate(file)?;
+                write_operand(file, dst)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Cmp(assembler::AssemblyType::Double, operand1, operand2) => {
+                write!(file, "\tcomisd ")?;
+                write_operand(file, operand1)?;
+                seperate(file)?;
+                write_operand(file, operand2)?;
+                newline(file)?;
+            }
+            assembler::Instruction::Cmp(assembly_type, operand1, operand2) => {
+                write!(file, "\tcmp{} ", type_suffix(assembly_type))?;
+                write_operand_sized(file, assembly_type, operand1)?;
                 seperate(file)?;
-                write_operand_not_imm(file, operand2)?;
+                write_operand_sized(file, assembly_type, operand2)?;
                 newline(file)?;
             }
             assembler::Instruction::Jmp(target) => {
@@ -244,6 +517,11 @@ fn generate_function(file: &mut File, symbol_table: &SymbolTable, function: asse
                     writeln!(file, "\tcall {}@PLT", label)?;
                 }
             },
+            assembler::Instruction::CallIndirect(operand) => {
+                write!(file, "\tcall *")?;
+                write_operand(file, operand)?;
+                newline(file)?;
+            },
         }
     }
 
@@ -1,16 +1,20 @@
+use crate::constant_evaluator::evaluate_integer_constant;
 use crate::parser::*;
+use crate::type_checker::{typecheck_struct_declaration, SymbolTable, Type};
 use std::collections::HashMap;
 
 struct IdentifierEntry {
     unique_name: String,
     from_current_scope: bool,
     has_linkage: bool,
+    constant: Option<Const>, // enumerators are replaced by their value
 }
 
 type IdentifierMap = HashMap<String, IdentifierEntry>;
 
 pub fn resolve_identifiers(program: Program) -> Result<Program, String> {
     let mut identifier_map: IdentifierMap = HashMap::new();
+    let mut structures: SymbolTable = HashMap::new();
     let mut new_declarations: Vec<Declaration> = Vec::new();
     for declaration in program.declarations {
         match declaration {
@@ -18,13 +22,32 @@ pub fn resolve_identifiers(program: Program) -> Result<Program, String> {
                 new_declarations.push(Declaration::FuncDecl(resolve_function_declaration(
                     function,
                     &mut identifier_map,
+                    &mut structures,
                 )?));
             }
             Declaration::VarDecl(variable) => {
                 new_declarations.push(Declaration::VarDecl(
-                    resolve_file_scope_variable_declaration(variable, &mut identifier_map)?,
+                    resolve_file_scope_variable_declaration(
+                        variable,
+                        &mut identifier_map,
+                        &mut structures,
+                    )?,
                 ));
             }
+            Declaration::StructDecl(struct_declaration) => {
+                new_declarations.push(Declaration::StructDecl(resolve_struct_declaration(
+                    struct_declaration,
+                    &mut identifier_map,
+                    &mut structures,
+                )?));
+            }
+            Declaration::EnumDecl(enum_declaration) => {
+                new_declarations.push(Declaration::EnumDecl(resolve_enum_declaration(
+                    enum_declaration,
+                    &mut identifier_map,
+                    &mut structures,
+                )?));
+            }
         }
     }
     Ok(Program {
@@ -35,6 +58,7 @@ pub fn resolve_identifiers(program: Program) -> Result<Program, String> {
 fn resolve_file_scope_variable_declaration(
     var_declaration: VariableDeclaration,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<VariableDeclaration, String> {
     identifier_map.insert(
         var_declaration.name.clone(),
@@ -42,20 +66,194 @@ fn resolve_file_scope_variable_declaration(
             unique_name: var_declaration.name.clone(),
             from_current_scope: true,
             has_linkage: true,
+            constant: None,
+        },
+    );
+    // Static initializers are constants, but they can still name enumerators
+    let init = match var_declaration.init {
+        Some(initializer) => Some(resolve_initializer(
+            initializer,
+            identifier_map,
+            structures,
+        )?),
+        None => None,
+    };
+    Ok(VariableDeclaration {
+        var_type: resolve_type(var_declaration.var_type, identifier_map, structures)?,
+        init,
+        ..var_declaration
+    })
+}
+
+// Marks the scope of a variadic function's body, an ellipsis can't be an identifier
+const VARIADIC_KEY: &str = "...";
+
+// Structure and union tags live in their own namespace, a space can't appear in an identifier
+fn struct_key(tag: &str) -> String {
+    format!("struct {}", tag)
+}
+
+fn resolve_struct_declaration(
+    struct_declaration: StructDeclaration,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<StructDeclaration, String> {
+    let unique_tag = match identifier_map.get(&struct_key(&struct_declaration.tag)) {
+        Some(entry) if entry.from_current_scope => entry.unique_name.clone(),
+        _ => declare_struct_tag(struct_declaration.tag, identifier_map),
+    };
+    let mut members: Vec<MemberDeclaration> = Vec::new();
+    for member in struct_declaration.members {
+        members.push(MemberDeclaration {
+            name: member.name,
+            member_type: resolve_type(member.member_type, identifier_map, structures)?,
+        });
+    }
+    // The layout is needed right away, sizeof a structure can appear in a constant expression
+    let struct_declaration = StructDeclaration {
+        tag: unique_tag,
+        members,
+        is_union: struct_declaration.is_union,
+    };
+    typecheck_struct_declaration(&struct_declaration, structures)?;
+    Ok(struct_declaration)
+}
+
+fn declare_struct_tag(tag: String, identifier_map: &mut IdentifierMap) -> String {
+    let key = struct_key(&tag);
+    let unique_tag = make_unique_name(tag);
+    identifier_map.insert(
+        key,
+        IdentifierEntry {
+            unique_name: unique_tag.clone(),
+            from_current_scope: true,
+            has_linkage: false,
+            constant: None,
         },
     );
-    Ok(var_declaration)
+    unique_tag
 }
 
-fn resolve_block(block: Block, identifier_map: &mut IdentifierMap) -> Result<Block, String> {
+// Enumerators are int constants in the ordinary namespace, numbered from zero or the explicit value
+fn resolve_enum_declaration(
+    enum_declaration: EnumDeclaration,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<EnumDeclaration, String> {
+    let mut next_value: i64 = 0;
+    let mut enumerators: Vec<Enumerator> = Vec::new();
+    for enumerator in enum_declaration.enumerators {
+        if let Some(value) = enumerator.value {
+            next_value = evaluate_integer_constant(
+                &resolve_expression(value, identifier_map, structures)?,
+                structures,
+            )?;
+        }
+        let value = i32::try_from(next_value)
+            .map_err(|_| format!("Enumerator value doesn't fit in an int: {}", enumerator.name))?;
+        if identifier_map
+            .get(&enumerator.name)
+            .is_some_and(|entry| entry.from_current_scope)
+        {
+            return Err(format!("Conflicting declarations: {}", enumerator.name));
+        }
+        identifier_map.insert(
+            enumerator.name.clone(),
+            IdentifierEntry {
+                unique_name: enumerator.name.clone(),
+                from_current_scope: true,
+                has_linkage: false,
+                constant: Some(Const::Int(value)),
+            },
+        );
+        enumerators.push(Enumerator {
+            name: enumerator.name,
+            value: Some(Expression::new(ExpressionKind::Constant(Const::Int(value)))),
+        });
+        next_value += 1;
+    }
+    Ok(EnumDeclaration {
+        tag: enum_declaration.tag,
+        enumerators,
+    })
+}
+
+fn resolve_type(
+    unresolved_type: Type,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<Type, String> {
+    match unresolved_type {
+        // A tag that wasn't declared yet declares an incomplete type in the current scope
+        Type::Structure(tag) => match identifier_map.get(&struct_key(&tag)) {
+            Some(entry) => Ok(Type::Structure(entry.unique_name.clone())),
+            None => Ok(Type::Structure(declare_struct_tag(tag, identifier_map))),
+        },
+        Type::Pointer(referenced) => Ok(Type::Pointer(Box::new(resolve_type(
+            *referenced,
+            identifier_map,
+            structures,
+        )?))),
+        Type::Array(element_type, size) => Ok(Type::Array(
+            Box::new(resolve_type(*element_type, identifier_map, structures)?),
+            size,
+        )),
+        Type::IncompleteArray(element_type) => Ok(Type::IncompleteArray(Box::new(resolve_type(
+            *element_type,
+            identifier_map,
+            structures,
+        )?))),
+        Type::UnresolvedArray(element_type, size) => {
+            let size = evaluate_integer_constant(
+                &resolve_expression(*size, identifier_map, structures)?,
+                structures,
+            )
+            .map_err(|error| format!("Array size must be an integer constant: {}", error))?;
+            if size <= 0 {
+                return Err("Array size must be positive".to_string());
+            }
+            Ok(Type::Array(
+                Box::new(resolve_type(*element_type, identifier_map, structures)?),
+                size,
+            ))
+        }
+        Type::Qualified(inner, is_const, is_volatile) => Ok(Type::Qualified(
+            Box::new(resolve_type(*inner, identifier_map, structures)?),
+            is_const,
+            is_volatile,
+        )),
+        Type::Function(param_types, return_type, variadic) => {
+            let mut new_param_types: Vec<Type> = Vec::new();
+            for param_type in param_types {
+                new_param_types.push(resolve_type(param_type, identifier_map, structures)?);
+            }
+            Ok(Type::Function(
+                new_param_types,
+                Box::new(resolve_type(*return_type, identifier_map, structures)?),
+                variadic,
+            ))
+        }
+        other => Ok(other),
+    }
+}
+
+fn resolve_block(
+    block: Block,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<Block, String> {
     let mut body: Vec<BlockItem> = Vec::new();
 
     for block_item in block {
         body.push(match block_item {
-            BlockItem::D(declaration) => {
-                BlockItem::D(resolve_declaration(declaration, identifier_map)?)
+            BlockItem::D(declaration) => BlockItem::D(resolve_declaration(
+                declaration,
+                identifier_map,
+                structures,
+            )?),
+            BlockItem::S(statement) => {
+                BlockItem::S(resolve_statement(statement, identifier_map, structures)?)
             }
-            BlockItem::S(statement) => BlockItem::S(resolve_statement(statement, identifier_map)?),
         })
     }
 
@@ -64,15 +262,19 @@ fn resolve_block(block: Block, identifier_map: &mut IdentifierMap) -> Result<Blo
 
 fn resolve_param_declaration(
     param: String,
+    param_type: Type,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<String, String> {
     match resolve_local_variable_declaration(
         VariableDeclaration {
             name: param,
             init: None,
+            var_type: param_type,
             storage_class: None,
         },
         identifier_map,
+        structures,
     ) {
         Ok(decl) => Ok(decl.name),
         Err(err) => Err(err),
@@ -82,6 +284,7 @@ fn resolve_param_declaration(
 fn resolve_local_variable_declaration(
     var_declaration: VariableDeclaration,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<VariableDeclaration, String> {
     if identifier_map.contains_key(&var_declaration.name) {
         let prev_entry = identifier_map.get(&var_declaration.name).unwrap();
@@ -100,9 +303,13 @@ fn resolve_local_variable_declaration(
                 unique_name: var_declaration.name.clone(),
                 from_current_scope: true,
                 has_linkage: true,
+                constant: None,
             },
         );
-        Ok(var_declaration)
+        Ok(VariableDeclaration {
+            var_type: resolve_type(var_declaration.var_type, identifier_map, structures)?,
+            ..var_declaration
+        })
     } else {
         let unique_name = make_unique_name(var_declaration.name.clone());
         identifier_map.insert(
@@ -111,22 +318,54 @@ fn resolve_local_variable_declaration(
                 unique_name: unique_name.clone(),
                 from_current_scope: true,
                 has_linkage: false,
+                constant: None,
             },
         );
         Ok(VariableDeclaration {
             name: unique_name,
             init: match var_declaration.init {
-                Some(expression) => Some(resolve_expression(expression, identifier_map)?),
+                Some(initializer) => Some(resolve_initializer(
+                    initializer,
+                    identifier_map,
+                    structures,
+                )?),
                 None => None,
             },
+            var_type: resolve_type(var_declaration.var_type, identifier_map, structures)?,
             storage_class: var_declaration.storage_class,
         })
     }
 }
 
+fn resolve_initializer(
+    initializer: Initializer,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<Initializer, String> {
+    match initializer {
+        Initializer::Single(expression) => Ok(Initializer::Single(resolve_expression(
+            expression,
+            identifier_map,
+            structures,
+        )?)),
+        Initializer::Compound(initializers, init_type) => {
+            let mut new_initializers: Vec<Initializer> = Vec::new();
+            for initializer in initializers {
+                new_initializers.push(resolve_initializer(
+                    initializer,
+                    identifier_map,
+                    structures,
+                )?);
+            }
+            Ok(Initializer::Compound(new_initializers, init_type))
+        }
+    }
+}
+
 fn resolve_function_declaration(
     function_declaration: FunctionDeclaration,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<FunctionDeclaration, String> {
     if identifier_map.contains_key(&function_declaration.name) {
         let prev_entry = identifier_map.get(&function_declaration.name).unwrap();
@@ -141,17 +380,45 @@ fn resolve_function_declaration(
             unique_name: function_declaration.name.clone(),
             from_current_scope: true,
             has_linkage: true,
+            constant: None,
         },
     );
 
+    // Parameter types are resolved along with the parameters themselves
+    let param_types = match &function_declaration.fun_type {
+        Type::Function(param_types, ..) => param_types.clone(),
+        _ => return Err(format!("Not a function type: {}", function_declaration.name)),
+    };
+    let fun_type = resolve_type(function_declaration.fun_type, identifier_map, structures)?;
     let mut inner_map = copy_identifier_map(identifier_map);
+    if let Type::Function(_, _, true) = fun_type {
+        inner_map.insert(
+            VARIADIC_KEY.to_string(),
+            IdentifierEntry {
+                unique_name: VARIADIC_KEY.to_string(),
+                from_current_scope: true,
+                has_linkage: false,
+                constant: None,
+            },
+        );
+    }
     let mut new_params: Vec<String> = Vec::new();
-    for param in function_declaration.params {
-        new_params.push(resolve_param_declaration(param, identifier_map)?);
+    for (param, param_type) in function_declaration.params.into_iter().zip(param_types) {
+        // An unnamed parameter of a declaration has nothing to resolve
+        if param.is_empty() {
+            new_params.push(param);
+            continue;
+        }
+        new_params.push(resolve_param_declaration(
+            param,
+            param_type,
+            &mut inner_map,
+            structures,
+        )?);
     }
 
     let new_body = match function_declaration.body {
-        Some(body) => Some(resolve_block(body, &mut inner_map)?),
+        Some(body) => Some(resolve_block(body, &mut inner_map, structures)?),
         None => None,
     };
 
@@ -159,6 +426,7 @@ fn resolve_function_declaration(
         name: function_declaration.name,
         params: new_params,
         body: new_body,
+        fun_type,
         storage_class: function_declaration.storage_class,
     })
 }
@@ -166,13 +434,14 @@ fn resolve_function_declaration(
 fn resolve_declaration(
     declaration: Declaration,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<Declaration, String> {
     match declaration {
         Declaration::VarDecl(var_declaration) => Ok(Declaration::VarDecl(
-            resolve_local_variable_declaration(var_declaration, identifier_map)?,
+            resolve_local_variable_declaration(var_declaration, identifier_map, structures)?,
         )),
         Declaration::FuncDecl(function_declaration) => {
-            if let Some(_) = function_declaration.body {
+            if function_declaration.body.is_some() {
                 return Err(format!(
                     "Local function declaration can't have a body: {}",
                     function_declaration.name
@@ -187,84 +456,180 @@ fn resolve_declaration(
             Ok(Declaration::FuncDecl(resolve_function_declaration(
                 function_declaration,
                 identifier_map,
+                structures,
             )?))
         }
+        Declaration::StructDecl(struct_declaration) => Ok(Declaration::StructDecl(
+            resolve_struct_declaration(struct_declaration, identifier_map, structures)?,
+        )),
+        Declaration::EnumDecl(enum_declaration) => Ok(Declaration::EnumDecl(
+            resolve_enum_declaration(enum_declaration, identifier_map, structures)?,
+        )),
     }
 }
 
 fn resolve_expression(
     expression: Expression,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<Expression, String> {
-    match expression {
-        Expression::Assignment(op, left, right) => {
-            match *left {
-                Expression::Var(_) => {}
-                _ => return Err("Invalid lvalue".to_string()),
-            };
-            Ok(Expression::Assignment(
+    let kind = match expression.kind {
+        // Checked after resolving, an enumerator isn't an lvalue
+        ExpressionKind::Assignment(op, left, right) => {
+            let left = resolve_expression(*left, identifier_map, structures)?;
+            if !is_lvalue(&left) {
+                return Err("Invalid lvalue".to_string());
+            }
+            ExpressionKind::Assignment(
                 op,
-                Box::new(resolve_expression(*left, identifier_map)?),
-                Box::new(resolve_expression(*right, identifier_map)?),
-            ))
+                Box::new(left),
+                Box::new(resolve_expression(*right, identifier_map, structures)?),
+            )
         }
-        Expression::Var(name) => {
-            if identifier_map.contains_key(&name) {
-                Ok(Expression::Var(
-                    identifier_map.get(&name).unwrap().unique_name.clone(),
-                ))
-            } else {
-                Err("Undeclared variable".to_string())
+        ExpressionKind::Var(name) => match identifier_map.get(&name) {
+            Some(IdentifierEntry {
+                constant: Some(constant),
+                ..
+            }) => ExpressionKind::Constant(*constant),
+            Some(entry) => ExpressionKind::Var(entry.unique_name.clone()),
+            None => return Err("Undeclared variable".to_string()),
+        },
+        ExpressionKind::Cast(target_type, exp) => ExpressionKind::Cast(
+            resolve_type(target_type, identifier_map, structures)?,
+            Box::new(resolve_expression(*exp, identifier_map, structures)?),
+        ),
+        ExpressionKind::VaStart(va_list) => {
+            if !identifier_map.contains_key(VARIADIC_KEY) {
+                return Err("va_start used outside of a variadic function".to_string());
             }
+            ExpressionKind::VaStart(Box::new(resolve_expression(
+                *va_list,
+                identifier_map,
+                structures,
+            )?))
+        }
+        ExpressionKind::VaArg(va_list, arg_type) => ExpressionKind::VaArg(
+            Box::new(resolve_expression(*va_list, identifier_map, structures)?),
+            resolve_type(arg_type, identifier_map, structures)?,
+        ),
+        ExpressionKind::VaEnd(va_list) => ExpressionKind::VaEnd(Box::new(resolve_expression(
+            *va_list,
+            identifier_map,
+            structures,
+        )?)),
+        ExpressionKind::VaCopy(destination, source) => ExpressionKind::VaCopy(
+            Box::new(resolve_expression(
+                *destination,
+                identifier_map,
+                structures,
+            )?),
+            Box::new(resolve_expression(*source, identifier_map, structures)?),
+        ),
+        ExpressionKind::SizeOf(exp) => ExpressionKind::SizeOf(Box::new(resolve_expression(
+            *exp,
+            identifier_map,
+            structures,
+        )?)),
+        ExpressionKind::SizeOfType(target_type) => {
+            ExpressionKind::SizeOfType(resolve_type(target_type, identifier_map, structures)?)
         }
-        Expression::Binary(op, left, right) => Ok(Expression::Binary(
+        ExpressionKind::Binary(op, left, right) => ExpressionKind::Binary(
             op,
-            Box::new(resolve_expression(*left, identifier_map)?),
-            Box::new(resolve_expression(*right, identifier_map)?),
-        )),
-        Expression::Unary(op, exp) => Ok(Expression::Unary(
+            Box::new(resolve_expression(*left, identifier_map, structures)?),
+            Box::new(resolve_expression(*right, identifier_map, structures)?),
+        ),
+        ExpressionKind::Unary(op, exp) => ExpressionKind::Unary(
             op,
-            Box::new(resolve_expression(*exp, identifier_map)?),
-        )),
-        Expression::Constant(imm) => Ok(Expression::Constant(imm)),
-        Expression::Conditional(left, middle, right) => Ok(Expression::Conditional(
-            Box::new(resolve_expression(*left, identifier_map)?),
-            Box::new(resolve_expression(*middle, identifier_map)?),
-            Box::new(resolve_expression(*right, identifier_map)?),
+            Box::new(resolve_expression(*exp, identifier_map, structures)?),
+        ),
+        ExpressionKind::Constant(constant) => ExpressionKind::Constant(constant),
+        ExpressionKind::String(bytes) => ExpressionKind::String(bytes),
+        ExpressionKind::Dereference(exp) => ExpressionKind::Dereference(Box::new(
+            resolve_expression(*exp, identifier_map, structures)?,
         )),
-        Expression::FunctionCall(name, args) => {
-            if identifier_map.contains_key(&name) {
-                let unique_name = identifier_map.get(&name).unwrap().unique_name.clone();
-                let mut new_args: Vec<Expression> = Vec::new();
-                for arg in args {
-                    new_args.push(resolve_expression(arg, identifier_map)?);
-                }
-                Ok(Expression::FunctionCall(unique_name, new_args))
-            } else {
-                Err(format!("Undeclared function: {}", name))
+        ExpressionKind::Subscript(array, index) => ExpressionKind::Subscript(
+            Box::new(resolve_expression(*array, identifier_map, structures)?),
+            Box::new(resolve_expression(*index, identifier_map, structures)?),
+        ),
+        ExpressionKind::Dot(structure, member) => ExpressionKind::Dot(
+            Box::new(resolve_expression(*structure, identifier_map, structures)?),
+            member,
+        ),
+        ExpressionKind::Arrow(pointer, member) => ExpressionKind::Arrow(
+            Box::new(resolve_expression(*pointer, identifier_map, structures)?),
+            member,
+        ),
+        ExpressionKind::AddressOf(exp) => {
+            let exp = resolve_expression(*exp, identifier_map, structures)?;
+            if !is_lvalue(&exp) {
+                return Err("Can't take the address of a non-lvalue".to_string());
             }
+            ExpressionKind::AddressOf(Box::new(exp))
         }
+        ExpressionKind::Postfix(op, exp) => {
+            let exp = resolve_expression(*exp, identifier_map, structures)?;
+            if !is_lvalue(&exp) {
+                return Err("Invalid lvalue".to_string());
+            }
+            ExpressionKind::Postfix(op, Box::new(exp))
+        }
+        ExpressionKind::Conditional(left, middle, right) => ExpressionKind::Conditional(
+            Box::new(resolve_expression(*left, identifier_map, structures)?),
+            Box::new(resolve_expression(*middle, identifier_map, structures)?),
+            Box::new(resolve_expression(*right, identifier_map, structures)?),
+        ),
+        ExpressionKind::Comma(left, right) => ExpressionKind::Comma(
+            Box::new(resolve_expression(*left, identifier_map, structures)?),
+            Box::new(resolve_expression(*right, identifier_map, structures)?),
+        ),
+        ExpressionKind::FunctionCall(function, args) => {
+            let function = resolve_expression(*function, identifier_map, structures)?;
+            let mut new_args: Vec<Expression> = Vec::new();
+            for arg in args {
+                new_args.push(resolve_expression(arg, identifier_map, structures)?);
+            }
+            ExpressionKind::FunctionCall(Box::new(function), new_args)
+        }
+    };
+    Ok(Expression::new(kind))
+}
+
+fn is_lvalue(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::Var(_)
+        | ExpressionKind::Dereference(_)
+        | ExpressionKind::Subscript(_, _)
+        | ExpressionKind::String(_)
+        | ExpressionKind::Arrow(_, _) => true,
+        ExpressionKind::Dot(structure, _) => is_lvalue(structure),
+        _ => false,
     }
 }
 
 fn resolve_statement(
     statement: Statement,
     identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
 ) -> Result<Statement, String> {
     match statement {
-        Statement::Return(expression) => Ok(Statement::Return(resolve_expression(
-            expression,
-            identifier_map,
-        )?)),
+        Statement::Return(expression) => Ok(Statement::Return(match expression {
+            Some(expression) => Some(resolve_expression(expression, identifier_map, structures)?),
+            None => None,
+        })),
         Statement::Expression(expression) => Ok(Statement::Expression(resolve_expression(
             expression,
             identifier_map,
+            structures,
         )?)),
         Statement::If(cond, if_body, else_body) => Ok(Statement::If(
-            resolve_expression(cond, identifier_map)?,
-            Box::new(resolve_statement(*if_body, identifier_map)?),
+            resolve_expression(cond, identifier_map, structures)?,
+            Box::new(resolve_statement(*if_body, identifier_map, structures)?),
             match else_body {
-                Some(else_body) => Some(Box::new(resolve_statement(*else_body, identifier_map)?)),
+                Some(else_body) => Some(Box::new(resolve_statement(
+                    *else_body,
+                    identifier_map,
+                    structures,
+                )?)),
                 None => None,
             },
         )),
@@ -274,68 +639,99 @@ fn resolve_statement(
             Ok(Statement::Compound(resolve_block(
                 block,
                 &mut new_scope_variables,
+                structures,
             )?))
         }
         Statement::Break(label) => Ok(Statement::Break(label)),
         Statement::Continue(label) => Ok(Statement::Continue(label)),
+        Statement::Goto(label) => Ok(Statement::Goto(label)),
+        Statement::Labeled(label, body) => Ok(Statement::Labeled(
+            label,
+            Box::new(resolve_statement(*body, identifier_map, structures)?),
+        )),
         Statement::While(cond, body, label) => Ok(Statement::While(
-            resolve_expression(cond, identifier_map)?,
-            Box::new(resolve_statement(*body, identifier_map)?),
+            resolve_expression(cond, identifier_map, structures)?,
+            Box::new(resolve_statement(*body, identifier_map, structures)?),
             label,
         )),
         Statement::DoWhile(body, cond, label) => Ok(Statement::DoWhile(
-            Box::new(resolve_statement(*body, identifier_map)?),
-            resolve_expression(cond, identifier_map)?,
+            Box::new(resolve_statement(*body, identifier_map, structures)?),
+            resolve_expression(cond, identifier_map, structures)?,
             label,
         )),
         Statement::For(init, cond, post, body, label) => {
             let mut new_scope_variables = copy_identifier_map(identifier_map);
             Ok(Statement::For(
-                resolve_for_init(init, &mut new_scope_variables)?,
+                resolve_for_init(init, &mut new_scope_variables, structures)?,
                 match cond {
-                    Some(cond) => Some(resolve_expression(cond, &mut new_scope_variables)?),
+                    Some(cond) => Some(resolve_expression(
+                        cond,
+                        &mut new_scope_variables,
+                        structures,
+                    )?),
                     None => None,
                 },
                 match post {
-                    Some(post) => Some(resolve_expression(post, &mut new_scope_variables)?),
+                    Some(post) => Some(resolve_expression(
+                        post,
+                        &mut new_scope_variables,
+                        structures,
+                    )?),
                     None => None,
                 },
-                Box::new(resolve_statement(*body, &mut new_scope_variables)?),
+                Box::new(resolve_statement(
+                    *body,
+                    &mut new_scope_variables,
+                    structures,
+                )?),
                 label,
             ))
         }
-        Statement::Switch(cond, cases, default, label) => {
-            let mut resolved_cases: Vec<Case> = Vec::new();
-            for case in cases {
-                resolved_cases.push(Case {
-                    cond: case.cond,
-                    body: resolve_statement(case.body, identifier_map)?,
-                });
-            }
-
-            Ok(Statement::Switch(
-                resolve_expression(cond, identifier_map)?,
-                resolved_cases,
-                match default {
-                    None => None,
-                    Some(body) => Some(Box::new(resolve_statement(*body, identifier_map)?)),
-                },
+        Statement::Switch(cond, body, cases, label) => Ok(Statement::Switch(
+            resolve_expression(cond, identifier_map, structures)?,
+            Box::new(resolve_statement(*body, identifier_map, structures)?),
+            cases,
+            label,
+        )),
+        Statement::Case(value, body, label) => {
+            let value = evaluate_integer_constant(
+                &resolve_expression(value, identifier_map, structures)?,
+                structures,
+            )
+            .map_err(|error| format!("Case label must be an integer constant: {}", error))?;
+            Ok(Statement::Case(
+                Expression::new(ExpressionKind::Constant(Const::Long(value))),
+                Box::new(resolve_statement(*body, identifier_map, structures)?),
                 label,
             ))
         }
+        Statement::Default(body, label) => Ok(Statement::Default(
+            Box::new(resolve_statement(*body, identifier_map, structures)?),
+            label,
+        )),
     }
 }
 
-fn resolve_for_init(init: ForInit, identifier_map: &mut IdentifierMap) -> Result<ForInit, String> {
+fn resolve_for_init(
+    init: ForInit,
+    identifier_map: &mut IdentifierMap,
+    structures: &mut SymbolTable,
+) -> Result<ForInit, String> {
     match init {
-        ForInit::InitDeclaration(declaration) => Ok(ForInit::InitDeclaration(
-            resolve_local_variable_declaration(declaration, identifier_map)?,
+        ForInit::InitDeclaration(declarations) => Ok(ForInit::InitDeclaration(
+            declarations
+                .into_iter()
+                .map(|declaration| {
+                    resolve_local_variable_declaration(declaration, identifier_map, structures)
+                })
+                .collect::<Result<_, _>>()?,
         )),
         ForInit::InitExpression(expression) => {
             if let Some(expression) = expression {
                 Ok(ForInit::InitExpression(Some(resolve_expression(
                     expression,
                     identifier_map,
+                    structures,
                 )?)))
             } else {
                 Ok(ForInit::InitExpression(None))
@@ -353,6 +749,7 @@ fn copy_identifier_map(identifier_map: &IdentifierMap) -> IdentifierMap {
                 unique_name: value.unique_name.clone(),
                 from_current_scope: false,
                 has_linkage: value.has_linkage,
+                constant: value.constant,
             },
         );
     }
@@ -1,10 +1,10 @@
-use lazy_static;
 use regex::Regex;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Keyword {
     Void,
     Int,
+    Long,
     Return,
     If,
     Else,
@@ -17,21 +17,48 @@ pub enum Keyword {
     Default,
     Case,
     Static,
-    Extern
+    Extern,
+    Signed,
+    Unsigned,
+    Double,
+    Char,
+    Struct,
+    Union,
+    Enum,
+    Typedef,
+    Goto,
+    Sizeof,
+    Const,
+    Volatile,
+    VaStart,
+    VaArg,
+    VaEnd,
+    VaCopy,
 }
 
 #[derive(Debug)]
 pub enum Token {
     Identifier(String),
     Keyword(Keyword),
-    Constant(i64),
+    Constant(u64),
+    LongConstant(u64),
+    UnsignedConstant(u64),
+    UnsignedLongConstant(u64),
+    DoubleConstant(f64),
+    CharConstant(i32),
+    StringLiteral(Vec<u8>),
     OpenParenthesis,
     CloseParenthesis,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
     Semicolon,
     Colon,
     Comma,
+    Dot,
+    Arrow,
+    Ellipsis,
     Operator(Operator),
 }
 
@@ -39,7 +66,8 @@ pub enum Token {
 pub enum Operator {
     Minus,
     Complement,
-    Decrement,
+    Decrement, // prefix or postfix is decided by the parser
+    Increment,
     Plus,
     Multiply,
     Divide,
@@ -70,12 +98,7 @@ pub enum Operator {
     LeftShiftAssign,
     RightShiftAssign,
     TernaryIf,
-    TernaryElse
-    // Discarded because lookahead is not supported - will be added when I make my own state machine to parse tokens
-    // PrefixIncrement,
-    // PrefixDecrement,
-    // PostfixIncrement,
-    // PostfixDecrement,
+    TernaryElse,
 }
 
 impl PartialEq for Token {
@@ -84,13 +107,24 @@ impl PartialEq for Token {
             (Token::Keyword(k1), Token::Keyword(k2)) => k1 == k2,
             (Token::Identifier(_), Token::Identifier(_)) => true,
             (Token::Constant(_), Token::Constant(_)) => true,
+            (Token::LongConstant(_), Token::LongConstant(_)) => true,
+            (Token::UnsignedConstant(_), Token::UnsignedConstant(_)) => true,
+            (Token::UnsignedLongConstant(_), Token::UnsignedLongConstant(_)) => true,
+            (Token::DoubleConstant(_), Token::DoubleConstant(_)) => true,
+            (Token::CharConstant(_), Token::CharConstant(_)) => true,
+            (Token::StringLiteral(_), Token::StringLiteral(_)) => true,
             (Token::OpenParenthesis, Token::OpenParenthesis) => true,
             (Token::CloseParenthesis, Token::CloseParenthesis) => true,
             (Token::OpenBrace, Token::OpenBrace) => true,
             (Token::CloseBrace, Token::CloseBrace) => true,
+            (Token::OpenBracket, Token::OpenBracket) => true,
+            (Token::CloseBracket, Token::CloseBracket) => true,
             (Token::Semicolon, Token::Semicolon) => true,
             (Token::Colon, Token::Colon) => true,
             (Token::Comma, Token::Comma) => true,
+            (Token::Dot, Token::Dot) => true,
+            (Token::Arrow, Token::Arrow) => true,
+            (Token::Ellipsis, Token::Ellipsis) => true,
             (Token::Operator(o1), Token::Operator(o2)) => o1 == o2,
             _ => false,
         }
@@ -99,297 +133,462 @@ impl PartialEq for Token {
 
 struct TokenPattern {
     regex: Regex,
-    token_type: fn(&str) -> Token,
+    token_type: fn(&str) -> Result<Token, String>,
 }
 
 lazy_static::lazy_static! {
     static ref TOKEN_PATTERNS: Vec<TokenPattern> = vec![
         TokenPattern {
-            regex: Regex::new(r"\bint\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Int),
+            regex: Regex::new(r"^\bint\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Int)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\blong\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Long)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bsigned\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Signed)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bunsigned\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Unsigned)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bdouble\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Double)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bchar\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Char)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bstruct\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Struct)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bunion\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Union)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\benum\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Enum)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\btypedef\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Typedef)),
         },
         TokenPattern {
-            regex: Regex::new(r"\breturn\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Return),
+            regex: Regex::new(r"^\bgoto\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Goto)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bsizeof\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Sizeof)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bconst\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Const)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bvolatile\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Volatile)),
+        },
+        // The variable argument macros are builtins, with or without the compiler prefix
+        TokenPattern {
+            regex: Regex::new(r"^\b(__builtin_)?va_start\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::VaStart)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\b(__builtin_)?va_arg\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::VaArg)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\b(__builtin_)?va_end\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::VaEnd)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\b(__builtin_)?va_copy\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::VaCopy)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\breturn\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Return)),
         },
         TokenPattern{
-            regex: Regex::new(r"\bvoid\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Void),
+            regex: Regex::new(r"^\bvoid\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Void)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\bif\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::If)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bif\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::If),
+            regex: Regex::new(r"^\belse\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Else)),
         },
         TokenPattern {
-            regex: Regex::new(r"\belse\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Else),
+            regex: Regex::new(r"^\bdo\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Do)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bdo\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Do),
+            regex: Regex::new(r"^\bwhile\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::While)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bwhile\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::While),
+            regex: Regex::new(r"^\bfor\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::For)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bfor\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::For),
+            regex: Regex::new(r"^\bbreak\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Break)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bbreak\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Break),
+            regex: Regex::new(r"^\bcontinue\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Continue)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bcontinue\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Continue),
+            regex: Regex::new(r"^\bswitch\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Switch)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bswitch\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Switch),
+            regex: Regex::new(r"^\bdefault\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Default)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bdefault\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Default),
+            regex: Regex::new(r"^\bcase\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Case)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bcase\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Case),
+            regex: Regex::new(r"^\bstatic\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Static)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bstatic\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Static),
+            regex: Regex::new(r"^\bextern\b").unwrap(),
+            token_type: |_| Ok(Token::Keyword(Keyword::Extern)),
         },
         TokenPattern {
-            regex: Regex::new(r"\bextern\b").unwrap(),
-            token_type: |_| Token::Keyword(Keyword::Extern),
+            regex: Regex::new(r"^[a-zA-Z_]\w*\b").unwrap(),
+            token_type: |lexeme| Ok(Token::Identifier(lexeme.to_string())),
         },
         TokenPattern {
-            regex: Regex::new(r"[a-zA-Z_]\w*\b").unwrap(),
-            token_type: |lexeme| Token::Identifier(lexeme.to_string()),
+            regex: Regex::new(r"^[0-9]+\b").unwrap(),
+            token_type: |lexeme| Ok(Token::Constant(parse_integer(lexeme)?)),
         },
         TokenPattern {
-            regex: Regex::new(r"[0-9]+\b").unwrap(),
-            token_type: |lexeme| Token::Constant(lexeme.parse().unwrap()),
+            regex: Regex::new(r"^[0-9]+[lL]\b").unwrap(),
+            token_type: |lexeme| {
+                Ok(Token::LongConstant(parse_integer(&lexeme[..lexeme.len() - 1])?))
+            },
         },
         TokenPattern {
-            regex: Regex::new(r"\(").unwrap(),
-            token_type: |_| Token::OpenParenthesis,
+            regex: Regex::new(r"^[0-9]+[uU]\b").unwrap(),
+            token_type: |lexeme| {
+                Ok(Token::UnsignedConstant(parse_integer(&lexeme[..lexeme.len() - 1])?))
+            },
         },
         TokenPattern {
-            regex: Regex::new(r"\)").unwrap(),
-            token_type: |_| Token::CloseParenthesis,
+            regex: Regex::new(r"^[0-9]+([lL][uU]|[uU][lL])\b").unwrap(),
+            token_type: |lexeme| {
+                Ok(Token::UnsignedLongConstant(parse_integer(&lexeme[..lexeme.len() - 2])?))
+            },
         },
         TokenPattern {
-            regex: Regex::new(r"\{").unwrap(),
-            token_type: |_| Token::OpenBrace,
+            regex: Regex::new(r"^(([0-9]*\.[0-9]+|[0-9]+\.?)[Ee][+-]?[0-9]+|[0-9]*\.[0-9]+|[0-9]+\.)")
+                .unwrap(),
+            token_type: |lexeme| Ok(Token::DoubleConstant(lexeme.parse().unwrap())),
         },
+        // Character constants have type int, their byte is sign-extended since char is signed
         TokenPattern {
-            regex: Regex::new(r"\}").unwrap(),
-            token_type: |_| Token::CloseBrace,
+            regex: Regex::new(r"^'([^'\\\n]|\\[^x0-7\n]|\\x[0-9a-fA-F]+|\\[0-7]{1,3})'").unwrap(),
+            token_type: |lexeme| {
+                Ok(Token::CharConstant(unescape(&lexeme[1..lexeme.len() - 1])[0] as i8 as i32))
+            },
         },
         TokenPattern {
-            regex: Regex::new(r";").unwrap(),
-            token_type: |_| Token::Semicolon,
+            regex: Regex::new(r#"^"([^"\\\n]|\\[^\n])*""#).unwrap(),
+            token_type: |lexeme| Ok(Token::StringLiteral(unescape(&lexeme[1..lexeme.len() - 1]))),
         },
         TokenPattern {
-            regex: Regex::new(r":").unwrap(),
-            token_type: |_| Token::Colon,
+            regex: Regex::new(r"^\(").unwrap(),
+            token_type: |_| Ok(Token::OpenParenthesis),
         },
         TokenPattern {
-            regex: Regex::new(r"-").unwrap(),
-            token_type: |_| Token::Operator(Operator::Minus),
+            regex: Regex::new(r"^\)").unwrap(),
+            token_type: |_| Ok(Token::CloseParenthesis),
         },
         TokenPattern {
-            regex: Regex::new(r"~").unwrap(),
-            token_type: |_| Token::Operator(Operator::Complement),
+            regex: Regex::new(r"^\{").unwrap(),
+            token_type: |_| Ok(Token::OpenBrace),
         },
         TokenPattern {
-            regex: Regex::new(r"--").unwrap(),
-            token_type: |_| Token::Operator(Operator::Decrement),
+            regex: Regex::new(r"^\}").unwrap(),
+            token_type: |_| Ok(Token::CloseBrace),
         },
         TokenPattern {
-            regex: Regex::new(r"\+").unwrap(),
-            token_type: |_| Token::Operator(Operator::Plus),
+            regex: Regex::new(r"^\[").unwrap(),
+            token_type: |_| Ok(Token::OpenBracket),
         },
         TokenPattern {
-            regex: Regex::new(r"\*").unwrap(),
-            token_type: |_| Token::Operator(Operator::Multiply),
+            regex: Regex::new(r"^\]").unwrap(),
+            token_type: |_| Ok(Token::CloseBracket),
         },
         TokenPattern {
-            regex: Regex::new(r"/").unwrap(),
-            token_type: |_| Token::Operator(Operator::Divide),
+            regex: Regex::new(r"^;").unwrap(),
+            token_type: |_| Ok(Token::Semicolon),
         },
         TokenPattern {
-            regex: Regex::new(r"%").unwrap(),
-            token_type: |_| Token::Operator(Operator::Modulo),
+            regex: Regex::new(r"^:").unwrap(),
+            token_type: |_| Ok(Token::Colon),
         },
         TokenPattern {
-            regex: Regex::new(r"&").unwrap(),
-            token_type: |_| Token::Operator(Operator::And),
+            regex: Regex::new(r"^-").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Minus)),
         },
         TokenPattern {
-            regex: Regex::new(r"\|").unwrap(),
-            token_type: |_| Token::Operator(Operator::Or),
+            regex: Regex::new(r"^~").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Complement)),
         },
         TokenPattern {
-            regex: Regex::new(r"\^").unwrap(),
-            token_type: |_| Token::Operator(Operator::Xor),
+            regex: Regex::new(r"^--").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Decrement)),
         },
         TokenPattern {
-            regex: Regex::new(r"<<").unwrap(),
-            token_type: |_| Token::Operator(Operator::ShiftLeft),
+            regex: Regex::new(r"^\+\+").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Increment)),
         },
         TokenPattern {
-            regex: Regex::new(r">>").unwrap(),
-            token_type: |_| Token::Operator(Operator::ShiftRight),
+            regex: Regex::new(r"^\+").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Plus)),
         },
         TokenPattern {
-            regex: Regex::new(r"!").unwrap(),
-            token_type: |_| Token::Operator(Operator::Not)
+            regex: Regex::new(r"^\*").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Multiply)),
         },
         TokenPattern {
-            regex: Regex::new(r"&&").unwrap(),
-            token_type: |_| Token::Operator(Operator::LAnd),
+            regex: Regex::new(r"^/").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Divide)),
         },
         TokenPattern {
-            regex: Regex::new(r"\|\|").unwrap(),
-            token_type: |_| Token::Operator(Operator::LOr),
+            regex: Regex::new(r"^%").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Modulo)),
         },
         TokenPattern {
-            regex: Regex::new(r"==").unwrap(),
-            token_type: |_| Token::Operator(Operator::EqualTo),
+            regex: Regex::new(r"^&").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::And)),
         },
         TokenPattern {
-            regex: Regex::new(r"!=").unwrap(),
-            token_type: |_| Token::Operator(Operator::NotEqualTo),
+            regex: Regex::new(r"^\|").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Or)),
         },
         TokenPattern {
-            regex: Regex::new(r"<").unwrap(),
-            token_type: |_| Token::Operator(Operator::LessThan),
+            regex: Regex::new(r"^\^").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Xor)),
         },
         TokenPattern {
-            regex: Regex::new(r">").unwrap(),
-            token_type: |_| Token::Operator(Operator::GreaterThan),
+            regex: Regex::new(r"^<<").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::ShiftLeft)),
         },
         TokenPattern {
-            regex: Regex::new(r"<=").unwrap(),
-            token_type: |_| Token::Operator(Operator::LessOrEqual),
+            regex: Regex::new(r"^>>").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::ShiftRight)),
         },
         TokenPattern {
-            regex: Regex::new(r">=").unwrap(),
-            token_type: |_| Token::Operator(Operator::GreaterOrEqual),
+            regex: Regex::new(r"^!").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Not))
         },
         TokenPattern {
-            regex: Regex::new(r"=").unwrap(),
-            token_type: |_| Token::Operator(Operator::Assign),
+            regex: Regex::new(r"^&&").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::LAnd)),
         },
         TokenPattern {
-            regex: Regex::new(r"\+=").unwrap(),
-            token_type: |_| Token::Operator(Operator::AddAssign),
+            regex: Regex::new(r"^\|\|").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::LOr)),
         },
         TokenPattern {
-            regex: Regex::new(r"-=").unwrap(),
-            token_type: |_| Token::Operator(Operator::SubAssign),
+            regex: Regex::new(r"^==").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::EqualTo)),
         },
         TokenPattern {
-            regex: Regex::new(r"\*=").unwrap(),
-            token_type: |_| Token::Operator(Operator::MulAssign),
+            regex: Regex::new(r"^!=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::NotEqualTo)),
         },
         TokenPattern {
-            regex: Regex::new(r"/=").unwrap(),
-            token_type: |_| Token::Operator(Operator::DivAssign),
+            regex: Regex::new(r"^<").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::LessThan)),
         },
         TokenPattern {
-            regex: Regex::new(r"%=").unwrap(),
-            token_type: |_| Token::Operator(Operator::ModAssign),
+            regex: Regex::new(r"^>").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::GreaterThan)),
         },
         TokenPattern {
-            regex: Regex::new(r"&=").unwrap(),
-            token_type: |_| Token::Operator(Operator::AndAssign),
+            regex: Regex::new(r"^<=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::LessOrEqual)),
         },
         TokenPattern {
-            regex: Regex::new(r"\|=").unwrap(),
-            token_type: |_| Token::Operator(Operator::OrAssign),
+            regex: Regex::new(r"^>=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::GreaterOrEqual)),
         },
         TokenPattern {
-            regex: Regex::new(r"\^=").unwrap(),
-            token_type: |_| Token::Operator(Operator::XorAssign),
+            regex: Regex::new(r"^=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::Assign)),
         },
         TokenPattern {
-            regex: Regex::new(r"<<=").unwrap(),
-            token_type: |_| Token::Operator(Operator::LeftShiftAssign),
+            regex: Regex::new(r"^\+=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::AddAssign)),
         },
         TokenPattern {
-            regex: Regex::new(r">>=").unwrap(),
-            token_type: |_| Token::Operator(Operator::RightShiftAssign),
+            regex: Regex::new(r"^-=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::SubAssign)),
         },
         TokenPattern {
-            regex: Regex::new(",").unwrap(),
-            token_type: |_| Token::Comma,
+            regex: Regex::new(r"^\*=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::MulAssign)),
         },
-        // TokenPattern {
-        //     regex: Regex::new(r"(?<!\w)\+\+").unwrap(),
-        //     token_type: |_| Token::Operator(Operator::PrefixIncrement),
-        // },
-        // TokenPattern {
-        //     regex: Regex::new(r"(?<!\w)--").unwrap(),
-        //     token_type: |_| Token::Operator(Operator::PrefixDecrement),
-        // },
-        // TokenPattern {
-        //     regex: Regex::new(r"(?<=\w)\+\+(?!\w)").unwrap(),
-        //     token_type: |_| Token::Operator(Operator::PostfixIncrement),
-        // },
-        // TokenPattern {
-        //     regex: Regex::new(r"(?<=\w)--(?!\w)").unwrap(),
-        //     token_type: |_| Token::Operator(Operator::PostfixDecrement),
-        // },
         TokenPattern {
-            regex: Regex::new(r"\?").unwrap(),
-            token_type: |_| Token::Operator(Operator::TernaryIf),
+            regex: Regex::new(r"^/=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::DivAssign)),
         },
         TokenPattern {
-            regex: Regex::new(r":").unwrap(),
-            token_type: |_| Token::Operator(Operator::TernaryElse),
+            regex: Regex::new(r"^%=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::ModAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^&=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::AndAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\|=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::OrAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\^=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::XorAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^<<=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::LeftShiftAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^>>=").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::RightShiftAssign)),
+        },
+        TokenPattern {
+            regex: Regex::new("^,").unwrap(),
+            token_type: |_| Ok(Token::Comma),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\.").unwrap(),
+            token_type: |_| Ok(Token::Dot),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\.\.\.").unwrap(),
+            token_type: |_| Ok(Token::Ellipsis),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^->").unwrap(),
+            token_type: |_| Ok(Token::Arrow),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^\?").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::TernaryIf)),
+        },
+        TokenPattern {
+            regex: Regex::new(r"^:").unwrap(),
+            token_type: |_| Ok(Token::Operator(Operator::TernaryElse)),
         },
     ];
 }
 
-fn match_token(input: &str) -> Option<(Token, usize)> {
-    let mut longest_match: Option<(Token, usize)> = None;
+pub fn unescape(lexeme: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chars = lexeme.bytes().peekable();
+    while let Some(c) = chars.next() {
+        if c != b'\\' {
+            bytes.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'r') => b'\r',
+            Some(b'a') => 0x07,
+            Some(b'b') => 0x08,
+            Some(b'f') => 0x0c,
+            Some(b'v') => 0x0b,
+            Some(b'x') => {
+                let mut value: u32 = 0;
+                while let Some(digit) = chars.peek().and_then(|&c| (c as char).to_digit(16)) {
+                    value = value * 16 + digit;
+                    chars.next();
+                }
+                value as u8
+            }
+            Some(digit @ b'0'..=b'7') => {
+                let mut value = (digit - b'0') as u32;
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(&digit @ b'0'..=b'7') => {
+                            value = value * 8 + (digit - b'0') as u32;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                value as u8
+            }
+            // \\, \', \" and \? stand for themselves
+            Some(c) => c,
+            None => panic!("Unterminated escape sequence in: {}", lexeme),
+        };
+        bytes.push(escaped);
+    }
+    bytes
+}
+
+fn match_token(input: &str) -> Result<Option<(Token, usize)>, String> {
+    // Only build the winning token, a shorter prefix might not be valid on its own
+    let mut longest_match: Option<(&TokenPattern, usize)> = None;
 
     for pattern in TOKEN_PATTERNS.iter() {
+        // The patterns are anchored, scanning the rest of the input would make lexing quadratic
         if let Some(mat) = pattern.regex.find(input) {
-            if mat.start() == 0 {
-                let length = mat.len();
-                let token = (pattern.token_type)(&input[..length]);
-                match &longest_match {
-                    Some((_, longest_length)) => {
-                        if length > *longest_length {
-                            longest_match = Some((token, length))
-                        }
+            let length = mat.len();
+            match &longest_match {
+                Some((_, longest_length)) => {
+                    if length > *longest_length {
+                        longest_match = Some((pattern, length))
                     }
-                    None => longest_match = Some((token, length)),
                 }
+                None => longest_match = Some((pattern, length)),
             }
         }
     }
 
-    longest_match
+    match longest_match {
+        Some((pattern, length)) => Ok(Some(((pattern.token_type)(&input[..length])?, length))),
+        None => Ok(None),
+    }
+}
+
+fn parse_integer(digits: &str) -> Result<u64, String> {
+    digits
+        .parse()
+        .map_err(|_| format!("Integer constant is too large: {}", digits))
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut remaining = input;
 
     while !remaining.is_empty() {
-        if let Some((token, length)) = match_token(remaining) {
+        if let Some((token, length)) = match_token(remaining)? {
             tokens.push(token);
-            remaining = (&remaining[length..]).trim_start();
+            remaining = remaining[length..].trim_start();
         } else {
-            panic!("Unexpected token at: {}", remaining);
+            return Err(format!("Unexpected token at: {}", remaining));
         }
     }
 
-    tokens
+    Ok(tokens)
 }
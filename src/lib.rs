@@ -1,12 +1,13 @@
-use std::fs;
 use std::io;
 
 mod assembler;
+mod constant_evaluator;
 mod gcc;
 mod generator;
 mod identifier_resolver;
 mod lexer;
 mod parser;
+mod preprocessor;
 mod semantic_analyzer;
 mod tacker;
 mod type_checker;
@@ -14,6 +15,8 @@ mod type_checker;
 pub fn run(
     executable_file: &str,
     source_files: Vec<String>,
+    include_dirs: &[String],
+    macro_options: &[String],
     stop_at: Option<&String>,
     no_main: bool,
 ) -> io::Result<()> {
@@ -24,13 +27,11 @@ pub fn run(
             None => source_file,
         };
 
-        let input = fs::read_to_string(source_file)?;
-
-        // let preprocessed_file = format!("{}.i", base_name);
         let assembly_file = format!("{}.s", base_name);
 
-        let tokens = lexer::tokenize(&input);
-        // gcc::preprocess(input_file, &preprocessed_file)?;
+        let input = preprocessor::preprocess(source_file, include_dirs, macro_options)
+            .map_err(io::Error::other)?;
+        let tokens = lexer::tokenize(&input).map_err(io::Error::other)?;
         for token in &tokens {
             println!("{:?}", token);
         }
@@ -48,20 +49,20 @@ pub fn run(
 
         let program = identifier_resolver::resolve_identifiers(program).unwrap();
         println!("{:#?}", program);
-        let program = semantic_analyzer::analyze_semantics(program).unwrap();
-        let symbol_table = type_checker::check_types(&program).unwrap();
+        let mut program = semantic_analyzer::analyze_semantics(program).unwrap();
+        let mut symbol_table = type_checker::check_types(&mut program).unwrap();
         println!("{:#?}", symbol_table);
         if stop_at == Some(&"--validate".to_string()) {
             continue;
         }
 
-        let tacky = tacker::generate_tacky(program);
+        let tacky = tacker::generate_tacky(program, &mut symbol_table);
         println!("{:#?}", tacky);
         if stop_at == Some(&"--tacky".to_string()) {
             continue;
         }
 
-        let assembly = assembler::assemble(tacky);
+        let assembly = assembler::assemble(tacky, &symbol_table);
         println!("{:#?}", assembly);
 
         if stop_at == Some(&"--codegen".to_string()) {
@@ -75,6 +76,6 @@ pub fn run(
     if stop_at.is_some() {
         Ok(())
     } else {
-        gcc::compile_executable(&assembly_files, &executable_file, no_main)
+        gcc::compile_executable(&assembly_files, executable_file, no_main)
     }
 }
@@ -1,21 +1,37 @@
-use compiler;
 use std::env;
-use std::io;
 
-fn main() -> io::Result<()> {
+fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 3 {
-        eprintln!("Usage: program <output_executable> <source_files...> [--lex|--parse|--validate|--tacky|--codegen] [-c]");
+        eprintln!("Usage: program <output_executable> <source_files...> [-I <dir>] [-D <name>[=<value>]] [-U <name>] [--lex|--parse|--validate|--tacky|--codegen] [-c]");
         std::process::exit(1);
     }
 
     let executable_file = &args[1];
-    let source_files: Vec<String> = args[2..args.len()]
-        .iter()
-        .filter(|&arg| !arg.starts_with("-"))
-        .cloned()
-        .collect();
+    let mut source_files: Vec<String> = Vec::new();
+    let mut include_dirs: Vec<String> = Vec::new();
+    let mut macro_options: Vec<String> = Vec::new();
+    let mut options = args[2..].iter();
+    while let Some(arg) = options.next() {
+        let mut arg = arg.clone();
+        if matches!(arg.as_str(), "-I" | "-D" | "-U") {
+            match options.next() {
+                Some(value) => arg.push_str(value),
+                None => {
+                    eprintln!("Missing argument after {}", arg);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(dir) = arg.strip_prefix("-I") {
+            include_dirs.push(dir.to_string());
+        } else if arg.starts_with("-D") || arg.starts_with("-U") {
+            macro_options.push(arg);
+        } else if !arg.starts_with("-") {
+            source_files.push(arg);
+        }
+    }
 
     let stop_at = args.iter().find(|&arg| {
         matches!(
@@ -25,5 +41,16 @@ fn main() -> io::Result<()> {
     });
     let no_main = args.contains(&"-c".to_string());
 
-    compiler::run(executable_file, source_files, stop_at, no_main)
+    // The diagnostics are printed as they are, a file stack takes several lines
+    if let Err(error) = compiler::run(
+        executable_file,
+        source_files,
+        &include_dirs,
+        &macro_options,
+        stop_at,
+        no_main,
+    ) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
 }
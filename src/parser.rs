@@ -1,6 +1,7 @@
 use lazy_static::lazy_static;
 
 use crate::lexer::{self, Keyword, Token};
+use crate::type_checker::Type;
 use std::collections::HashMap;
 use std::iter::Peekable;
 
@@ -12,20 +13,22 @@ pub struct Program {
 pub type Block = Vec<BlockItem>;
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum BlockItem {
     S(Statement),
     D(Declaration),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum StorageClass {
     Static,
     Extern,
 }
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Statement {
-    Return(Expression),
+    Return(Option<Expression>),
     Expression(Expression),
     Null,
     If(Expression, Box<Statement>, Option<Box<Statement>>), // condition, then, ?else
@@ -41,37 +44,33 @@ pub enum Statement {
         Box<Statement>,
         Option<String>,
     ), // init, condition, post, body, label
-    Switch(
-        Expression,
-        Vec<Case>,
-        Option<Box<Statement>>,
-        Option<String>,
-    ), // value, cases, default?, label
+    Switch(Expression, Box<Statement>, Vec<Case>, Option<String>), // value, body, cases, label
+    Case(Expression, Box<Statement>, Option<String>), // value, body, label
+    Default(Box<Statement>, Option<String>),           // body, label
+    Goto(String),
+    Labeled(String, Box<Statement>),
 }
 
+// The cases of a switch are collected from its body by the semantic analyzer
 #[derive(Debug)]
 pub struct Case {
-    pub cond: i64,
-    pub body: Statement,
+    pub value: Option<i64>, // None for the default case
+    pub label: String,
 }
 
 #[derive(Debug)]
 pub enum ForInit {
-    InitDeclaration(VariableDeclaration),
+    InitDeclaration(Vec<VariableDeclaration>),
     InitExpression(Option<Expression>),
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Declaration {
     FuncDecl(FunctionDeclaration),
     VarDecl(VariableDeclaration),
-}
-
-#[derive(Debug)]
-pub enum Specifier {
-    Int,
-    Static,
-    Extern,
+    StructDecl(StructDeclaration),
+    EnumDecl(EnumDeclaration),
 }
 
 #[derive(Debug)]
@@ -79,35 +78,133 @@ pub struct FunctionDeclaration {
     pub name: String,
     pub params: Vec<String>,
     pub body: Option<Block>,
+    pub fun_type: Type,
     pub storage_class: Option<StorageClass>,
 }
 
 #[derive(Debug)]
 pub struct VariableDeclaration {
     pub name: String,
-    pub init: Option<Expression>,
+    pub init: Option<Initializer>,
+    pub var_type: Type,
     pub storage_class: Option<StorageClass>,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Expression {
+#[derive(Debug)]
+pub struct StructDeclaration {
+    pub tag: String,
+    pub members: Vec<MemberDeclaration>, // empty when the struct is only declared
+    pub is_union: bool,
+}
+
+#[derive(Debug)]
+pub struct MemberDeclaration {
+    pub name: String,
+    pub member_type: Type,
+}
+
+#[derive(Debug)]
+pub struct EnumDeclaration {
+    pub tag: Option<String>,
+    pub enumerators: Vec<Enumerator>,
+}
+
+#[derive(Debug)]
+pub struct Enumerator {
+    pub name: String,
+    pub value: Option<Expression>, // the previous value plus one when missing
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Initializer {
+    Single(Expression),
+    Compound(Vec<Initializer>, Option<Type>), // initializers, type filled in by the type checker
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub exp_type: Option<Type>, // filled in by the type checker
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExpressionKind {
     Var(String),
-    Constant(i64),
+    Constant(Const),
+    Cast(Type, Box<Expression>), // target type, expression
     Unary(UnaryOperator, Box<Expression>),
     Binary(BinaryOperator, Box<Expression>, Box<Expression>),
     Assignment(Option<BinaryOperator>, Box<Expression>, Box<Expression>),
     Conditional(Box<Expression>, Box<Expression>, Box<Expression>), // condition, then, else
-    FunctionCall(String, Vec<Expression>),                          // identifier, args
+    Comma(Box<Expression>, Box<Expression>),
+    FunctionCall(Box<Expression>, Vec<Expression>),                 // function, args
+    Dereference(Box<Expression>),
+    AddressOf(Box<Expression>),
+    Subscript(Box<Expression>, Box<Expression>), // array, index
+    String(Vec<u8>),
+    Dot(Box<Expression>, String),   // structure, member
+    Arrow(Box<Expression>, String), // pointer, member
+    Postfix(BinaryOperator, Box<Expression>), // Add or Subtract, lvalue
+    SizeOf(Box<Expression>),
+    SizeOfType(Type),
+    VaStart(Box<Expression>),                // va_list
+    VaArg(Box<Expression>, Type),            // va_list, argument type
+    VaEnd(Box<Expression>),                  // va_list
+    VaCopy(Box<Expression>, Box<Expression>), // destination, source
 }
 
-#[derive(Debug, PartialEq)]
+impl Expression {
+    pub fn new(kind: ExpressionKind) -> Self {
+        Expression {
+            kind,
+            exp_type: None,
+        }
+    }
+
+    pub fn get_type(&self) -> &Type {
+        self.exp_type
+            .as_ref()
+            .expect("Expression should have been type checked")
+    }
+}
+
+#[derive(Debug)]
+enum Declarator {
+    Identifier(String),
+    Pointer(Box<Declarator>, bool, bool), // declarator, const, volatile
+    Array(Box<Declarator>, Option<Expression>), // element declarator, size unless left out
+    Function(Vec<(Type, Declarator)>, Box<Declarator>, bool), // params, declarator, variadic
+    Unnamed, // a parameter of a declaration can omit its name
+}
+
+// The declarator of a type name in a cast or sizeof, which doesn't declare an identifier
+#[derive(Debug)]
+enum AbstractDeclarator {
+    Pointer(Box<AbstractDeclarator>, bool, bool), // declarator, const, volatile
+    Array(Box<AbstractDeclarator>, Option<Expression>), // element declarator, size unless left out
+    Function(Vec<Type>, Box<AbstractDeclarator>, bool), // params, declarator, variadic
+    Base,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Const {
+    Int(i32),
+    Long(i64),
+    UInt(u32),
+    ULong(u64),
+    Double(f64),
+    Char(i8),
+    UChar(u8),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum UnaryOperator {
     Negate,
     Complement,
     Not,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -140,9 +237,12 @@ pub enum BinaryOperator {
     RightShiftAssign,
     TernaryIf,
     TernaryElse,
+    Comma,
 }
 
-const MAX_PRECEDENCE: u8 = 150;
+const MAX_PRECEDENCE: u8 = 160;
+// Initializers and arguments are separated by commas so they can't be comma expressions
+const ASSIGNMENT_PRECEDENCE: u8 = 150;
 lazy_static! {
     static ref PRECEDENCE_MAP: HashMap<BinaryOperator, u8> = {
         let mut map = HashMap::new();
@@ -177,137 +277,827 @@ lazy_static! {
         map.insert(BinaryOperator::XorAssign, 140);
         map.insert(BinaryOperator::LeftShiftAssign, 140);
         map.insert(BinaryOperator::RightShiftAssign, 140);
+        map.insert(BinaryOperator::Comma, 150);
         map
     };
 }
 
+// Typedef names are looked up while parsing, an ordinary identifier declared in an inner scope
+// hides an outer typedef name
+struct TypeNameEntry {
+    typedef_type: Option<Type>,
+    from_current_scope: bool,
+}
+
+type TypeNameMap = HashMap<String, TypeNameEntry>;
+
+fn copy_type_name_map(type_names: &TypeNameMap) -> TypeNameMap {
+    type_names
+        .iter()
+        .map(|(name, entry)| {
+            (
+                name.clone(),
+                TypeNameEntry {
+                    typedef_type: entry.typedef_type.clone(),
+                    from_current_scope: false,
+                },
+            )
+        })
+        .collect()
+}
+
+fn get_typedef<'a>(name: &str, type_names: &'a TypeNameMap) -> Option<&'a Type> {
+    type_names
+        .get(name)
+        .and_then(|entry| entry.typedef_type.as_ref())
+}
+
+// A typedef can only be repeated with the same type, and not over an ordinary identifier
+fn declare_type_name(
+    name: &str,
+    typedef_type: Option<Type>,
+    type_names: &mut TypeNameMap,
+) -> Result<(), String> {
+    if let Some(entry) = type_names.get(name) {
+        if entry.from_current_scope
+            && (entry.typedef_type.is_some() || typedef_type.is_some())
+            && entry.typedef_type != typedef_type
+        {
+            return Err(format!("Conflicting declarations: {}", name));
+        }
+    }
+    type_names.insert(
+        name.to_string(),
+        TypeNameEntry {
+            typedef_type,
+            from_current_scope: true,
+        },
+    );
+    Ok(())
+}
+
 pub fn parse_program(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
 ) -> Result<Program, String> {
     let mut program = Program {
         declarations: Vec::new(),
     };
+    let mut type_names: TypeNameMap = HashMap::new();
+    for name in ["va_list", "__builtin_va_list"] {
+        declare_type_name(name, Some(Type::va_list()), &mut type_names)?;
+    }
     while tokens.peek().is_some() {
-        program.declarations.push(parse_declaration(tokens)?);
+        program
+            .declarations
+            .extend(parse_declaration(tokens, &mut type_names)?);
     }
     Ok(program)
 }
 
+// Structure, union and enumeration definitions in the specifiers come before the declared names,
+// a function definition can only have a single declarator
 fn parse_declaration(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<Declaration, String> {
-    let mut types: Vec<Token> = Vec::new();
-    let mut storage_classes: Vec<Token> = Vec::new();
+    type_names: &mut TypeNameMap,
+) -> Result<Vec<Declaration>, String> {
+    let mut declarations: Vec<Declaration> = Vec::new();
+    let (dtype, storage_classes) = parse_specifiers(tokens, type_names, &mut declarations)?;
 
-    while let Some(token) = tokens.peek() {
-        match token {
-            Token::Keyword(Keyword::Int) => types.push(tokens.next().unwrap()),
-            Token::Keyword(Keyword::Static | Keyword::Extern) => {
-                storage_classes.push(tokens.next().unwrap())
-            }
-            _ => break,
+    if let Some(Token::Semicolon) = tokens.peek() {
+        tokens.next();
+        if !storage_classes.is_empty() {
+            return Err("Type declarations can't have a storage class".to_string());
         }
+        return Ok(declarations);
     }
 
-    if types.len() != 1 {
-        return Err("Invalid type specifier".to_string());
-    }
     if storage_classes.len() > 1 {
         return Err("Invalid storage class".to_string());
     }
 
-    // let dtype = Specifier::Int;
+    let storage_class = match storage_classes.first() {
+        Some(Token::Keyword(Keyword::Static)) => Some(StorageClass::Static),
+        Some(Token::Keyword(Keyword::Extern)) => Some(StorageClass::Extern),
+        Some(Token::Keyword(Keyword::Typedef)) => {
+            loop {
+                let declarator = parse_declarator(tokens, type_names, false)?;
+                let (identifier, decl_type, _) = process_declarator(declarator, dtype.clone())?;
+                declare_type_name(&identifier, Some(decl_type), type_names)?;
+                match tokens.next() {
+                    Some(Token::Comma) => continue,
+                    Some(Token::Semicolon) => return Ok(declarations),
+                    _ => return Err("Expected a comma or a semicolon".to_string()),
+                }
+            }
+        }
+        Some(_) => panic!("Can't reach here"),
+        None => None,
+    };
 
-    let storage_class = if storage_classes.len() == 1 {
-        match storage_classes[0] {
-            Token::Keyword(Keyword::Static) => Some(StorageClass::Static),
-            Token::Keyword(Keyword::Extern) => Some(StorageClass::Extern),
-            _ => panic!("Can't reach here"),
+    let mut is_first = true;
+    loop {
+        let declarator = parse_declarator(tokens, type_names, false)?;
+        let (identifier, decl_type, params) = process_declarator(declarator, dtype.clone())?;
+        declare_type_name(&identifier, None, type_names)?;
+        if let Type::Function(..) = decl_type {
+            let body = if matches!(tokens.peek(), Some(Token::OpenBrace)) && is_first {
+                let mut inner_type_names = copy_type_name_map(type_names);
+                for param in &params {
+                    if param.is_empty() {
+                        return Err(format!("Parameter of {} must be named", identifier));
+                    }
+                    declare_type_name(param, None, &mut inner_type_names)?;
+                }
+                Some(parse_block(tokens, &inner_type_names)?)
+            } else {
+                None
+            };
+            let is_definition = body.is_some();
+            declarations.push(Declaration::FuncDecl(FunctionDeclaration {
+                name: identifier,
+                body,
+                params,
+                fun_type: decl_type,
+                storage_class: storage_class.clone(),
+            }));
+            if is_definition {
+                return Ok(declarations);
+            }
+        } else {
+            let init = if let Some(Token::Operator(lexer::Operator::Assign)) = tokens.peek() {
+                tokens.next();
+                Some(parse_initializer(tokens, type_names)?)
+            } else {
+                None
+            };
+            declarations.push(Declaration::VarDecl(VariableDeclaration {
+                name: identifier,
+                init,
+                var_type: decl_type,
+                storage_class: storage_class.clone(),
+            }));
         }
-    } else {
-        None
+        match tokens.next() {
+            Some(Token::Comma) => is_first = false,
+            Some(Token::Semicolon) => return Ok(declarations),
+            Some(_) => return Err("Expected a variable or function declaration".to_string()),
+            None => return Err("Expected more tokens".to_string()),
+        }
+    }
+}
+
+fn parse_struct_declaration(
+    tag: String,
+    is_union: bool,
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+    declarations: &mut Vec<Declaration>,
+) -> Result<StructDeclaration, String> {
+    let mut members: Vec<MemberDeclaration> = Vec::new();
+    expect(Token::OpenBrace, tokens)?;
+    while !matches!(tokens.peek(), Some(Token::CloseBrace) | None) {
+        let member_base_type = parse_type_name(tokens, type_names, declarations)?;
+        loop {
+            let (name, member_type, _) = process_declarator(
+                parse_declarator(tokens, type_names, false)?,
+                member_base_type.clone(),
+            )?;
+            if let Type::Function(..) = member_type {
+                return Err(format!("Structure member can't be a function: {}", name));
+            }
+            members.push(MemberDeclaration { name, member_type });
+            match tokens.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::Semicolon) => break,
+                _ => return Err("Expected a comma or a semicolon".to_string()),
+            }
+        }
+    }
+    expect(Token::CloseBrace, tokens)?;
+    if members.is_empty() {
+        return Err(format!("Structure has no members: {}", tag));
+    }
+    Ok(StructDeclaration {
+        tag,
+        members,
+        is_union,
+    })
+}
+
+fn parse_enum_declaration(
+    tag: Option<String>,
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+) -> Result<EnumDeclaration, String> {
+    let mut enumerators: Vec<Enumerator> = Vec::new();
+    expect(Token::OpenBrace, tokens)?;
+    loop {
+        let name = parse_identifier(tokens)?;
+        declare_type_name(&name, None, type_names)?;
+        let value = if let Some(Token::Operator(lexer::Operator::Assign)) = tokens.peek() {
+            tokens.next();
+            Some(parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?)
+        } else {
+            None
+        };
+        enumerators.push(Enumerator { name, value });
+        if let Some(Token::Comma) = tokens.peek() {
+            tokens.next();
+            // A trailing comma is allowed
+            if let Some(Token::CloseBrace) = tokens.peek() {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+    expect(Token::CloseBrace, tokens)?;
+    Ok(EnumDeclaration { tag, enumerators })
+}
+
+// The qualifiers that follow the * of a pointer declarator apply to the pointer itself
+fn parse_qualifiers(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> (bool, bool) {
+    let mut is_const = false;
+    let mut is_volatile = false;
+    loop {
+        match tokens.peek() {
+            Some(Token::Keyword(Keyword::Const)) => is_const = true,
+            Some(Token::Keyword(Keyword::Volatile)) => is_volatile = true,
+            _ => return (is_const, is_volatile),
+        }
+        tokens.next();
+    }
+}
+
+fn parse_declarator(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+    allow_unnamed: bool,
+) -> Result<Declarator, String> {
+    if let Some(Token::Operator(lexer::Operator::Multiply)) = tokens.peek() {
+        tokens.next();
+        let (is_const, is_volatile) = parse_qualifiers(tokens);
+        return Ok(Declarator::Pointer(
+            Box::new(parse_declarator(tokens, type_names, allow_unnamed)?),
+            is_const,
+            is_volatile,
+        ));
+    }
+
+    // Without a name, a parenthesis opens a nested declarator unless it starts the parameter list
+    let simple_declarator = match tokens.peek() {
+        Some(Token::OpenParenthesis) => {
+            tokens.next();
+            if allow_unnamed
+                && tokens.peek().is_some_and(|token| {
+                    matches!(token, Token::CloseParenthesis) || is_specifier(token, type_names)
+                })
+            {
+                let (params, variadic) = parse_param_list(tokens, type_names)?;
+                Declarator::Function(params, Box::new(Declarator::Unnamed), variadic)
+            } else {
+                let declarator = parse_declarator(tokens, type_names, allow_unnamed)?;
+                expect(Token::CloseParenthesis, tokens)?;
+                declarator
+            }
+        }
+        Some(Token::Identifier(_)) | None => Declarator::Identifier(parse_identifier(tokens)?),
+        Some(_) if allow_unnamed => Declarator::Unnamed,
+        Some(_) => Declarator::Identifier(parse_identifier(tokens)?),
+    };
+
+    match tokens.peek() {
+        Some(Token::OpenParenthesis) => {
+            tokens.next();
+            let (params, variadic) = parse_param_list(tokens, type_names)?;
+            Ok(Declarator::Function(
+                params,
+                Box::new(simple_declarator),
+                variadic,
+            ))
+        }
+        Some(Token::OpenBracket) => {
+            let mut declarator = simple_declarator;
+            while let Some(Token::OpenBracket) = tokens.peek() {
+                tokens.next();
+                let size = parse_array_size(tokens, type_names)?;
+                expect(Token::CloseBracket, tokens)?;
+                declarator = Declarator::Array(Box::new(declarator), size);
+            }
+            Ok(declarator)
+        }
+        _ => Ok(simple_declarator),
+    }
+}
+
+fn parse_abstract_declarator(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<AbstractDeclarator, String> {
+    if let Some(Token::Operator(lexer::Operator::Multiply)) = tokens.peek() {
+        tokens.next();
+        let (is_const, is_volatile) = parse_qualifiers(tokens);
+        return Ok(AbstractDeclarator::Pointer(
+            Box::new(parse_abstract_declarator(tokens, type_names)?),
+            is_const,
+            is_volatile,
+        ));
+    }
+
+    // A parenthesis opens a nested declarator unless it starts the parameter list
+    let mut declarator = match tokens.peek() {
+        Some(Token::OpenParenthesis) => {
+            tokens.next();
+            if tokens.peek().is_some_and(|token| is_specifier(token, type_names)) {
+                let (params, variadic) = parse_abstract_param_list(tokens, type_names)?;
+                AbstractDeclarator::Function(params, Box::new(AbstractDeclarator::Base), variadic)
+            } else {
+                let declarator = parse_abstract_declarator(tokens, type_names)?;
+                expect(Token::CloseParenthesis, tokens)?;
+                declarator
+            }
+        }
+        _ => AbstractDeclarator::Base,
     };
+    loop {
+        declarator = match tokens.peek() {
+            Some(Token::OpenBracket) => {
+                tokens.next();
+                let size = parse_array_size(tokens, type_names)?;
+                expect(Token::CloseBracket, tokens)?;
+                AbstractDeclarator::Array(Box::new(declarator), size)
+            }
+            Some(Token::OpenParenthesis) => {
+                tokens.next();
+                let (params, variadic) = parse_abstract_param_list(tokens, type_names)?;
+                AbstractDeclarator::Function(params, Box::new(declarator), variadic)
+            }
+            _ => return Ok(declarator),
+        };
+    }
+}
+
+// The parameters in a type name have no names
+fn parse_abstract_param_list(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<(Vec<Type>, bool), String> {
+    let mut params: Vec<Type> = Vec::new();
+    let mut variadic = false;
+    loop {
+        let param_type = parse_abstract_type(tokens, type_names)?;
+        if is_empty_param_list(&param_type, &params, tokens) {
+            break;
+        }
+        params.push(adjust_param_type(param_type));
+        if let Some(Token::Comma) = tokens.peek() {
+            tokens.next();
+            if let Some(Token::Ellipsis) = tokens.peek() {
+                tokens.next();
+                variadic = true;
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+    expect(Token::CloseParenthesis, tokens)?;
+    Ok((params, variadic))
+}
+
+fn process_abstract_declarator(declarator: AbstractDeclarator, base_type: Type) -> Type {
+    match declarator {
+        AbstractDeclarator::Base => base_type,
+        AbstractDeclarator::Pointer(inner, is_const, is_volatile) => process_abstract_declarator(
+            *inner,
+            Type::Pointer(Box::new(base_type)).qualify(is_const, is_volatile),
+        ),
+        AbstractDeclarator::Array(inner, size) => {
+            process_abstract_declarator(*inner, make_array_type(base_type, size))
+        }
+        AbstractDeclarator::Function(params, inner, variadic) => process_abstract_declarator(
+            *inner,
+            Type::Function(params, Box::new(base_type), variadic),
+        ),
+    }
+}
+
+// The type name of a cast or sizeof, it's parsed in a copy of the scope as it can't declare anything
+fn parse_abstract_type(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Type, String> {
+    let mut type_names = copy_type_name_map(type_names);
+    let mut definitions: Vec<Declaration> = Vec::new();
+    let base_type = parse_type_name(tokens, &mut type_names, &mut definitions)?;
+    if !definitions.is_empty() {
+        return Err("Types can't be defined in a type name".to_string());
+    }
+    let declarator = parse_abstract_declarator(tokens, &type_names)?;
+    Ok(process_abstract_declarator(declarator, base_type))
+}
+
+// The size is evaluated once its identifiers are resolved, as it can use enumerators
+fn parse_array_size(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Option<Expression>, String> {
+    if let Some(Token::CloseBracket) = tokens.peek() {
+        return Ok(None);
+    }
+    Ok(Some(parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?))
+}
+
+fn parse_initializer(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Initializer, String> {
+    if let Some(Token::OpenBrace) = tokens.peek() {
+        tokens.next();
+        let mut initializers: Vec<Initializer> = Vec::new();
+        loop {
+            initializers.push(parse_initializer(tokens, type_names)?);
+            if let Some(Token::Comma) = tokens.peek() {
+                tokens.next();
+                // A trailing comma is allowed
+                if let Some(Token::CloseBrace) = tokens.peek() {
+                    tokens.next();
+                    break;
+                }
+            } else {
+                expect(Token::CloseBrace, tokens)?;
+                break;
+            }
+        }
+        Ok(Initializer::Compound(initializers, None))
+    } else {
+        Ok(Initializer::Single(parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?))
+    }
+}
+
+// A prototype can end with an ellipsis after its named parameters
+fn parse_param_list(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+) -> Result<(Vec<(Type, Declarator)>, bool), String> {
+    let mut params: Vec<(Type, Declarator)> = Vec::new();
+    let mut variadic = false;
+    loop {
+        let mut definitions: Vec<Declaration> = Vec::new();
+        let param_type = parse_type_name(tokens, type_names, &mut definitions)?;
+        if !definitions.is_empty() {
+            return Err("Types can't be defined in a parameter list".to_string());
+        }
+        if is_empty_param_list(&param_type, &params, tokens) {
+            break;
+        }
+        params.push((param_type, parse_declarator(tokens, type_names, true)?));
+        if let Some(Token::Comma) = tokens.peek() {
+            tokens.next();
+            if let Some(Token::Ellipsis) = tokens.peek() {
+                tokens.next();
+                variadic = true;
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+    expect(Token::CloseParenthesis, tokens)?;
+    Ok((params, variadic))
+}
+
+// A lone void stands for no parameters, a void pointer parameter only starts the same way
+fn is_empty_param_list<T>(
+    param_type: &Type,
+    params: &[T],
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+) -> bool {
+    *param_type == Type::Void
+        && params.is_empty()
+        && matches!(tokens.peek(), Some(Token::CloseParenthesis))
+}
+
+// An array without a size is incomplete until an initializer or another declaration gives it one
+fn make_array_type(element_type: Type, size: Option<Expression>) -> Type {
+    match size {
+        Some(size) => Type::UnresolvedArray(Box::new(element_type), Box::new(size)),
+        None => Type::IncompleteArray(Box::new(element_type)),
+    }
+}
+
+// Array and function parameters are really pointers to their first element or to the function
+fn adjust_param_type(param_type: Type) -> Type {
+    match param_type {
+        Type::Array(element_type, _)
+        | Type::IncompleteArray(element_type)
+        | Type::UnresolvedArray(element_type, _) => Type::Pointer(element_type),
+        function_type @ Type::Function(..) => Type::Pointer(Box::new(function_type)),
+        param_type => param_type,
+    }
+}
+
+// Applies the declarator's derivations to the base type, returns the name, the derived type and
+// the parameter names if it declares a function
+fn process_declarator(
+    declarator: Declarator,
+    base_type: Type,
+) -> Result<(String, Type, Vec<String>), String> {
+    match declarator {
+        Declarator::Identifier(name) => Ok((name, base_type, Vec::new())),
+        Declarator::Unnamed => Ok((String::new(), base_type, Vec::new())),
+        Declarator::Pointer(inner, is_const, is_volatile) => process_declarator(
+            *inner,
+            Type::Pointer(Box::new(base_type)).qualify(is_const, is_volatile),
+        ),
+        Declarator::Array(_, _) if matches!(base_type, Type::Function(..)) => {
+            Err("Can't declare an array of functions".to_string())
+        }
+        Declarator::Array(_, _) if matches!(base_type, Type::IncompleteArray(_)) => {
+            Err("Array elements must have a size".to_string())
+        }
+        Declarator::Array(inner, size) => {
+            process_declarator(*inner, make_array_type(base_type, size))
+        }
+        Declarator::Function(params, inner, variadic) => {
+            let mut param_names: Vec<String> = Vec::new();
+            let mut param_types: Vec<Type> = Vec::new();
+            for (param_base_type, param_declarator) in params {
+                let (param_name, param_type, _) =
+                    process_declarator(param_declarator, param_base_type)?;
+                param_names.push(param_name);
+                param_types.push(adjust_param_type(param_type));
+            }
+            if matches!(
+                base_type,
+                Type::Function(..)
+                    | Type::Array(..)
+                    | Type::IncompleteArray(_)
+                    | Type::UnresolvedArray(..)
+            ) {
+                return Err("A function can't return a function or an array".to_string());
+            }
+            let function_type = Type::Function(param_types, Box::new(base_type), variadic);
+            // Only the parameters of the declared function itself are named, the others belong
+            // to a function type the declarator derives from
+            match *inner {
+                Declarator::Identifier(name) => Ok((name, function_type, param_names)),
+                inner => process_declarator(inner, function_type),
+            }
+        }
+    }
+}
+
+fn is_type_specifier(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Int
+                | Keyword::Long
+                | Keyword::Signed
+                | Keyword::Unsigned
+                | Keyword::Double
+                | Keyword::Char
+                | Keyword::Void
+                | Keyword::Struct
+                | Keyword::Union
+                | Keyword::Enum
+        )
+    )
+}
+
+fn is_specifier(token: &Token, type_names: &TypeNameMap) -> bool {
+    match token {
+        Token::Identifier(name) => get_typedef(name, type_names).is_some(),
+        Token::Keyword(
+            Keyword::Static
+            | Keyword::Extern
+            | Keyword::Typedef
+            | Keyword::Const
+            | Keyword::Volatile,
+        ) => true,
+        token => is_type_specifier(token),
+    }
+}
 
-    let identifier = parse_identifier(tokens)?;
-    if let Some(token) = tokens.next() {
+// Collects the type specifiers and storage classes, a typedef name stands for a whole type
+fn parse_specifiers(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+    declarations: &mut Vec<Declaration>,
+) -> Result<(Type, Vec<Token>), String> {
+    let mut types: Vec<Token> = Vec::new();
+    let mut typedef_type: Option<Type> = None;
+    let mut storage_classes: Vec<Token> = Vec::new();
+    let mut is_const = false;
+    let mut is_volatile = false;
+    while let Some(token) = tokens.peek() {
         match token {
-            Token::Operator(lexer::Operator::Assign) => {
-                let expression = parse_expression(tokens, MAX_PRECEDENCE)?;
-                expect(Token::Semicolon, tokens)?;
-                Ok(Declaration::VarDecl(VariableDeclaration {
-                    name: identifier,
-                    init: Some(expression),
-                    storage_class,
-                }))
-            }
-            Token::Semicolon => Ok(Declaration::VarDecl(VariableDeclaration {
-                name: identifier,
-                init: None,
-                storage_class,
-            })),
-            Token::OpenParenthesis => {
-                let mut params: Vec<String> = Vec::new();
-                let token = tokens.next();
-                if let Some(Token::Keyword(Keyword::Void)) = token {
-                } else if let Some(Token::Keyword(Keyword::Int)) = token {
-                    let param = parse_identifier(tokens)?;
-                    params.push(param);
-
-                    while let Some(Token::Comma) = tokens.peek() {
+            token if is_type_specifier(token) => {
+                push_type_specifier(tokens, &mut types, type_names, declarations)?
+            }
+            Token::Keyword(Keyword::Const) => {
+                is_const = true;
+                tokens.next();
+            }
+            Token::Keyword(Keyword::Volatile) => {
+                is_volatile = true;
+                tokens.next();
+            }
+            Token::Keyword(Keyword::Static | Keyword::Extern | Keyword::Typedef) => {
+                storage_classes.push(tokens.next().unwrap())
+            }
+            Token::Identifier(name) if types.is_empty() && typedef_type.is_none() => {
+                match get_typedef(name, type_names) {
+                    Some(named_type) => {
+                        typedef_type = Some(named_type.clone());
                         tokens.next();
-                        expect(Token::Keyword(Keyword::Int), tokens)?;
-                        let param = parse_identifier(tokens)?;
-                        params.push(param);
                     }
+                    None => break,
                 }
-                expect(Token::CloseParenthesis, tokens)?;
+            }
+            _ => break,
+        }
+    }
+    let base_type = match typedef_type {
+        Some(named_type) if types.is_empty() => named_type,
+        Some(_) => return Err(format!("Invalid type specifier: {:?}", types)),
+        None => parse_type(&types)?,
+    };
+    Ok((base_type.qualify(is_const, is_volatile), storage_classes))
+}
 
-                let body = if let Some(Token::Semicolon) = tokens.peek() {
-                    tokens.next();
-                    None
-                } else {
-                    Some(parse_block(tokens)?)
-                };
+// The specifiers of a member or parameter, which can't have a storage class
+fn parse_type_name(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+    declarations: &mut Vec<Declaration>,
+) -> Result<Type, String> {
+    let (base_type, storage_classes) = parse_specifiers(tokens, type_names, declarations)?;
+    if !storage_classes.is_empty() {
+        return Err("Unexpected storage class".to_string());
+    }
+    Ok(base_type)
+}
 
-                Ok(Declaration::FuncDecl(FunctionDeclaration {
-                    name: identifier,
-                    body,
-                    params,
-                    storage_class,
-                }))
+// A structure or union specifier is the keyword followed by its tag, and maybe its definition.
+// An enumeration is always an int, its tag is optional.
+fn push_type_specifier(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    types: &mut Vec<Token>,
+    type_names: &mut TypeNameMap,
+    declarations: &mut Vec<Declaration>,
+) -> Result<(), String> {
+    let token = tokens.next().unwrap();
+    let keyword = match token {
+        Token::Keyword(keyword) => keyword,
+        _ => panic!("Only called on type specifiers"),
+    };
+    match keyword {
+        Keyword::Struct | Keyword::Union => {
+            let tag = match tokens.peek() {
+                Some(Token::OpenBrace) => make_anonymous_tag(),
+                _ => parse_identifier(tokens)?,
+            };
+            match tokens.peek() {
+                Some(Token::OpenBrace) => {
+                    let definition = parse_struct_declaration(
+                        tag.clone(),
+                        keyword == Keyword::Union,
+                        tokens,
+                        type_names,
+                        declarations,
+                    )?;
+                    declarations.push(Declaration::StructDecl(definition));
+                }
+                // A declaration without a declarator only introduces the tag
+                Some(Token::Semicolon) => {
+                    declarations.push(Declaration::StructDecl(StructDeclaration {
+                        tag: tag.clone(),
+                        members: Vec::new(),
+                        is_union: keyword == Keyword::Union,
+                    }));
+                }
+                _ => {}
             }
-            _ => Err("Expected a variable or function declaration".to_string()),
+            types.push(Token::Keyword(keyword));
+            types.push(Token::Identifier(tag));
         }
-    } else {
-        Err("Expected more tokens".to_string())
+        Keyword::Enum => {
+            let tag = match tokens.peek() {
+                Some(Token::Identifier(_)) => Some(parse_identifier(tokens)?),
+                _ => None,
+            };
+            if let Some(Token::OpenBrace) = tokens.peek() {
+                let definition = parse_enum_declaration(tag, tokens, type_names)?;
+                declarations.push(Declaration::EnumDecl(definition));
+            } else if tag.is_none() {
+                return Err("Expected an enumeration tag or definition".to_string());
+            }
+            types.push(Token::Keyword(Keyword::Enum));
+        }
+        _ => types.push(Token::Keyword(keyword)),
     }
+    Ok(())
+}
+
+static mut ANONYMOUS_COUNTER: i64 = -1;
+
+// The dot keeps it from clashing with a tag written in the source
+fn make_anonymous_tag() -> String {
+    format!("anonymous.{}", unsafe {
+        ANONYMOUS_COUNTER += 1;
+        ANONYMOUS_COUNTER
+    })
 }
 
-fn parse_block(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Block, String> {
+fn parse_type(types: &[Token]) -> Result<Type, String> {
+    let count = |keyword: Keyword| {
+        types
+            .iter()
+            .filter(|&token| *token == Token::Keyword(keyword))
+            .count()
+    };
+    let int_count = count(Keyword::Int);
+    let long_count = count(Keyword::Long);
+    let signed_count = count(Keyword::Signed);
+    let unsigned_count = count(Keyword::Unsigned);
+    let double_count = count(Keyword::Double);
+    let char_count = count(Keyword::Char);
+
+    // Structures and unions share their tags and layouts, enumerations are plain ints
+    if let [Token::Keyword(Keyword::Struct | Keyword::Union), Token::Identifier(tag)] = types {
+        return Ok(Type::Structure(tag.clone()));
+    }
+    if let [Token::Keyword(Keyword::Enum)] = types {
+        return Ok(Type::Int);
+    }
+
+    if double_count == 1 && types.len() == 1 {
+        return Ok(Type::Double);
+    }
+
+    if let [Token::Keyword(Keyword::Void)] = types {
+        return Ok(Type::Void);
+    }
+
+    if char_count == 1 && types.len() - signed_count - unsigned_count == 1 {
+        match (signed_count, unsigned_count) {
+            (0, 0) => return Ok(Type::Char),
+            (1, 0) => return Ok(Type::SChar),
+            (0, 1) => return Ok(Type::UChar),
+            _ => {}
+        }
+    }
+
+    if types.is_empty()
+        || double_count > 0
+        || char_count > 0
+        || count(Keyword::Struct) > 0
+        || count(Keyword::Union) > 0
+        || count(Keyword::Enum) > 0
+        || count(Keyword::Void) > 0
+        || int_count > 1
+        || long_count > 1
+        || signed_count + unsigned_count > 1
+    {
+        return Err(format!("Invalid type specifier: {:?}", types));
+    }
+
+    match (unsigned_count, long_count) {
+        (1, 1) => Ok(Type::ULong),
+        (1, 0) => Ok(Type::UInt),
+        (0, 1) => Ok(Type::Long),
+        _ => Ok(Type::Int),
+    }
+}
+
+// Every block opens a new scope for typedef names
+fn parse_block(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Block, String> {
     expect(Token::OpenBrace, tokens)?;
+    let mut inner_type_names = copy_type_name_map(type_names);
     let mut block: Block = Vec::new();
-    while match tokens.peek() {
-        Some(Token::CloseBrace) | None => false,
-        _ => true,
-    } {
-        block.push(parse_block_item(tokens)?);
+    while !matches!(tokens.peek(), Some(Token::CloseBrace) | None) {
+        block.extend(parse_block_items(tokens, &mut inner_type_names)?);
     }
     expect(Token::CloseBrace, tokens)?;
     Ok(block)
 }
 
-fn parse_block_item(
+fn parse_block_items(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<BlockItem, String> {
-    if let Some(
-        Token::Keyword(Keyword::Int)
-        | Token::Keyword(Keyword::Static)
-        | Token::Keyword(Keyword::Extern),
-    ) = tokens.peek()
-    {
-        Ok(BlockItem::D(parse_declaration(tokens)?))
+    type_names: &mut TypeNameMap,
+) -> Result<Vec<BlockItem>, String> {
+    if tokens.peek().is_some_and(|token| is_specifier(token, type_names)) {
+        Ok(parse_declaration(tokens, type_names)?
+            .into_iter()
+            .map(BlockItem::D)
+            .collect())
     } else {
-        Ok(BlockItem::S(parse_statement(tokens)?))
+        Ok(vec![BlockItem::S(parse_statement(tokens, type_names)?)])
     }
 }
 
@@ -402,13 +1192,18 @@ fn parse_identifier(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Resul
 
 fn parse_statement(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
 ) -> Result<Statement, String> {
     match tokens.peek() {
         Some(Token::Keyword(Keyword::Return)) => {
             tokens.next();
-            let expression = parse_expression(tokens, MAX_PRECEDENCE)?;
+            if let Some(Token::Semicolon) = tokens.peek() {
+                tokens.next();
+                return Ok(Statement::Return(None));
+            }
+            let expression = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::Semicolon, tokens)?;
-            Ok(Statement::Return(expression))
+            Ok(Statement::Return(Some(expression)))
         }
         Some(Token::Semicolon) => {
             tokens.next();
@@ -417,19 +1212,19 @@ fn parse_statement(
         Some(Token::Keyword(Keyword::If)) => {
             tokens.next();
             expect(Token::OpenParenthesis, tokens)?;
-            let condition = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let condition = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::CloseParenthesis, tokens)?;
-            let then = Box::new(parse_statement(tokens)?);
+            let then = Box::new(parse_statement(tokens, type_names)?);
             let optional_else = if let Some(Token::Keyword(Keyword::Else)) = tokens.peek() {
                 tokens.next();
-                Some(Box::new(parse_statement(tokens)?))
+                Some(Box::new(parse_statement(tokens, type_names)?))
             } else {
                 None
             };
             Ok(Statement::If(condition, then, optional_else))
         }
         Some(Token::OpenBrace) => {
-            let block = parse_block(tokens)?;
+            let block = parse_block(tokens, type_names)?;
             Ok(Statement::Compound(block))
         }
         Some(Token::Keyword(Keyword::Break)) => {
@@ -445,17 +1240,17 @@ fn parse_statement(
         Some(Token::Keyword(Keyword::While)) => {
             tokens.next();
             expect(Token::OpenParenthesis, tokens)?;
-            let condition = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let condition = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::CloseParenthesis, tokens)?;
-            let body = Box::new(parse_statement(tokens)?);
+            let body = Box::new(parse_statement(tokens, type_names)?);
             Ok(Statement::While(condition, body, None))
         }
         Some(Token::Keyword(Keyword::Do)) => {
             tokens.next();
-            let body = Box::new(parse_statement(tokens)?);
+            let body = Box::new(parse_statement(tokens, type_names)?);
             expect(Token::Keyword(Keyword::While), tokens)?;
             expect(Token::OpenParenthesis, tokens)?;
-            let condition = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let condition = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::CloseParenthesis, tokens)?;
             expect(Token::Semicolon, tokens)?;
             Ok(Statement::DoWhile(body, condition, None))
@@ -463,121 +1258,150 @@ fn parse_statement(
         Some(Token::Keyword(Keyword::For)) => {
             tokens.next();
             expect(Token::OpenParenthesis, tokens)?;
-            let init = parse_for_init(tokens)?;
+            // The loop is a scope of its own, the declaration in its header can hide a typedef
+            let mut type_names = copy_type_name_map(type_names);
+            let init = parse_for_init(tokens, &mut type_names)?;
             let condition = if let Some(Token::Semicolon) = tokens.peek() {
                 None
             } else {
-                Some(parse_expression(tokens, MAX_PRECEDENCE)?)
+                Some(parse_expression(tokens, &type_names, MAX_PRECEDENCE)?)
             };
             expect(Token::Semicolon, tokens)?;
             let post = if let Some(Token::CloseParenthesis) = tokens.peek() {
                 None
             } else {
-                Some(parse_expression(tokens, MAX_PRECEDENCE)?)
+                Some(parse_expression(tokens, &type_names, MAX_PRECEDENCE)?)
             };
             expect(Token::CloseParenthesis, tokens)?;
-            let body = Box::new(parse_statement(tokens)?);
+            let body = Box::new(parse_statement(tokens, &type_names)?);
             Ok(Statement::For(init, condition, post, body, None))
         }
         Some(Token::Keyword(Keyword::Switch)) => {
             tokens.next();
             expect(Token::OpenParenthesis, tokens)?;
-            let value = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let value = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::CloseParenthesis, tokens)?;
-            expect(Token::OpenBrace, tokens)?;
-            let mut cases: Vec<Case> = Vec::new();
-            while let Some(Token::Keyword(Keyword::Case)) = tokens.peek() {
-                tokens.next();
-                let next = tokens.next();
-                let cond = if let Some(Token::Constant(val)) = next {
-                    val
-                } else {
-                    return Err(format!("Expected a constant value. Got: {:#?}", next));
-                };
-                expect(Token::Colon, tokens)?;
-                let body = parse_statement(tokens)?;
-                cases.push(Case { cond, body });
-            }
-            let default = if let Some(Token::Keyword(Keyword::Default)) = tokens.peek() {
-                tokens.next();
-                expect(Token::Colon, tokens)?;
-                Some(Box::new(parse_statement(tokens)?))
-            } else {
-                None
+            let body = Box::new(parse_statement(tokens, type_names)?);
+            Ok(Statement::Switch(value, body, Vec::new(), None))
+        }
+        Some(Token::Keyword(Keyword::Case)) => {
+            tokens.next();
+            let value = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            expect(Token::Colon, tokens)?;
+            let body = Box::new(parse_statement(tokens, type_names)?);
+            Ok(Statement::Case(value, body, None))
+        }
+        Some(Token::Keyword(Keyword::Default)) => {
+            tokens.next();
+            expect(Token::Colon, tokens)?;
+            let body = Box::new(parse_statement(tokens, type_names)?);
+            Ok(Statement::Default(body, None))
+        }
+        Some(Token::Keyword(Keyword::Goto)) => {
+            tokens.next();
+            let label = match tokens.next() {
+                Some(Token::Identifier(label)) => label,
+                _ => return Err("Expected a label after goto".to_string()),
             };
-            expect(Token::CloseBrace, tokens)?;
-
-            Ok(Statement::Switch(value, cases, default, None))
+            expect(Token::Semicolon, tokens)?;
+            Ok(Statement::Goto(label))
         }
         Some(_) => {
-            let expression = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let expression = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
+            // A lone identifier followed by a colon is a label, not an expression
+            if let (ExpressionKind::Var(label), Some(Token::Colon)) =
+                (&expression.kind, tokens.peek())
+            {
+                let label = label.clone();
+                tokens.next();
+                let body = Box::new(parse_statement(tokens, type_names)?);
+                return Ok(Statement::Labeled(label, body));
+            }
             expect(Token::Semicolon, tokens)?;
             Ok(Statement::Expression(expression))
         }
-        None => return Err("Unexpected end of tokens.".to_string()),
+        None => Err("Unexpected end of tokens.".to_string()),
     }
 }
 
-fn parse_for_init(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<ForInit, String> {
-    if let Some(Token::Keyword(Keyword::Int)) = tokens.peek() {
-        let declaration = parse_declaration(tokens)?;
-        match declaration {
-            Declaration::FuncDecl(_) => {
-                Err("Expected a variable declaration got a function".to_string())
+fn parse_for_init(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &mut TypeNameMap,
+) -> Result<ForInit, String> {
+    if tokens.peek().is_some_and(|token| is_specifier(token, type_names)) {
+        let declarations = parse_declaration(tokens, type_names)?;
+        if declarations.is_empty() {
+            return Err("Expected a variable declaration".to_string());
+        }
+        let mut variables: Vec<VariableDeclaration> = Vec::new();
+        for declaration in declarations {
+            match declaration {
+                Declaration::FuncDecl(_) => {
+                    return Err("Expected a variable declaration got a function".to_string())
+                }
+                Declaration::StructDecl(_) => {
+                    return Err("Expected a variable declaration got a structure".to_string())
+                }
+                Declaration::EnumDecl(_) => {
+                    return Err("Expected a variable declaration got an enumeration".to_string())
+                }
+                Declaration::VarDecl(var) => variables.push(var),
             }
-            Declaration::VarDecl(var) => Ok(ForInit::InitDeclaration(var)),
         }
+        Ok(ForInit::InitDeclaration(variables))
+    } else if let Some(Token::Semicolon) = tokens.peek() {
+        tokens.next();
+        Ok(ForInit::InitExpression(None))
     } else {
-        let token = tokens.next();
-        Ok(ForInit::InitExpression(
-            if let Some(Token::Semicolon) = token {
-                None
-            } else {
-                Some(parse_expression(tokens, MAX_PRECEDENCE)?)
-            },
-        ))
+        let expression = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
+        expect(Token::Semicolon, tokens)?;
+        Ok(ForInit::InitExpression(Some(expression)))
     }
 }
 
 fn parse_expression(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
     max_precedence: u8,
 ) -> Result<Expression, String> {
-    let mut left = parse_factor(tokens)?;
-    while let Some(Token::Operator(
-        op @ (lexer::Operator::Plus
-        | lexer::Operator::Minus
-        | lexer::Operator::Multiply
-        | lexer::Operator::Divide
-        | lexer::Operator::Modulo
-        | lexer::Operator::And
-        | lexer::Operator::Or
-        | lexer::Operator::Xor
-        | lexer::Operator::ShiftLeft
-        | lexer::Operator::ShiftRight
-        | lexer::Operator::LAnd
-        | lexer::Operator::LOr
-        | lexer::Operator::EqualTo
-        | lexer::Operator::NotEqualTo
-        | lexer::Operator::LessThan
-        | lexer::Operator::LessOrEqual
-        | lexer::Operator::GreaterThan
-        | lexer::Operator::GreaterOrEqual
-        | lexer::Operator::Assign
-        | lexer::Operator::AddAssign
-        | lexer::Operator::SubAssign
-        | lexer::Operator::MulAssign
-        | lexer::Operator::DivAssign
-        | lexer::Operator::ModAssign
-        | lexer::Operator::AndAssign
-        | lexer::Operator::OrAssign
-        | lexer::Operator::XorAssign
-        | lexer::Operator::LeftShiftAssign
-        | lexer::Operator::RightShiftAssign
-        | lexer::Operator::TernaryIf),
-    )) = tokens.peek()
-    {
-        let op = parse_binary_operator(&op)?;
+    let mut left = parse_factor(tokens, type_names)?;
+    loop {
+        let op = match tokens.peek() {
+            Some(Token::Operator(
+                op @ (lexer::Operator::Plus
+                | lexer::Operator::Minus
+                | lexer::Operator::Multiply
+                | lexer::Operator::Divide
+                | lexer::Operator::Modulo
+                | lexer::Operator::And
+                | lexer::Operator::Or
+                | lexer::Operator::Xor
+                | lexer::Operator::ShiftLeft
+                | lexer::Operator::ShiftRight
+                | lexer::Operator::LAnd
+                | lexer::Operator::LOr
+                | lexer::Operator::EqualTo
+                | lexer::Operator::NotEqualTo
+                | lexer::Operator::LessThan
+                | lexer::Operator::LessOrEqual
+                | lexer::Operator::GreaterThan
+                | lexer::Operator::GreaterOrEqual
+                | lexer::Operator::Assign
+                | lexer::Operator::AddAssign
+                | lexer::Operator::SubAssign
+                | lexer::Operator::MulAssign
+                | lexer::Operator::DivAssign
+                | lexer::Operator::ModAssign
+                | lexer::Operator::AndAssign
+                | lexer::Operator::OrAssign
+                | lexer::Operator::XorAssign
+                | lexer::Operator::LeftShiftAssign
+                | lexer::Operator::RightShiftAssign
+                | lexer::Operator::TernaryIf),
+            )) => parse_binary_operator(op)?,
+            Some(Token::Comma) => BinaryOperator::Comma,
+            _ => break,
+        };
         let precedence = *PRECEDENCE_MAP.get(&op).unwrap();
         if precedence >= max_precedence {
             break;
@@ -586,8 +1410,12 @@ fn parse_expression(
             // Right to left associativity
             BinaryOperator::Assign => {
                 tokens.next();
-                let right = parse_expression(tokens, precedence)?;
-                left = Expression::Assignment(None, Box::new(left), Box::new(right));
+                let right = parse_expression(tokens, type_names, precedence + 1)?;
+                left = Expression::new(ExpressionKind::Assignment(
+                    None,
+                    Box::new(left),
+                    Box::new(right),
+                ));
             }
             BinaryOperator::AddAssign
             | BinaryOperator::SubAssign
@@ -613,55 +1441,199 @@ fn parse_expression(
                     _ => return Err("Shouldn't reach here".to_string()),
                 };
                 tokens.next();
-                let right = parse_expression(tokens, precedence)?;
-                left = Expression::Assignment(Some(op), Box::new(left), Box::new(right));
+                let right = parse_expression(tokens, type_names, precedence + 1)?;
+                left = Expression::new(ExpressionKind::Assignment(
+                    Some(op),
+                    Box::new(left),
+                    Box::new(right),
+                ));
             }
             BinaryOperator::TernaryIf => {
                 tokens.next();
-                let middle = parse_expression(tokens, MAX_PRECEDENCE)?;
+                let middle = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
                 expect(Token::Colon, tokens)?;
-                let right = parse_expression(tokens, precedence)?;
-                left = Expression::Conditional(Box::new(left), Box::new(middle), Box::new(right));
+                let right = parse_expression(tokens, type_names, precedence + 1)?;
+                left = Expression::new(ExpressionKind::Conditional(
+                    Box::new(left),
+                    Box::new(middle),
+                    Box::new(right),
+                ));
+            }
+            BinaryOperator::Comma => {
+                tokens.next();
+                let right = parse_expression(tokens, type_names, precedence)?;
+                left = Expression::new(ExpressionKind::Comma(Box::new(left), Box::new(right)));
             }
             // Left to right associativity
             _ => {
                 tokens.next();
-                let right: Expression = parse_expression(tokens, precedence - 1)?;
-                left = Expression::Binary(op, Box::new(left), Box::new(right));
+                let right: Expression = parse_expression(tokens, type_names, precedence - 1)?;
+                left = Expression::new(ExpressionKind::Binary(op, Box::new(left), Box::new(right)));
             }
         }
     }
     Ok(left)
 }
 
-fn parse_factor(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expression, String> {
-    match tokens.next() {
-        Some(Token::Constant(constant)) => Ok(Expression::Constant(constant)),
+fn parse_factor(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Expression, String> {
+    let primary = match tokens.next() {
+        Some(
+            token @ (Token::Constant(_)
+            | Token::LongConstant(_)
+            | Token::UnsignedConstant(_)
+            | Token::UnsignedLongConstant(_)
+            | Token::DoubleConstant(_)
+            | Token::CharConstant(_)),
+        ) => Expression::new(ExpressionKind::Constant(parse_constant(token)?)),
+        Some(Token::StringLiteral(mut bytes)) => {
+            // Adjacent string literals are concatenated
+            while let Some(Token::StringLiteral(_)) = tokens.peek() {
+                if let Some(Token::StringLiteral(next)) = tokens.next() {
+                    bytes.extend(next);
+                }
+            }
+            Expression::new(ExpressionKind::String(bytes))
+        }
         Some(Token::Operator(
             operator
             @ (lexer::Operator::Minus | lexer::Operator::Complement | lexer::Operator::Not),
         )) => {
             // let expression = parse_expression(tokens)?;
-            let inner_expression = parse_factor(tokens)?;
-            Ok(Expression::Unary(
+            let inner_expression = parse_factor(tokens, type_names)?;
+            return Ok(Expression::new(ExpressionKind::Unary(
                 parse_unary_operator(operator)?,
                 Box::new(inner_expression),
-            ))
+            )));
+        }
+        // ++x is the same as x += 1
+        Some(Token::Operator(
+            operator @ (lexer::Operator::Increment | lexer::Operator::Decrement),
+        )) => {
+            let op = match operator {
+                lexer::Operator::Increment => BinaryOperator::Add,
+                _ => BinaryOperator::Subtract,
+            };
+            return Ok(Expression::new(ExpressionKind::Assignment(
+                Some(op),
+                Box::new(parse_factor(tokens, type_names)?),
+                Box::new(Expression::new(ExpressionKind::Constant(Const::Int(1)))),
+            )));
+        }
+        Some(Token::Operator(lexer::Operator::Multiply)) => {
+            return Ok(Expression::new(ExpressionKind::Dereference(Box::new(
+                parse_factor(tokens, type_names)?,
+            ))));
+        }
+        Some(Token::Operator(lexer::Operator::And)) => {
+            return Ok(Expression::new(ExpressionKind::AddressOf(Box::new(
+                parse_factor(tokens, type_names)?,
+            ))));
+        }
+        Some(Token::OpenParenthesis)
+            if tokens.peek().is_some_and(|token| is_specifier(token, type_names)) =>
+        {
+            let target_type = parse_abstract_type(tokens, type_names)?;
+            expect(Token::CloseParenthesis, tokens)?;
+            return Ok(Expression::new(ExpressionKind::Cast(
+                target_type,
+                Box::new(parse_factor(tokens, type_names)?),
+            )));
         }
         Some(Token::OpenParenthesis) => {
-            let inner_expression = parse_expression(tokens, MAX_PRECEDENCE)?;
+            let inner_expression = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
             expect(Token::CloseParenthesis, tokens)?;
-            Ok(inner_expression)
+            inner_expression
         }
-        Some(Token::Identifier(id)) => {
+        // A parenthesized operand can't be told apart from a type name before the next token
+        Some(Token::Keyword(Keyword::Sizeof)) => {
             if let Some(Token::OpenParenthesis) = tokens.peek() {
                 tokens.next();
+                if tokens.peek().is_some_and(|token| is_specifier(token, type_names)) {
+                    let target_type = parse_abstract_type(tokens, type_names)?;
+                    expect(Token::CloseParenthesis, tokens)?;
+                    return Ok(Expression::new(ExpressionKind::SizeOfType(target_type)));
+                }
+                let inner_expression = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
+                expect(Token::CloseParenthesis, tokens)?;
+                let inner_expression = parse_postfix(inner_expression, tokens, type_names)?;
+                return Ok(Expression::new(ExpressionKind::SizeOf(Box::new(
+                    inner_expression,
+                ))));
+            }
+            return Ok(Expression::new(ExpressionKind::SizeOf(Box::new(
+                parse_factor(tokens, type_names)?,
+            ))));
+        }
+        Some(Token::Keyword(Keyword::VaStart)) => {
+            expect(Token::OpenParenthesis, tokens)?;
+            let va_list = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            // The last named parameter isn't needed to find the variable arguments
+            expect(Token::Comma, tokens)?;
+            parse_identifier(tokens)?;
+            expect(Token::CloseParenthesis, tokens)?;
+            Expression::new(ExpressionKind::VaStart(Box::new(va_list)))
+        }
+        Some(Token::Keyword(Keyword::VaArg)) => {
+            expect(Token::OpenParenthesis, tokens)?;
+            let va_list = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            expect(Token::Comma, tokens)?;
+            let arg_type = parse_abstract_type(tokens, type_names)?;
+            expect(Token::CloseParenthesis, tokens)?;
+            Expression::new(ExpressionKind::VaArg(Box::new(va_list), arg_type))
+        }
+        Some(Token::Keyword(Keyword::VaEnd)) => {
+            expect(Token::OpenParenthesis, tokens)?;
+            let va_list = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            expect(Token::CloseParenthesis, tokens)?;
+            Expression::new(ExpressionKind::VaEnd(Box::new(va_list)))
+        }
+        Some(Token::Keyword(Keyword::VaCopy)) => {
+            expect(Token::OpenParenthesis, tokens)?;
+            let destination = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            expect(Token::Comma, tokens)?;
+            let source = parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?;
+            expect(Token::CloseParenthesis, tokens)?;
+            Expression::new(ExpressionKind::VaCopy(
+                Box::new(destination),
+                Box::new(source),
+            ))
+        }
+        Some(Token::Identifier(id)) => Expression::new(ExpressionKind::Var(id)),
+        Some(token) => {
+            print!("Remaining: ");
+            for token in tokens {
+                print!("{:?}", token);
+            }
+            println!();
+            return Err(format!(
+                "Invalid token. Expected a factor, got: {:?}",
+                token
+            ));
+        }
+        None => return Err("Unexpected end of tokens.".to_string()),
+    };
+
+    parse_postfix(primary, tokens, type_names)
+}
+
+// Postfix operators bind tighter than the prefix ones
+fn parse_postfix(
+    mut expression: Expression,
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    type_names: &TypeNameMap,
+) -> Result<Expression, String> {
+    loop {
+        expression = match tokens.peek() {
+            Some(Token::OpenParenthesis) => {
+                tokens.next();
+                let mut args: Vec<Expression> = Vec::new();
                 if let Some(Token::CloseParenthesis) = tokens.peek() {
                     tokens.next();
-                    Ok(Expression::FunctionCall(id, Vec::new()))
                 } else {
-                    let mut args: Vec<Expression> = Vec::new();
-                    args.push(parse_expression(tokens, MAX_PRECEDENCE)?);
+                    args.push(parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?);
                     while let Some(token) = tokens.peek() {
                         if let Token::CloseParenthesis = token {
                             tokens.next();
@@ -669,29 +1641,64 @@ fn parse_factor(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Ex
                         }
                         if let Token::Comma = token {
                             tokens.next();
-                            args.push(parse_expression(tokens, MAX_PRECEDENCE)?);
+                            args.push(parse_expression(tokens, type_names, ASSIGNMENT_PRECEDENCE)?);
                         } else {
                             return Err(format!("Expected a comma, got: {:#?}", token));
                         }
                     }
-                    Ok(Expression::FunctionCall(id, args))
                 }
-            } else {
-                Ok(Expression::Var(id))
+                Expression::new(ExpressionKind::FunctionCall(Box::new(expression), args))
             }
-        }
-        Some(token) => {
-            print!("Remaining: ");
-            for token in tokens {
-                print!("{:?}", token);
+            Some(Token::OpenBracket) => {
+                tokens.next();
+                let index = parse_expression(tokens, type_names, MAX_PRECEDENCE)?;
+                expect(Token::CloseBracket, tokens)?;
+                Expression::new(ExpressionKind::Subscript(
+                    Box::new(expression),
+                    Box::new(index),
+                ))
             }
-            println!();
-            Err(format!(
-                "Invalid token. Expected a factor, got: {:?}",
-                token
-            ))
+            Some(Token::Dot) => {
+                tokens.next();
+                let member = parse_identifier(tokens)?;
+                Expression::new(ExpressionKind::Dot(Box::new(expression), member))
+            }
+            Some(Token::Arrow) => {
+                tokens.next();
+                let member = parse_identifier(tokens)?;
+                Expression::new(ExpressionKind::Arrow(Box::new(expression), member))
+            }
+            Some(Token::Operator(lexer::Operator::Increment)) => {
+                tokens.next();
+                Expression::new(ExpressionKind::Postfix(BinaryOperator::Add, Box::new(expression)))
+            }
+            Some(Token::Operator(lexer::Operator::Decrement)) => {
+                tokens.next();
+                Expression::new(ExpressionKind::Postfix(
+                    BinaryOperator::Subtract,
+                    Box::new(expression),
+                ))
+            }
+            _ => return Ok(expression),
+        };
+    }
+}
+
+fn parse_constant(token: Token) -> Result<Const, String> {
+    match token {
+        Token::Constant(value) if value <= i32::MAX as u64 => Ok(Const::Int(value as i32)),
+        Token::Constant(value) | Token::LongConstant(value) if value <= i64::MAX as u64 => {
+            Ok(Const::Long(value as i64))
         }
-        None => Err("Unexpected end of tokens.".to_string()),
+        Token::UnsignedConstant(value) if value <= u32::MAX as u64 => {
+            Ok(Const::UInt(value as u32))
+        }
+        Token::UnsignedConstant(value) | Token::UnsignedLongConstant(value) => {
+            Ok(Const::ULong(value))
+        }
+        Token::DoubleConstant(value) => Ok(Const::Double(value)),
+        Token::CharConstant(value) => Ok(Const::Int(value)),
+        _ => Err(format!("Constant is too large to be represented: {:?}", token)),
     }
 }
 
@@ -700,7 +1707,7 @@ fn parse_unary_operator(op: lexer::Operator) -> Result<UnaryOperator, String> {
         lexer::Operator::Minus => Ok(UnaryOperator::Negate),
         lexer::Operator::Complement => Ok(UnaryOperator::Complement),
         lexer::Operator::Not => Ok(UnaryOperator::Not),
-        _ => return Err(format!("Unsupported unary operator: {:?}", op)),
+        _ => Err(format!("Unsupported unary operator: {:?}", op)),
     }
 }
 
@@ -737,7 +1744,7 @@ fn parse_binary_operator(op: &lexer::Operator) -> Result<BinaryOperator, String>
         lexer::Operator::RightShiftAssign => Ok(BinaryOperator::RightShiftAssign),
         lexer::Operator::TernaryIf => Ok(BinaryOperator::TernaryIf),
         lexer::Operator::TernaryElse => Ok(BinaryOperator::TernaryElse),
-        _ => return Err(format!("Unsupported binary operator: {:?}", op)),
+        _ => Err(format!("Unsupported binary operator: {:?}", op)),
     }
 }
 
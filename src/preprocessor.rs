@@ -0,0 +1,1299 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+use crate::lexer;
+
+// The compiler's own stddef.h and stdarg.h, searched before the system headers
+const COMPILER_INCLUDE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/include");
+
+const SYSTEM_INCLUDE_DIRS: [&str; 3] = [
+    "/usr/local/include",
+    "/usr/include/x86_64-linux-gnu",
+    "/usr/include",
+];
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// Above the conditional operator, the precedences are the parser's
+const MAX_PRECEDENCE: u8 = 140;
+
+const PREDEFINED_MACROS: &str = "#define __STDC__ 1
+#define __STDC_HOSTED__ 1
+#define __STDC_VERSION__ 201710L
+#define __x86_64__ 1
+#define __x86_64 1
+#define __linux__ 1
+#define __unix__ 1
+#define __LP64__ 1
+#define _LP64 1
+#define __CHAR_BIT__ 8
+";
+
+// Longest first so the first match is the longest one
+const PUNCTUATORS: [&str; 23] = [
+    "<<=", ">>=", "...", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "&=", "|=", "^=", "##",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum PpTokenKind {
+    Identifier,
+    Number,
+    CharConstant,
+    StringLiteral,
+    Punctuator,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct PpToken {
+    kind: PpTokenKind,
+    text: String,
+    line: usize,
+    at_line_start: bool,
+    leading_space: bool,
+    hide_set: HashSet<String>, // macros that can't expand this token again
+}
+
+struct Macro {
+    params: Option<Vec<String>>, // None for object-like macros
+    variadic: bool,              // the last parameter is __VA_ARGS__
+    body: Vec<PpToken>,
+}
+
+struct Conditional {
+    line: usize,
+    taken: bool, // one of the groups was already included
+    seen_else: bool,
+}
+
+// #if arithmetic is done in intmax_t or uintmax_t
+#[derive(Clone, Copy)]
+struct PpValue {
+    value: u64,
+    unsigned: bool,
+}
+
+struct IncludeFrame {
+    file: PathBuf,
+    line: usize, // of the directive being processed
+}
+
+struct PreprocessorState<'a> {
+    include_dirs: &'a [String],
+    include_stack: Vec<IncludeFrame>,
+    once_files: HashSet<PathBuf>,
+    // Files wrapped in #ifndef GUARD / #define GUARD / #endif
+    include_guards: HashMap<PathBuf, String>,
+    macros: HashMap<String, Macro>,
+}
+
+// Macro options are -DNAME, -DNAME=VALUE and -UNAME, applied in order
+pub fn preprocess(
+    source_file: &str,
+    include_dirs: &[String],
+    macro_options: &[String],
+) -> Result<String, String> {
+    let mut state = PreprocessorState {
+        include_dirs,
+        include_stack: Vec::new(),
+        once_files: HashSet::new(),
+        include_guards: HashMap::new(),
+        macros: HashMap::new(),
+    };
+    let mut output: Vec<PpToken> = Vec::new();
+    let mut command_line = String::new();
+    for option in macro_options {
+        if let Some(definition) = option.strip_prefix("-D") {
+            match definition.split_once('=') {
+                Some((name, value)) => {
+                    command_line.push_str(&format!("#define {} {}\n", name, value))
+                }
+                None => command_line.push_str(&format!("#define {} 1\n", definition)),
+            }
+        } else if let Some(name) = option.strip_prefix("-U") {
+            command_line.push_str(&format!("#undef {}\n", name));
+        }
+    }
+    define_macros(PREDEFINED_MACROS, "<built-in>", &mut state, &mut output)?;
+    define_macros(&command_line, "<command-line>", &mut state, &mut output)?;
+    include_file(Path::new(source_file), 0, &mut state, &mut output)?;
+    Ok(render(&output))
+}
+
+fn include_file(
+    path: &Path,
+    line: usize,
+    state: &mut PreprocessorState,
+    output: &mut Vec<PpToken>,
+) -> Result<(), String> {
+    let key = file_key(path);
+    if state.once_files.contains(&key) {
+        return Ok(());
+    }
+    if let Some(guard) = state.include_guards.get(&key) {
+        if state.macros.contains_key(guard) {
+            return Ok(());
+        }
+    }
+    if state.include_stack.len() >= MAX_INCLUDE_DEPTH {
+        return Err(error(state, line, "#include nested too deeply"));
+    }
+
+    let input = fs::read_to_string(path).map_err(|e| {
+        error(
+            state,
+            line,
+            &format!("Can't read {}: {}", path.display(), e),
+        )
+    })?;
+    state.include_stack.push(IncludeFrame {
+        file: path.to_path_buf(),
+        line: 0,
+    });
+    let tokens = tokenize(&splice_lines(&input), state)?;
+    if let Some(guard) = find_include_guard(&tokens) {
+        state.include_guards.insert(key, guard);
+    }
+    preprocess_tokens(tokens, state, output)?;
+    state.include_stack.pop();
+    Ok(())
+}
+
+// Predefined and command line macros are read from directives in a pseudo file
+fn define_macros(
+    directives: &str,
+    file: &str,
+    state: &mut PreprocessorState,
+    output: &mut Vec<PpToken>,
+) -> Result<(), String> {
+    state.include_stack.push(IncludeFrame {
+        file: PathBuf::from(file),
+        line: 0,
+    });
+    let tokens = tokenize(directives, state)?;
+    preprocess_tokens(tokens, state, output)?;
+    state.include_stack.pop();
+    Ok(())
+}
+
+fn preprocess_tokens(
+    tokens: Vec<PpToken>,
+    state: &mut PreprocessorState,
+    output: &mut Vec<PpToken>,
+) -> Result<(), String> {
+    let mut tokens: VecDeque<PpToken> = tokens.into();
+    // Conditionals can't span files
+    let mut conditionals: Vec<Conditional> = Vec::new();
+    while let Some(token) = tokens.pop_front() {
+        // Tokens coming from expansions never start a line so they can't form a directive
+        if token.at_line_start && token.text == "#" {
+            let mut directive: Vec<PpToken> = Vec::new();
+            while tokens.front().is_some_and(|next| !next.at_line_start) {
+                directive.push(tokens.pop_front().unwrap());
+            }
+            state.include_stack.last_mut().unwrap().line = token.line;
+            process_directive(
+                &directive,
+                token.line,
+                &mut tokens,
+                &mut conditionals,
+                state,
+                output,
+            )?;
+        } else if !expand_macro(&token, &mut tokens, state)? {
+            output.push(token);
+        }
+    }
+    match conditionals.last() {
+        Some(conditional) => Err(error(state, conditional.line, "Unterminated #if")),
+        None => Ok(()),
+    }
+}
+
+fn process_directive(
+    directive: &[PpToken],
+    line: usize,
+    tokens: &mut VecDeque<PpToken>,
+    conditionals: &mut Vec<Conditional>,
+    state: &mut PreprocessorState,
+    output: &mut Vec<PpToken>,
+) -> Result<(), String> {
+    // A lone # is the null directive
+    let Some(name) = directive.first() else {
+        return Ok(());
+    };
+    match name.text.as_str() {
+        "include" => include_directive(&directive[1..], line, state, output),
+        "define" => define_directive(&directive[1..], line, state),
+        "undef" => {
+            let name = macro_name(&directive[1..], line, state)?;
+            state.macros.remove(&name);
+            Ok(())
+        }
+        "if" | "ifdef" | "ifndef" => {
+            let taken = match name.text.as_str() {
+                "if" => evaluate_condition(&directive[1..], line, state)?,
+                "ifdef" => is_defined(&macro_name(&directive[1..], line, state)?, state),
+                _ => !is_defined(&macro_name(&directive[1..], line, state)?, state),
+            };
+            conditionals.push(Conditional {
+                line,
+                taken,
+                seen_else: false,
+            });
+            if !taken {
+                skip_group(tokens);
+            }
+            Ok(())
+        }
+        "elif" | "else" => {
+            let Some(conditional) = conditionals.last_mut() else {
+                return Err(error(state, line, &format!("#{} without #if", name.text)));
+            };
+            if conditional.seen_else {
+                return Err(error(state, line, &format!("#{} after #else", name.text)));
+            }
+            conditional.seen_else = name.text == "else";
+            // Once a group was included the other conditions aren't evaluated
+            let taken = !conditional.taken
+                && (name.text == "else" || evaluate_condition(&directive[1..], line, state)?);
+            conditional.taken |= taken;
+            if !taken {
+                skip_group(tokens);
+            }
+            Ok(())
+        }
+        "endif" => match conditionals.pop() {
+            Some(_) => Ok(()),
+            None => Err(error(state, line, "#endif without #if")),
+        },
+        "error" => Err(error(
+            state,
+            line,
+            &format!("#error {}", join_tokens(&directive[1..])),
+        )),
+        "warning" => {
+            let message = format!("warning: #warning {}", join_tokens(&directive[1..]));
+            eprintln!("{}", error(state, line, &message));
+            Ok(())
+        }
+        "pragma" => {
+            if directive.len() == 2 && directive[1].text == "once" {
+                let key = file_key(&state.include_stack.last().unwrap().file);
+                state.once_files.insert(key);
+            }
+            // Other pragmas are ignored
+            Ok(())
+        }
+        _ => Err(error(
+            state,
+            line,
+            &format!("Unsupported preprocessing directive #{}", name.text),
+        )),
+    }
+}
+
+fn include_directive(
+    operand: &[PpToken],
+    line: usize,
+    state: &mut PreprocessorState,
+    output: &mut Vec<PpToken>,
+) -> Result<(), String> {
+    // Any other form is macro expanded first
+    let operand = match operand.first() {
+        Some(token) if token.kind == PpTokenKind::StringLiteral || token.text == "<" => {
+            operand.to_vec()
+        }
+        _ => expand_tokens(operand.to_vec(), state)?,
+    };
+    let (name, quoted) = match operand.as_slice() {
+        [token] if token.kind == PpTokenKind::StringLiteral => {
+            (token.text[1..token.text.len() - 1].to_string(), true)
+        }
+        [open, rest @ .., close] if open.text == "<" && close.text == ">" => {
+            // The header name was split into tokens, glue it back together
+            let mut name = String::new();
+            for (i, token) in rest.iter().enumerate() {
+                if i > 0 && token.leading_space {
+                    name.push(' ');
+                }
+                name.push_str(&token.text);
+            }
+            (name, false)
+        }
+        _ => {
+            return Err(error(
+                state,
+                line,
+                "#include expects \"FILENAME\" or <FILENAME>",
+            ))
+        }
+    };
+
+    let path = find_include_file(&name, quoted, state)
+        .ok_or_else(|| error(state, line, &format!("Can't find include file: {}", name)))?;
+    include_file(&path, line, state, output)
+}
+
+// Quoted names are looked up next to the including file first
+fn find_include_file(name: &str, quoted: bool, state: &PreprocessorState) -> Option<PathBuf> {
+    if Path::new(name).is_absolute() {
+        return Some(PathBuf::from(name)).filter(|path| path.is_file());
+    }
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if quoted {
+        let current_file = &state.include_stack.last().unwrap().file;
+        dirs.push(current_file.parent().unwrap_or(Path::new("")).to_path_buf());
+    }
+    dirs.extend(state.include_dirs.iter().map(PathBuf::from));
+    dirs.push(PathBuf::from(COMPILER_INCLUDE_DIR));
+    dirs.extend(SYSTEM_INCLUDE_DIRS.iter().map(PathBuf::from));
+    dirs.into_iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn file_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn macro_name(
+    operand: &[PpToken],
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<String, String> {
+    match operand.first() {
+        Some(name) if name.text == "defined" => Err(error(
+            state,
+            line,
+            "\"defined\" can't be used as a macro name",
+        )),
+        Some(name) if name.kind == PpTokenKind::Identifier => Ok(name.text.clone()),
+        _ => Err(error(state, line, "Macro names must be identifiers")),
+    }
+}
+
+fn define_directive(
+    operand: &[PpToken],
+    line: usize,
+    state: &mut PreprocessorState,
+) -> Result<(), String> {
+    let name = macro_name(operand, line, state)?;
+    // A function-like macro has the parenthesis right after its name
+    let (params, variadic, body) = match operand.get(1) {
+        Some(paren) if paren.text == "(" && !paren.leading_space => {
+            let (params, variadic, length) = parse_macro_params(&operand[2..], line, state)?;
+            (Some(params), variadic, &operand[2 + length..])
+        }
+        _ => (None, false, &operand[1..]),
+    };
+
+    if body.first().is_some_and(|token| token.text == "##")
+        || body.last().is_some_and(|token| token.text == "##")
+    {
+        return Err(error(
+            state,
+            line,
+            "'##' can't appear at either end of a macro expansion",
+        ));
+    }
+    if let Some(params) = &params {
+        for (i, token) in body.iter().enumerate() {
+            let operand = body.get(i + 1);
+            if token.text == "#" && !operand.is_some_and(|operand| params.contains(&operand.text)) {
+                return Err(error(
+                    state,
+                    line,
+                    "'#' is not followed by a macro parameter",
+                ));
+            }
+        }
+    }
+
+    let mut body = body.to_vec();
+    if let Some(first) = body.first_mut() {
+        first.leading_space = false;
+    }
+    let definition = Macro {
+        params,
+        variadic,
+        body,
+    };
+    state.macros.insert(name, definition);
+    Ok(())
+}
+
+// Returns the parameters, whether the macro is variadic and the tokens used up to the ')'
+fn parse_macro_params(
+    tokens: &[PpToken],
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<(Vec<String>, bool, usize), String> {
+    let mut params: Vec<String> = Vec::new();
+    if tokens.first().is_some_and(|token| token.text == ")") {
+        return Ok((params, false, 1));
+    }
+    let mut i = 0;
+    loop {
+        match tokens.get(i) {
+            Some(token) if token.text == "..." => {
+                params.push("__VA_ARGS__".to_string());
+                return match tokens.get(i + 1) {
+                    Some(token) if token.text == ")" => Ok((params, true, i + 2)),
+                    _ => Err(error(state, line, "Expected ')' after '...'")),
+                };
+            }
+            Some(token) if token.kind == PpTokenKind::Identifier => {
+                if params.contains(&token.text) {
+                    return Err(error(
+                        state,
+                        line,
+                        &format!("Duplicate macro parameter: {}", token.text),
+                    ));
+                }
+                params.push(token.text.clone());
+            }
+            _ => return Err(error(state, line, "Expected a macro parameter name")),
+        }
+        match tokens.get(i + 1) {
+            Some(token) if token.text == ")" => return Ok((params, false, i + 2)),
+            Some(token) if token.text == "," => i += 2,
+            _ => {
+                return Err(error(
+                    state,
+                    line,
+                    "Expected ',' or ')' in macro parameters",
+                ))
+            }
+        }
+    }
+}
+
+// Replaces a macro name with its expansion at the front of the input, so the expansion is
+// scanned again together with the rest of the input
+fn expand_macro(
+    token: &PpToken,
+    tokens: &mut VecDeque<PpToken>,
+    state: &PreprocessorState,
+) -> Result<bool, String> {
+    if token.kind != PpTokenKind::Identifier || token.hide_set.contains(&token.text) {
+        return Ok(false);
+    }
+    let expansion = match token.text.as_str() {
+        "__FILE__" => {
+            let file = state
+                .include_stack
+                .last()
+                .unwrap()
+                .file
+                .display()
+                .to_string();
+            let file = PpToken {
+                kind: PpTokenKind::StringLiteral,
+                text: format!("\"{}\"", escape(&file)),
+                ..token.clone()
+            };
+            vec![file]
+        }
+        "__LINE__" => vec![make_number(token.line as u64, token)],
+        _ => {
+            let Some(definition) = state.macros.get(&token.text) else {
+                return Ok(false);
+            };
+            // The hide set of an invocation is the one its name and ')' have in common
+            let (args, mut hide_set) = match &definition.params {
+                None => (Vec::new(), token.hide_set.clone()),
+                Some(_) if tokens.front().is_none_or(|next| next.text != "(") => return Ok(false),
+                Some(_) => {
+                    let (args, closing) = collect_arguments(token, definition, tokens, state)?;
+                    let hide_set = token.hide_set.intersection(&closing.hide_set);
+                    (args, hide_set.cloned().collect())
+                }
+            };
+            hide_set.insert(token.text.clone());
+            let mut expansion = substitute(definition, &args, state)?;
+            for expanded in expansion.iter_mut() {
+                expanded.hide_set.extend(hide_set.iter().cloned());
+            }
+            expansion
+        }
+    };
+
+    for (i, mut expanded) in expansion.into_iter().enumerate().rev() {
+        expanded.line = token.line;
+        expanded.at_line_start = false;
+        if i == 0 {
+            expanded.leading_space = token.leading_space;
+        }
+        tokens.push_front(expanded);
+    }
+    Ok(true)
+}
+
+// Returns the arguments and the closing parenthesis of an invocation
+fn collect_arguments(
+    name: &PpToken,
+    definition: &Macro,
+    tokens: &mut VecDeque<PpToken>,
+    state: &PreprocessorState,
+) -> Result<(Vec<Vec<PpToken>>, PpToken), String> {
+    let params = definition.params.as_ref().unwrap();
+    tokens.pop_front();
+    let mut args: Vec<Vec<PpToken>> = vec![Vec::new()];
+    let mut depth = 0;
+    let closing = loop {
+        let Some(token) = tokens.pop_front() else {
+            return Err(error(
+                state,
+                name.line,
+                &format!("Unterminated argument list invoking macro {}", name.text),
+            ));
+        };
+        match token.text.as_str() {
+            ")" if depth == 0 => break token,
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            // The variadic arguments keep their commas
+            "," if depth == 0 && !(definition.variadic && args.len() == params.len()) => {
+                args.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        args.last_mut().unwrap().push(token);
+    };
+
+    // A macro without parameters is invoked with a single empty argument
+    if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    if definition.variadic && args.len() == params.len() - 1 {
+        args.push(Vec::new());
+    }
+    if args.len() != params.len() {
+        return Err(error(
+            state,
+            name.line,
+            &format!(
+                "Macro {} expects {} arguments, but {} were given",
+                name.text,
+                params.len(),
+                args.len()
+            ),
+        ));
+    }
+    Ok((args, closing))
+}
+
+// Arguments are fully expanded before substitution unless they are operands of # or ##
+fn substitute(
+    definition: &Macro,
+    args: &[Vec<PpToken>],
+    state: &PreprocessorState,
+) -> Result<Vec<PpToken>, String> {
+    let body = &definition.body;
+    let param_index = |token: &PpToken| match &definition.params {
+        Some(params) if token.kind == PpTokenKind::Identifier => {
+            params.iter().position(|param| *param == token.text)
+        }
+        _ => None,
+    };
+    let mut result: Vec<PpToken> = Vec::new();
+    // An empty left operand of ## isn't pasted with anything
+    let mut placemarker = false;
+    let mut i = 0;
+    while i < body.len() {
+        let token = &body[i];
+        let next = body.get(i + 1);
+
+        if token.text == "#" && definition.params.is_some() {
+            let arg = &args[param_index(next.unwrap()).unwrap()];
+            result.push(stringize(arg, token));
+            i += 2;
+            continue;
+        }
+
+        // The GNU extension , ## __VA_ARGS__ drops the comma when there are no variadic arguments
+        if token.text == ","
+            && definition.variadic
+            && next.is_some_and(|next| next.text == "##")
+            && body
+                .get(i + 2)
+                .is_some_and(|operand| operand.text == "__VA_ARGS__")
+        {
+            let variadic_args = args.last().unwrap();
+            if !variadic_args.is_empty() {
+                result.push(token.clone());
+                result.extend(variadic_args.iter().cloned());
+            }
+            i += 3;
+            continue;
+        }
+
+        if token.text == "##" {
+            let operand = next.unwrap();
+            let operand = match param_index(operand) {
+                Some(index) => args[index].clone(),
+                None => vec![operand.clone()],
+            };
+            // An empty operand leaves the other side unchanged
+            match operand.split_first() {
+                Some(_) if placemarker => result.extend(operand.iter().cloned()),
+                Some((first, rest)) => {
+                    let left = result.pop().unwrap();
+                    result.push(paste(&left, first, state)?);
+                    result.extend(rest.iter().cloned());
+                }
+                None => {}
+            }
+            placemarker = placemarker && operand.is_empty();
+            i += 2;
+            continue;
+        }
+
+        match param_index(token) {
+            Some(index) if next.is_some_and(|next| next.text == "##") => {
+                placemarker = args[index].is_empty();
+                result.extend(args[index].iter().cloned());
+            }
+            Some(index) => {
+                let mut expanded = expand_tokens(args[index].clone(), state)?;
+                if let Some(first) = expanded.first_mut() {
+                    first.leading_space = token.leading_space;
+                }
+                result.extend(expanded);
+            }
+            None => result.push(token.clone()),
+        }
+        i += 1;
+    }
+    Ok(result)
+}
+
+fn expand_tokens(tokens: Vec<PpToken>, state: &PreprocessorState) -> Result<Vec<PpToken>, String> {
+    let mut tokens: VecDeque<PpToken> = tokens.into();
+    let mut output: Vec<PpToken> = Vec::new();
+    while let Some(token) = tokens.pop_front() {
+        if !expand_macro(&token, &mut tokens, state)? {
+            output.push(token);
+        }
+    }
+    Ok(output)
+}
+
+// Only quotes and backslashes inside literals are escaped, the lexer unescapes them again
+fn stringize(tokens: &[PpToken], hash: &PpToken) -> PpToken {
+    let mut text = String::from("\"");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.leading_space {
+            text.push(' ');
+        }
+        match token.kind {
+            PpTokenKind::StringLiteral | PpTokenKind::CharConstant => {
+                text.push_str(&escape(&token.text))
+            }
+            _ => text.push_str(&token.text),
+        }
+    }
+    text.push('"');
+    PpToken {
+        kind: PpTokenKind::StringLiteral,
+        text,
+        ..hash.clone()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn paste(left: &PpToken, right: &PpToken, state: &PreprocessorState) -> Result<PpToken, String> {
+    let text = format!("{}{}", left.text, right.text);
+    let pasted = tokenize(&text, state)?;
+    if pasted.len() != 1 || pasted[0].text != text {
+        return Err(error(
+            state,
+            left.line,
+            &format!(
+                "Pasting \"{}\" and \"{}\" doesn't give a valid preprocessing token",
+                left.text, right.text
+            ),
+        ));
+    }
+    Ok(PpToken {
+        kind: pasted[0].kind.clone(),
+        text,
+        ..left.clone()
+    })
+}
+
+// Once the guard of a file wrapped in #ifndef GUARD / #define GUARD / #endif is defined, including
+// the file again has no effect
+fn find_include_guard(tokens: &[PpToken]) -> Option<String> {
+    let lines = split_lines(tokens);
+    let texts = |line: &[PpToken]| line.iter().map(|t| t.text.clone()).collect::<Vec<String>>();
+    let first = texts(lines.first()?);
+    let second = texts(lines.get(1)?);
+    if first.len() != 3 || first[..2] != ["#", "ifndef"] || second.len() < 3 {
+        return None;
+    }
+    if second[..2] != ["#", "define"] || second[2] != first[2] {
+        return None;
+    }
+    // The #endif that closes the #ifndef has to be the last line
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if line.len() < 2 || line[0].text != "#" {
+            continue;
+        }
+        match line[1].text.as_str() {
+            "if" | "ifdef" | "ifndef" => depth += 1,
+            "elif" | "else" if depth == 1 => return None,
+            "endif" => {
+                depth -= 1;
+                if depth == 0 {
+                    return (i == lines.len() - 1).then(|| first[2].clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_lines(tokens: &[PpToken]) -> Vec<&[PpToken]> {
+    let mut lines: Vec<&[PpToken]> = Vec::new();
+    let mut start = 0;
+    for i in 1..=tokens.len() {
+        if i == tokens.len() || tokens[i].at_line_start {
+            lines.push(&tokens[start..i]);
+            start = i;
+        }
+    }
+    lines
+}
+
+// Stops at the #elif, #else or #endif that ends the current group
+fn skip_group(tokens: &mut VecDeque<PpToken>) {
+    let mut depth = 0;
+    while let Some(token) = tokens.front() {
+        let directive = match tokens.get(1) {
+            Some(name) if token.at_line_start && token.text == "#" && !name.at_line_start => {
+                name.text.as_str()
+            }
+            _ => "",
+        };
+        match directive {
+            "if" | "ifdef" | "ifndef" => depth += 1,
+            "elif" | "else" | "endif" if depth == 0 => return,
+            "endif" => depth -= 1,
+            _ => {}
+        }
+        tokens.pop_front();
+    }
+}
+
+fn is_defined(name: &str, state: &PreprocessorState) -> bool {
+    state.macros.contains_key(name) || name == "__FILE__" || name == "__LINE__"
+}
+
+// defined is resolved before macro expansion, identifiers left after expansion are 0
+fn evaluate_condition(
+    tokens: &[PpToken],
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<bool, String> {
+    let mut resolved: Vec<PpToken> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].text != "defined" {
+            resolved.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+        let (name, length) = match (tokens.get(i + 1), tokens.get(i + 2), tokens.get(i + 3)) {
+            (Some(open), Some(name), Some(close)) if open.text == "(" && close.text == ")" => {
+                (name, 4)
+            }
+            (Some(name), ..) => (name, 2),
+            _ => (&tokens[i], 1),
+        };
+        if name.kind != PpTokenKind::Identifier || name.text == "defined" {
+            return Err(error(state, line, "Expected a macro name after defined"));
+        }
+        resolved.push(make_number(
+            is_defined(&name.text, state) as u64,
+            &tokens[i],
+        ));
+        i += length;
+    }
+
+    let expanded = expand_tokens(resolved, state)?;
+    if expanded.is_empty() {
+        return Err(error(state, line, "#if with no expression"));
+    }
+    let mut expression = expanded
+        .into_iter()
+        .map(|token| match token.kind {
+            PpTokenKind::Identifier => make_number(0, &token),
+            _ => token,
+        })
+        .peekable();
+    let condition = evaluate_expression(&mut expression, MAX_PRECEDENCE, true, line, state)?;
+    if let Some(token) = expression.next() {
+        return Err(error(
+            state,
+            line,
+            &format!("Unexpected token in #if: {}", token.text),
+        ));
+    }
+    Ok(condition.value != 0)
+}
+
+// Operands that aren't evaluated can't fail, as in #if b != 0 && a / b
+fn evaluate_expression(
+    tokens: &mut Peekable<impl Iterator<Item = PpToken>>,
+    max_precedence: u8,
+    evaluated: bool,
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<PpValue, String> {
+    let mut left = evaluate_factor(tokens, evaluated, line, state)?;
+    while let Some(precedence) = tokens.peek().and_then(binary_precedence) {
+        if precedence >= max_precedence {
+            break;
+        }
+        let op = tokens.next().unwrap().text;
+        left = match op.as_str() {
+            "&&" => {
+                let right = evaluate_expression(
+                    tokens,
+                    precedence,
+                    evaluated && left.value != 0,
+                    line,
+                    state,
+                )?;
+                make_bool(left.value != 0 && right.value != 0)
+            }
+            "||" => {
+                let right = evaluate_expression(
+                    tokens,
+                    precedence,
+                    evaluated && left.value == 0,
+                    line,
+                    state,
+                )?;
+                make_bool(left.value != 0 || right.value != 0)
+            }
+            // Right to left associativity
+            "?" => {
+                let condition = left.value != 0;
+                let then = evaluate_expression(
+                    tokens,
+                    MAX_PRECEDENCE,
+                    evaluated && condition,
+                    line,
+                    state,
+                )?;
+                if tokens.next().is_none_or(|token| token.text != ":") {
+                    return Err(error(state, line, "Expected ':' in #if"));
+                }
+                let otherwise = evaluate_expression(
+                    tokens,
+                    precedence + 1,
+                    evaluated && !condition,
+                    line,
+                    state,
+                )?;
+                PpValue {
+                    value: if condition {
+                        then.value
+                    } else {
+                        otherwise.value
+                    },
+                    unsigned: then.unsigned || otherwise.unsigned,
+                }
+            }
+            _ => {
+                let right = evaluate_expression(tokens, precedence, evaluated, line, state)?;
+                evaluate_binary(&op, left, right, evaluated, line, state)?
+            }
+        };
+    }
+    Ok(left)
+}
+
+fn evaluate_factor(
+    tokens: &mut Peekable<impl Iterator<Item = PpToken>>,
+    evaluated: bool,
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<PpValue, String> {
+    let Some(token) = tokens.next() else {
+        return Err(error(state, line, "Expected an expression in #if"));
+    };
+    match token.text.as_str() {
+        "(" => {
+            let value = evaluate_expression(tokens, MAX_PRECEDENCE, evaluated, line, state)?;
+            match tokens.next() {
+                Some(token) if token.text == ")" => Ok(value),
+                _ => Err(error(state, line, "Expected ')' in #if")),
+            }
+        }
+        "+" => evaluate_factor(tokens, evaluated, line, state),
+        "-" => {
+            let value = evaluate_factor(tokens, evaluated, line, state)?;
+            Ok(PpValue {
+                value: value.value.wrapping_neg(),
+                ..value
+            })
+        }
+        "~" => {
+            let value = evaluate_factor(tokens, evaluated, line, state)?;
+            Ok(PpValue {
+                value: !value.value,
+                ..value
+            })
+        }
+        "!" => Ok(make_bool(
+            evaluate_factor(tokens, evaluated, line, state)?.value == 0,
+        )),
+        _ => match token.kind {
+            PpTokenKind::Number => parse_integer(&token.text).ok_or_else(|| {
+                error(
+                    state,
+                    line,
+                    &format!("Invalid integer constant in #if: {}", token.text),
+                )
+            }),
+            // Character constants have type int, so plain chars are signed
+            PpTokenKind::CharConstant if token.text.len() > 2 => {
+                let value = lexer::unescape(&token.text[1..token.text.len() - 1])[0];
+                Ok(PpValue {
+                    value: value as i8 as u64,
+                    unsigned: false,
+                })
+            }
+            _ => Err(error(
+                state,
+                line,
+                &format!("Unexpected token in #if: {}", token.text),
+            )),
+        },
+    }
+}
+
+fn evaluate_binary(
+    op: &str,
+    left: PpValue,
+    right: PpValue,
+    evaluated: bool,
+    line: usize,
+    state: &PreprocessorState,
+) -> Result<PpValue, String> {
+    // Signed operations wrap around like gcc's, which only warns about overflow
+    let unsigned = left.unsigned || right.unsigned;
+    let (l, r) = (left.value, right.value);
+    let (signed_l, signed_r) = (l as i64, r as i64);
+    let value = match op {
+        "/" | "%" if r == 0 => {
+            if evaluated {
+                return Err(error(state, line, "Division by zero in #if"));
+            }
+            0
+        }
+        "*" => l.wrapping_mul(r),
+        "/" if unsigned => l / r,
+        "/" => signed_l.wrapping_div(signed_r) as u64,
+        "%" if unsigned => l % r,
+        "%" => signed_l.wrapping_rem(signed_r) as u64,
+        "+" => l.wrapping_add(r),
+        "-" => l.wrapping_sub(r),
+        // Shifts keep the type of the left operand
+        "<<" => {
+            let value = l.checked_shl(r as u32).unwrap_or(0);
+            return Ok(PpValue { value, ..left });
+        }
+        ">>" if left.unsigned => {
+            return Ok(PpValue {
+                value: l.checked_shr(r as u32).unwrap_or(0),
+                ..left
+            })
+        }
+        ">>" => {
+            let value = signed_l.checked_shr(r as u32).unwrap_or(signed_l >> 63) as u64;
+            return Ok(PpValue { value, ..left });
+        }
+        "<" | "<=" | ">" | ">=" => {
+            let ordering = if unsigned {
+                l.cmp(&r)
+            } else {
+                signed_l.cmp(&signed_r)
+            };
+            return Ok(make_bool(match op {
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }));
+        }
+        "==" => return Ok(make_bool(l == r)),
+        "!=" => return Ok(make_bool(l != r)),
+        "&" => l & r,
+        "^" => l ^ r,
+        _ => l | r,
+    };
+    Ok(PpValue { value, unsigned })
+}
+
+fn binary_precedence(token: &PpToken) -> Option<u8> {
+    match token.text.as_str() {
+        "*" | "/" | "%" => Some(30),
+        "+" | "-" => Some(40),
+        "<<" | ">>" => Some(50),
+        "<" | "<=" | ">" | ">=" => Some(60),
+        "==" | "!=" => Some(70),
+        "&" => Some(80),
+        "^" => Some(90),
+        "|" => Some(100),
+        "&&" => Some(110),
+        "||" => Some(120),
+        "?" => Some(130),
+        _ => None,
+    }
+}
+
+// Decimal, octal, hexadecimal and binary constants with their u and l suffixes
+fn parse_integer(text: &str) -> Option<PpValue> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let suffix = &text[digits.len()..];
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        (binary, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits, 10)
+    };
+    let value = u64::from_str_radix(digits, radix).ok()?;
+    Some(PpValue {
+        value,
+        // Too large for intmax_t
+        unsigned: suffix.contains(['u', 'U']) || value > i64::MAX as u64,
+    })
+}
+
+fn make_bool(value: bool) -> PpValue {
+    PpValue {
+        value: value as u64,
+        unsigned: false,
+    }
+}
+
+fn make_number(value: u64, token: &PpToken) -> PpToken {
+    PpToken {
+        kind: PpTokenKind::Number,
+        text: value.to_string(),
+        ..token.clone()
+    }
+}
+
+fn join_tokens(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.leading_space {
+            text.push(' ');
+        }
+        text.push_str(&token.text);
+    }
+    text
+}
+
+// Tokens are always separated so the lexer never merges them
+fn render(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            text.push(if token.at_line_start { '\n' } else { ' ' });
+        }
+        text.push_str(&token.text);
+    }
+    text.push('\n');
+    text
+}
+
+// The diagnostic points at the current file and lists the files that included it
+fn error(state: &PreprocessorState, line: usize, message: &str) -> String {
+    let mut frames = state.include_stack.iter().rev();
+    let mut error = match frames.next() {
+        Some(frame) => format!("{}:{}: {}", frame.file.display(), line, message),
+        None => message.to_string(),
+    };
+    for frame in frames {
+        error.push_str(&format!(
+            "\n    included from {}:{}",
+            frame.file.display(),
+            frame.line
+        ));
+    }
+    error
+}
+
+// Backslash-newline joins lines, the removed newlines come back after the joined line so
+// the following lines keep their numbers
+fn splice_lines(input: &str) -> String {
+    let mut output = String::new();
+    let mut removed = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                removed += 1;
+            }
+            '\\' if chars.peek() == Some(&'\r') => {
+                chars.next();
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                    removed += 1;
+                } else {
+                    output.push_str("\\\r");
+                }
+            }
+            '\n' => {
+                output.push('\n');
+                output.push_str(&"\n".repeat(removed));
+                removed = 0;
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+fn tokenize(input: &str, state: &PreprocessorState) -> Result<Vec<PpToken>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let starts_with = |position: usize, prefix: &str| {
+        prefix
+            .chars()
+            .enumerate()
+            .all(|(i, c)| chars.get(position + i) == Some(&c))
+    };
+    let mut tokens: Vec<PpToken> = Vec::new();
+    let mut position = 0;
+    let mut line = 1;
+    let mut at_line_start = true;
+    let mut leading_space = false;
+
+    while position < chars.len() {
+        let c = chars[position];
+        if c == '\n' {
+            position += 1;
+            line += 1;
+            at_line_start = true;
+            leading_space = false;
+            continue;
+        }
+        if c.is_whitespace() {
+            position += 1;
+            leading_space = true;
+            continue;
+        }
+        if starts_with(position, "//") {
+            while position < chars.len() && chars[position] != '\n' {
+                position += 1;
+            }
+            leading_space = true;
+            continue;
+        }
+        // A block comment is a single space even when it spans lines
+        if starts_with(position, "/*") {
+            let start_line = line;
+            position += 2;
+            while !starts_with(position, "*/") {
+                match chars.get(position) {
+                    Some('\n') => line += 1,
+                    Some(_) => {}
+                    None => return Err(error(state, start_line, "Unterminated comment")),
+                }
+                position += 1;
+            }
+            position += 2;
+            leading_space = true;
+            continue;
+        }
+
+        let start = position;
+        let next = chars.get(position + 1).copied().unwrap_or('\0');
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            while position < chars.len()
+                && (chars[position].is_ascii_alphanumeric() || chars[position] == '_')
+            {
+                position += 1;
+            }
+            PpTokenKind::Identifier
+        } else if c.is_ascii_digit() || (c == '.' && next.is_ascii_digit()) {
+            position += 1;
+            while position < chars.len() {
+                let c = chars[position];
+                if "eEpP".contains(c) && matches!(chars.get(position + 1), Some('+' | '-')) {
+                    position += 2;
+                } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                    position += 1;
+                } else {
+                    break;
+                }
+            }
+            PpTokenKind::Number
+        } else if c == '\'' || c == '"' {
+            let mut end = position + 1;
+            let closed = loop {
+                match chars.get(end) {
+                    Some(&quote) if quote == c => break true,
+                    Some('\\') => end += 2,
+                    None | Some('\n') => break false,
+                    Some(_) => end += 1,
+                }
+            };
+            // A lone quote is fine in skipped groups and #error, elsewhere the lexer rejects it
+            if !closed {
+                position += 1;
+                PpTokenKind::Other
+            } else if c == '\'' {
+                position = end + 1;
+                PpTokenKind::CharConstant
+            } else {
+                position = end + 1;
+                PpTokenKind::StringLiteral
+            }
+        } else if let Some(punctuator) = PUNCTUATORS.iter().find(|p| starts_with(position, p)) {
+            position += punctuator.len();
+            PpTokenKind::Punctuator
+        } else {
+            position += 1;
+            if c.is_ascii_punctuation() {
+                PpTokenKind::Punctuator
+            } else {
+                PpTokenKind::Other
+            }
+        };
+
+        tokens.push(PpToken {
+            kind,
+            text: chars[start..position].iter().collect(),
+            line,
+            at_line_start,
+            leading_space,
+            hide_set: HashSet::new(),
+        });
+        at_line_start = false;
+        leading_space = false;
+    }
+    Ok(tokens)
+}
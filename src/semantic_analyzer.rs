@@ -1,27 +1,92 @@
 use crate::parser::*;
-
-#[derive(Clone, Copy, PartialEq)]
-enum InStatement {
-    Other,
-    Loop,
-    Switch,
-}
+use std::collections::HashMap;
 
 pub fn analyze_semantics(mut program: Program) -> Result<Program, String> {
-    // for function in program.functions.iter_mut() {
-    //     if let Some(body) = &mut function.body {
-    //         label_block(body, None)?;
-    //     }
-    // }
+    for declaration in program.declarations.iter_mut() {
+        if let Declaration::FuncDecl(function) = declaration {
+            if let Some(body) = &mut function.body {
+                // Labels are visible across the whole function so they are collected first
+                let mut goto_labels: HashMap<String, String> = HashMap::new();
+                for block_item in body.iter_mut() {
+                    if let BlockItem::S(statement) = block_item {
+                        collect_goto_labels(statement, &function.name, &mut goto_labels)?;
+                    }
+                }
+                label_block(body, None, None, None, &goto_labels)?;
+            }
+        }
+    }
     Ok(program)
 }
 
-fn label_block(block: &mut Block, label: Option<String>) -> Result<(), String> {
+fn collect_goto_labels(
+    statement: &mut Statement,
+    function_name: &str,
+    goto_labels: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    match statement {
+        Statement::Labeled(label, body) => {
+            if goto_labels.contains_key(label) {
+                return Err(format!("Duplicate label: {}", label));
+            }
+            let unique_label = format!("goto.{}.{}", function_name, label);
+            goto_labels.insert(label.clone(), unique_label.clone());
+            *label = unique_label;
+            collect_goto_labels(body, function_name, goto_labels)?;
+        }
+        Statement::Compound(block) => {
+            for block_item in block {
+                if let BlockItem::S(statement) = block_item {
+                    collect_goto_labels(statement, function_name, goto_labels)?;
+                }
+            }
+        }
+        Statement::If(_, if_block, else_block) => {
+            collect_goto_labels(if_block, function_name, goto_labels)?;
+            if let Some(else_body) = else_block {
+                collect_goto_labels(else_body, function_name, goto_labels)?;
+            }
+        }
+        Statement::While(_, body, _)
+        | Statement::DoWhile(body, _, _)
+        | Statement::For(_, _, _, body, _) => {
+            collect_goto_labels(body, function_name, goto_labels)?;
+        }
+        Statement::Switch(_, body, _, _)
+        | Statement::Case(_, body, _)
+        | Statement::Default(body, _) => {
+            collect_goto_labels(body, function_name, goto_labels)?;
+        }
+        Statement::Return(_)
+        | Statement::Expression(_)
+        | Statement::Null
+        | Statement::Break(_)
+        | Statement::Continue(_)
+        | Statement::Goto(_) => {}
+    }
+    Ok(())
+}
+
+// Break jumps out of the innermost loop or switch while continue skips switches, the cases of the
+// innermost switch are collected as they are found
+fn label_block(
+    block: &mut Block,
+    break_label: Option<String>,
+    continue_label: Option<String>,
+    mut cases: Option<&mut Vec<Case>>,
+    goto_labels: &HashMap<String, String>,
+) -> Result<(), String> {
     for block_item in block {
         match block_item {
             BlockItem::D(_) => {}
             BlockItem::S(statement) => {
-                label_statement(statement, label.clone(), InStatement::Other)?;
+                label_statement(
+                    statement,
+                    break_label.clone(),
+                    continue_label.clone(),
+                    cases.as_deref_mut(),
+                    goto_labels,
+                )?;
             }
         }
     }
@@ -29,64 +94,128 @@ fn label_block(block: &mut Block, label: Option<String>) -> Result<(), String> {
 }
 fn label_statement(
     statement: &mut Statement,
-    label: Option<String>,
-    in_statement: InStatement,
+    break_label: Option<String>,
+    continue_label: Option<String>,
+    mut cases: Option<&mut Vec<Case>>,
+    goto_labels: &HashMap<String, String>,
 ) -> Result<(), String> {
     match statement {
         Statement::Compound(block) => {
-            label_block(block, label)?;
+            label_block(block, break_label, continue_label, cases, goto_labels)?;
         }
         Statement::If(_, if_block, else_block) => {
-            label_statement(if_block, label.clone(), in_statement)?;
+            label_statement(
+                if_block,
+                break_label.clone(),
+                continue_label.clone(),
+                cases.as_deref_mut(),
+                goto_labels,
+            )?;
             if let Some(else_body) = else_block {
-                label_statement(else_body, label, in_statement)?;
+                label_statement(else_body, break_label, continue_label, cases, goto_labels)?;
             }
         }
         Statement::While(_, body, label_opt) => {
             let new_label = make_label_name("while");
-            label_statement(body, Some(new_label.clone()), InStatement::Loop)?;
+            label_loop_body(body, &new_label, cases, goto_labels)?;
             *label_opt = Some(new_label);
         }
         Statement::DoWhile(body, _, label_opt) => {
             let new_label = make_label_name("do_while");
-            label_statement(body, Some(new_label.clone()), InStatement::Loop)?;
+            label_loop_body(body, &new_label, cases, goto_labels)?;
             *label_opt = Some(new_label);
         }
         Statement::For(_, _, _, body, label_opt) => {
             let new_label = make_label_name("for");
-            label_statement(body, Some(new_label.clone()), InStatement::Loop)?;
+            label_loop_body(body, &new_label, cases, goto_labels)?;
             *label_opt = Some(new_label);
         }
         Statement::Break(label_opt) => {
-            if label.is_none() {
-                return Err("Break statement outside of loop".to_string());
+            if break_label.is_none() {
+                return Err("Break statement outside of loop or switch".to_string());
             }
-            *label_opt = label;
+            *label_opt = break_label;
         }
         Statement::Continue(label_opt) => {
-            if label.is_none() || in_statement == InStatement::Switch {
+            if continue_label.is_none() {
                 return Err("Continue statement outside of loop".to_string());
             }
-            *label_opt = label;
+            *label_opt = continue_label;
         }
         Statement::Return(_) => {}
         Statement::Expression(_) => {}
         Statement::Null => {}
-        Statement::Switch(_, cases, default, label_opt) => {
+        Statement::Goto(label) => match goto_labels.get(label) {
+            Some(unique_label) => *label = unique_label.clone(),
+            None => return Err(format!("Undefined label: {}", label)),
+        },
+        Statement::Labeled(_, body) => {
+            label_statement(body, break_label, continue_label, cases, goto_labels)?;
+        }
+        Statement::Switch(_, body, switch_cases, label_opt) => {
             let new_label = make_label_name("switch");
-            for case in cases {
-                label_statement(&mut case.body, Some(new_label.clone()), InStatement::Switch)?;
-            }
-            if let Some(default) = default {
-                label_statement(&mut *default, Some(new_label.clone()), InStatement::Switch)?;
+            label_statement(
+                body,
+                Some(new_label.clone()),
+                continue_label,
+                Some(switch_cases),
+                goto_labels,
+            )?;
+            *label_opt = Some(new_label);
+        }
+        Statement::Case(value, body, label_opt) => {
+            let value = match value.kind {
+                ExpressionKind::Constant(Const::Long(value)) => value,
+                _ => panic!("Case values are resolved to constants"),
+            };
+            let switch_cases = match cases.as_deref_mut() {
+                Some(switch_cases) => switch_cases,
+                None => return Err("Case statement outside of switch".to_string()),
+            };
+            let new_label = make_label_name("case");
+            switch_cases.push(Case {
+                value: Some(value),
+                label: new_label.clone(),
+            });
+            *label_opt = Some(new_label);
+            label_statement(body, break_label, continue_label, cases, goto_labels)?;
+        }
+        Statement::Default(body, label_opt) => {
+            let switch_cases = match cases.as_deref_mut() {
+                Some(switch_cases) => switch_cases,
+                None => return Err("Default statement outside of switch".to_string()),
+            };
+            if switch_cases.iter().any(|case| case.value.is_none()) {
+                return Err("Duplicate default statement in switch".to_string());
             }
+            let new_label = make_label_name("default");
+            switch_cases.push(Case {
+                value: None,
+                label: new_label.clone(),
+            });
             *label_opt = Some(new_label);
+            label_statement(body, break_label, continue_label, cases, goto_labels)?;
         }
     }
 
     Ok(())
 }
 
+fn label_loop_body(
+    body: &mut Statement,
+    loop_label: &str,
+    cases: Option<&mut Vec<Case>>,
+    goto_labels: &HashMap<String, String>,
+) -> Result<(), String> {
+    label_statement(
+        body,
+        Some(loop_label.to_string()),
+        Some(loop_label.to_string()),
+        cases,
+        goto_labels,
+    )
+}
+
 static mut LABEL_COUNTER: i64 = -1;
 
 fn make_label_name(prefix: &str) -> String {
@@ -1,4 +1,7 @@
-use crate::parser;
+use crate::parser::{self, Const};
+use crate::type_checker::{
+    get_struct_entry, IdentifierAttr, InitialValue, StaticInit, SymbolEntry, SymbolTable, Type,
+};
 
 #[derive(Debug)]
 pub struct Program {
@@ -13,16 +16,22 @@ pub enum TopLevel {
         params: Vec<String>,
         instructions: Vec<Instruction>,
     },
-    StaticVariable{
+    StaticVariable {
         identifier: String,
         global: bool,
-        init: i64
+        var_type: Type,
+        init: Vec<StaticInit>,
+    },
+    StaticConstant {
+        identifier: String,
+        constant_type: Type,
+        init: StaticInit,
     },
 }
 
 #[derive(Debug)]
 pub enum Instruction {
-    Return(Val),
+    Return(Option<Val>),
     Unary(UnaryOperator, Val, Val),        // op, src, dst
     Binary(BinaryOperator, Val, Val, Val), // op, src1, src2, dst
     Copy(Val, Val),                        // src, dst
@@ -31,15 +40,39 @@ pub enum Instruction {
     JumpIfNotZero(Val, String),            // condition, target
     JumpIfEqual(Val, Val, String),         // value1, value2, target (vs edx)
     Label(String),                         // identifier
-    FunctionCall(String, Vec<Val>, Val),   // function name, args, destination
+    FunctionCall(String, Vec<Val>, Option<Val>), // function name, args, destination
+    IndirectCall(Val, Vec<Val>, Option<Val>),    // function pointer, args, destination
+    SignExtend(Val, Val),                  // src, dst
+    Truncate(Val, Val),                    // src, dst
+    ZeroExtend(Val, Val),                  // src, dst
+    DoubleToInt(Val, Val),                 // src, dst
+    DoubleToUInt(Val, Val),                // src, dst
+    IntToDouble(Val, Val),                 // src, dst
+    UIntToDouble(Val, Val),                // src, dst
+    GetAddress(Val, Val),                  // src, dst
+    Load(Val, Val),                        // src pointer, dst
+    Store(Val, Val),                       // src, dst pointer
+    AddPtr(Val, Val, i64, Val),            // pointer, index, scale, dst
+    CopyToOffset(Val, String, i64),        // src, dst identifier, offset
+    CopyFromOffset(String, i64, Val),      // src identifier, offset, dst
+    VaStart(Val),                          // va_list pointer
+    VaArg(Val, Val),                       // va_list pointer, dst
 }
 
 #[derive(Debug, Clone)]
 pub enum Val {
-    Constant(i64),
+    Constant(Const),
     Var(String),
 }
 
+// An lvalue either names an object directly, part of an object, or goes through a pointer that
+// still has to be followed
+enum ExpressionResult {
+    PlainOperand(Val),
+    SubObject(String, i64), // base identifier, offset
+    DereferencedPointer(Val),
+}
+
 #[derive(Debug)]
 pub enum UnaryOperator {
     Negate,
@@ -67,73 +100,138 @@ pub enum BinaryOperator {
     GreaterOrEqual,
 }
 
-pub fn generate_tacky(program: parser::Program) -> Program {
-    // let mut tacky_program = Program {
-    //     function: FunctionDefinition {
-    //         identifier: program.function.identifier,
-    //         instructions: Vec::new(),
-    //     },
-    // };
-
+pub fn generate_tacky(program: parser::Program, symbol_table: &mut SymbolTable) -> Program {
     let mut tacky_program = Program {
-        functions: Vec::new(),
+        declarations: Vec::new(),
     };
 
-    // for function in program.functions {
-    //     if let Some(body) = function.body {
-    //         let mut tacky_function = FunctionDefinition {
-    //             identifier: function.name,
-    //             params: function.params,
-    //             instructions: Vec::new(),
-    //         };
-    //         emit_tacky_block(body, &mut tacky_function.instructions);
-    //         tacky_function.instructions.push(Instruction::Return(Val::Constant(0)));
-    //         tacky_program.functions.push(tacky_function);
-    //     }
-    // }
+    for declaration in program.declarations {
+        if let parser::Declaration::FuncDecl(function) = declaration {
+            if let Some(body) = function.body {
+                let global = match symbol_table.get(&function.name) {
+                    Some(SymbolEntry {
+                        identifier_attrs: IdentifierAttr::FunAttr(_, global),
+                        ..
+                    }) => *global,
+                    _ => true,
+                };
+                let mut instructions: Vec<Instruction> = Vec::new();
+                emit_tacky_block(body, &mut instructions, symbol_table);
+                let returns_void = matches!(
+                    &symbol_table[&function.name].sym_type,
+                    Type::Function(_, return_type, _) if **return_type == Type::Void
+                );
+                instructions.push(Instruction::Return(if returns_void {
+                    None
+                } else {
+                    Some(Val::Constant(Const::Int(0)))
+                }));
+                tacky_program.declarations.push(TopLevel::Function {
+                    identifier: function.name,
+                    global,
+                    params: function.params,
+                    instructions,
+                });
+            }
+        }
+    }
+
+    // Variables with static storage were collected by the type checker, tentative definitions are
+    // zero initialized and declarations without a definition get no storage
+    let mut variables: Vec<(&String, &SymbolEntry)> = symbol_table
+        .iter()
+        .filter(|(_, entry)| matches!(entry.identifier_attrs, IdentifierAttr::StaticAttr(..)))
+        .collect();
+    variables.sort_by_key(|(name, _)| *name);
+    for (name, entry) in variables {
+        if let IdentifierAttr::StaticAttr(initial_value, global) = &entry.identifier_attrs {
+            let init = match initial_value {
+                InitialValue::Initial(init) => init.clone(),
+                InitialValue::Tentative => {
+                    vec![StaticInit::ZeroInit(entry.sym_type.size(symbol_table))]
+                }
+                InitialValue::NoInitializer => continue,
+            };
+            tacky_program.declarations.push(TopLevel::StaticVariable {
+                identifier: name.clone(),
+                global: *global,
+                var_type: entry.sym_type.clone(),
+                init,
+            });
+        }
+    }
+
+    // String literals were turned into constants by the type checker
+    let mut constants: Vec<(&String, &SymbolEntry)> = symbol_table
+        .iter()
+        .filter(|(_, entry)| matches!(entry.identifier_attrs, IdentifierAttr::ConstantAttr(_)))
+        .collect();
+    constants.sort_by_key(|(name, _)| *name);
+    for (name, entry) in constants {
+        if let IdentifierAttr::ConstantAttr(init) = &entry.identifier_attrs {
+            tacky_program.declarations.push(TopLevel::StaticConstant {
+                identifier: name.clone(),
+                constant_type: entry.sym_type.clone(),
+                init: init.clone(),
+            });
+        }
+    }
 
     tacky_program
 }
 
-fn emit_tacky_block(block: parser::Block, instructions: &mut Vec<Instruction>) {
+fn emit_tacky_block(
+    block: parser::Block,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) {
     for block_item in block {
         match block_item {
-            parser::BlockItem::S(statement) => emit_tacky_statement(statement, instructions),
-            parser::BlockItem::D(declaration) => emit_tacky_delcaration(declaration, instructions),
+            parser::BlockItem::S(statement) => {
+                emit_tacky_statement(statement, instructions, symbol_table)
+            }
+            parser::BlockItem::D(declaration) => {
+                emit_tacky_delcaration(declaration, instructions, symbol_table)
+            }
         }
     }
 }
 
-fn emit_tacky_statement(statement: parser::Statement, instructions: &mut Vec<Instruction>) {
+fn emit_tacky_statement(
+    statement: parser::Statement,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) {
     match statement {
         parser::Statement::Return(expression) => {
-            let val = emit_tacky_value(expression, instructions);
+            let val = expression
+                .map(|expression| emit_tacky_value(expression, instructions, symbol_table));
             instructions.push(Instruction::Return(val));
         }
         parser::Statement::Expression(expression) => {
-            emit_tacky_value(expression, instructions);
+            emit_tacky_value(expression, instructions, symbol_table);
         }
         parser::Statement::Null => {}
         parser::Statement::If(cond, if_body, else_body) => {
             let false_label = make_label_name("false");
             let end_label = make_label_name("if_end");
 
-            let condition = emit_tacky_value(cond, instructions);
+            let condition = emit_tacky_value(cond, instructions, symbol_table);
             if let Some(else_body) = else_body {
                 instructions.push(Instruction::JumpIfZero(condition, false_label.clone()));
-                emit_tacky_statement(*if_body, instructions);
+                emit_tacky_statement(*if_body, instructions, symbol_table);
                 instructions.push(Instruction::Jump(end_label.clone()));
                 instructions.push(Instruction::Label(false_label));
-                emit_tacky_statement(*else_body, instructions);
+                emit_tacky_statement(*else_body, instructions, symbol_table);
                 instructions.push(Instruction::Label(end_label));
             } else {
                 instructions.push(Instruction::JumpIfZero(condition, end_label.clone()));
-                emit_tacky_statement(*if_body, instructions);
+                emit_tacky_statement(*if_body, instructions, symbol_table);
                 instructions.push(Instruction::Label(end_label));
             }
         }
         parser::Statement::Compound(block) => {
-            emit_tacky_block(block, instructions);
+            emit_tacky_block(block, instructions, symbol_table);
         }
         parser::Statement::DoWhile(body, cond, label) => {
             let label = label.unwrap();
@@ -142,9 +240,9 @@ fn emit_tacky_statement(statement: parser::Statement, instructions: &mut Vec<Ins
             let continue_label = format!("continue_{}", label);
 
             instructions.push(Instruction::Label(start_label.clone()));
-            emit_tacky_statement(*body, instructions);
+            emit_tacky_statement(*body, instructions, symbol_table);
             instructions.push(Instruction::Label(continue_label));
-            let condition = emit_tacky_value(cond, instructions);
+            let condition = emit_tacky_value(cond, instructions, symbol_table);
             instructions.push(Instruction::JumpIfNotZero(condition, start_label));
             instructions.push(Instruction::Label(break_label));
         }
@@ -154,9 +252,9 @@ fn emit_tacky_statement(statement: parser::Statement, instructions: &mut Vec<Ins
             let continue_label = format!("continue_{}", label);
 
             instructions.push(Instruction::Label(continue_label.clone()));
-            let condition = emit_tacky_value(cond, instructions);
+            let condition = emit_tacky_value(cond, instructions, symbol_table);
             instructions.push(Instruction::JumpIfZero(condition, break_label.clone()));
-            emit_tacky_statement(*body, instructions);
+            emit_tacky_statement(*body, instructions, symbol_table);
             instructions.push(Instruction::Jump(continue_label));
             instructions.push(Instruction::Label(break_label));
         }
@@ -167,24 +265,30 @@ fn emit_tacky_statement(statement: parser::Statement, instructions: &mut Vec<Ins
             let continue_label = format!("continue_{}", label);
 
             match init {
-                parser::ForInit::InitDeclaration(declaration) => {
-                    emit_tacky_delcaration(parser::Declaration::VarDecl(declaration), instructions);
+                parser::ForInit::InitDeclaration(declarations) => {
+                    for declaration in declarations {
+                        emit_tacky_delcaration(
+                            parser::Declaration::VarDecl(declaration),
+                            instructions,
+                            symbol_table,
+                        );
+                    }
                 }
                 parser::ForInit::InitExpression(expression) => {
                     if let Some(expression) = expression {
-                        emit_tacky_value(expression, instructions);
+                        emit_tacky_value(expression, instructions, symbol_table);
                     }
                 }
             }
             instructions.push(Instruction::Label(start_label.clone()));
             if let Some(cond) = cond {
-                let condition = emit_tacky_value(cond, instructions);
+                let condition = emit_tacky_value(cond, instructions, symbol_table);
                 instructions.push(Instruction::JumpIfZero(condition, break_label.clone()));
             }
-            emit_tacky_statement(*body, instructions);
+            emit_tacky_statement(*body, instructions, symbol_table);
             instructions.push(Instruction::Label(continue_label.clone()));
             if let Some(post) = post {
-                emit_tacky_value(post, instructions);
+                emit_tacky_value(post, instructions, symbol_table);
             }
             instructions.push(Instruction::Jump(start_label));
             instructions.push(Instruction::Label(break_label));
@@ -195,60 +299,317 @@ fn emit_tacky_statement(statement: parser::Statement, instructions: &mut Vec<Ins
         parser::Statement::Continue(label) => {
             instructions.push(Instruction::Jump(format!("continue_{}", label.unwrap())));
         }
-        parser::Statement::Switch(cond, cases, default, label) => {
-            let label = label.unwrap();
-            let value = emit_tacky_value(cond, instructions);
-            let break_label = format!("break_{}", label);
-            for case in &cases {
-                instructions.push(Instruction::JumpIfEqual(
-                    value.clone(),
-                    Val::Constant(case.cond),
-                    format!("{}.{}", label, case.cond),
-                ))
-            }
-            if let Some(_) = default {
-                instructions.push(Instruction::Jump(format!("{}.default", label)));
-            } else {
-                instructions.push(Instruction::Jump(break_label.clone()));
-            }
-
+        parser::Statement::Goto(label) => {
+            instructions.push(Instruction::Jump(label));
+        }
+        parser::Statement::Labeled(label, body) => {
+            instructions.push(Instruction::Label(label));
+            emit_tacky_statement(*body, instructions, symbol_table);
+        }
+        parser::Statement::Switch(cond, body, cases, label) => {
+            let cond_type = cond.get_type().clone();
+            let value = emit_tacky_value(cond, instructions, symbol_table);
+            let break_label = format!("break_{}", label.unwrap());
+            let mut default_label = None;
             for case in cases {
-                instructions.push(Instruction::Label(format!("{}.{}", label, case.cond)));
-                emit_tacky_statement(case.body, instructions);
-            }
-
-            if let Some(default) = default {
-                instructions.push(Instruction::Label(format!("{}.default", label)));
-                emit_tacky_statement(*default, instructions);
+                match case.value {
+                    Some(case_value) => instructions.push(Instruction::JumpIfEqual(
+                        value.clone(),
+                        Val::Constant(make_constant(case_value, &cond_type)),
+                        case.label,
+                    )),
+                    None => default_label = Some(case.label),
+                }
             }
-
+            instructions.push(Instruction::Jump(
+                default_label.unwrap_or_else(|| break_label.clone()),
+            ));
+            emit_tacky_statement(*body, instructions, symbol_table);
             instructions.push(Instruction::Label(break_label));
         }
+        parser::Statement::Case(_, body, label) | parser::Statement::Default(body, label) => {
+            instructions.push(Instruction::Label(label.unwrap()));
+            emit_tacky_statement(*body, instructions, symbol_table);
+        }
     }
 }
 
-fn emit_tacky_delcaration(declaration: parser::Declaration, instructions: &mut Vec<Instruction>) {
+fn emit_tacky_delcaration(
+    declaration: parser::Declaration,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) {
     match declaration {
+        // Static and extern variables are initialized before the program starts
         parser::Declaration::VarDecl(parser::VariableDeclaration {
-            name,
-            init,
-            storage_class,
-        }) => {
-            if let Some(init) = init {
-                let result = emit_tacky_value(init, instructions);
-                instructions.push(Instruction::Copy(result, Val::Var(name)));
+            storage_class: Some(_),
+            ..
+        }) => {}
+        parser::Declaration::VarDecl(parser::VariableDeclaration { name, init, .. }) => {
+            match init {
+                Some(parser::Initializer::Single(init))
+                    if !matches!(init.kind, parser::ExpressionKind::String(_)) =>
+                {
+                    let result = emit_tacky_value(init, instructions, symbol_table);
+                    instructions.push(Instruction::Copy(result, Val::Var(name)));
+                }
+                Some(init) => emit_compound_init(init, &name, 0, instructions, symbol_table),
+                None => {}
+            }
+        }
+        parser::Declaration::FuncDecl(_)
+        | parser::Declaration::StructDecl(_)
+        | parser::Declaration::EnumDecl(_) => {}
+    }
+}
+
+fn emit_compound_init(
+    init: parser::Initializer,
+    name: &str,
+    offset: i64,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) {
+    match init {
+        parser::Initializer::Single(parser::Expression {
+            kind: parser::ExpressionKind::String(bytes),
+            exp_type: Some(Type::Array(_, size)),
+        }) => emit_string_init(bytes, size, name, offset, instructions),
+        parser::Initializer::Single(expression) => {
+            let result = emit_tacky_value(expression, instructions, symbol_table);
+            instructions.push(Instruction::CopyToOffset(result, name.to_string(), offset));
+        }
+        parser::Initializer::Compound(initializers, init_type) => {
+            let offsets: Vec<i64> = match init_type {
+                Some(Type::Array(element_type, size)) => {
+                    let element_size = element_type.size(symbol_table);
+                    (0..size).map(|i| i * element_size).collect()
+                }
+                Some(Type::Structure(tag)) => get_struct_entry(&tag, symbol_table)
+                    .members
+                    .iter()
+                    .map(|member| member.offset)
+                    .collect(),
+                _ => panic!("Compound initializer without an aggregate type"),
+            };
+            for (initializer, member_offset) in initializers.into_iter().zip(offsets) {
+                emit_compound_init(
+                    initializer,
+                    name,
+                    offset + member_offset,
+                    instructions,
+                    symbol_table,
+                );
             }
         }
-        parser::Declaration::FuncDecl(_) => {}
     }
 }
 
-fn emit_tacky_value(expression: parser::Expression, instructions: &mut Vec<Instruction>) -> Val {
-    match expression {
-        parser::Expression::Constant(value) => Val::Constant(value),
-        parser::Expression::Unary(operator, expression) => {
-            let src = emit_tacky_value(*expression, instructions);
-            let dst = Val::Var(make_temp_name());
+// Copies the string into the array as wide as possible, padding the rest with zeros
+fn emit_string_init(
+    mut bytes: Vec<u8>,
+    size: i64,
+    name: &str,
+    offset: i64,
+    instructions: &mut Vec<Instruction>,
+) {
+    bytes.resize(size as usize, 0);
+    let mut copied = 0;
+    while copied < bytes.len() {
+        let remaining = &bytes[copied..];
+        let (constant, width) = if remaining.len() >= 8 {
+            let chunk: [u8; 8] = remaining[..8].try_into().unwrap();
+            (Const::Long(i64::from_le_bytes(chunk)), 8)
+        } else if remaining.len() >= 4 {
+            let chunk: [u8; 4] = remaining[..4].try_into().unwrap();
+            (Const::Int(i32::from_le_bytes(chunk)), 4)
+        } else {
+            (Const::Char(remaining[0] as i8), 1)
+        };
+        instructions.push(Instruction::CopyToOffset(
+            Val::Constant(constant),
+            name.to_string(),
+            offset + copied as i64,
+        ));
+        copied += width;
+    }
+}
+
+fn emit_tacky_value(
+    expression: parser::Expression,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    let exp_type = expression.get_type().clone();
+    match emit_tacky_expression(expression, instructions, symbol_table) {
+        // Every evaluation of a volatile object reads it, even when the value isn't used
+        ExpressionResult::PlainOperand(Val::Var(name))
+            if symbol_table[&name].sym_type.qualifiers().1 =>
+        {
+            let dst = make_tacky_variable(&exp_type, symbol_table);
+            instructions.push(Instruction::Copy(Val::Var(name), dst.clone()));
+            dst
+        }
+        ExpressionResult::PlainOperand(val) => val,
+        ExpressionResult::SubObject(base, offset) => {
+            let dst = make_tacky_variable(&exp_type, symbol_table);
+            instructions.push(Instruction::CopyFromOffset(base, offset, dst.clone()));
+            dst
+        }
+        ExpressionResult::DereferencedPointer(ptr) => {
+            let dst = make_tacky_variable(&exp_type, symbol_table);
+            instructions.push(Instruction::Load(ptr, dst.clone()));
+            dst
+        }
+    }
+}
+
+fn emit_tacky_expression(
+    expression: parser::Expression,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> ExpressionResult {
+    let exp_type = expression.get_type().clone();
+    let val = match expression.kind {
+        parser::ExpressionKind::Dereference(inner) => {
+            let ptr = emit_tacky_value(*inner, instructions, symbol_table);
+            return ExpressionResult::DereferencedPointer(ptr);
+        }
+        parser::ExpressionKind::AddressOf(inner) => {
+            match emit_tacky_expression(*inner, instructions, symbol_table) {
+                ExpressionResult::PlainOperand(val) => {
+                    let dst = make_tacky_variable(&exp_type, symbol_table);
+                    instructions.push(Instruction::GetAddress(val, dst.clone()));
+                    dst
+                }
+                ExpressionResult::SubObject(base, offset) => {
+                    let base_address = make_tacky_variable(&exp_type, symbol_table);
+                    instructions.push(Instruction::GetAddress(
+                        Val::Var(base),
+                        base_address.clone(),
+                    ));
+                    emit_offset_pointer(base_address, offset, &exp_type, instructions, symbol_table)
+                }
+                ExpressionResult::DereferencedPointer(ptr) => ptr,
+            }
+        }
+        parser::ExpressionKind::Dot(structure, member) => {
+            let offset = get_member_offset(structure.get_type(), &member, symbol_table);
+            return match emit_tacky_expression(*structure, instructions, symbol_table) {
+                ExpressionResult::PlainOperand(Val::Var(base)) => {
+                    ExpressionResult::SubObject(base, offset)
+                }
+                ExpressionResult::PlainOperand(Val::Constant(_)) => {
+                    panic!("A constant can't be a structure")
+                }
+                ExpressionResult::SubObject(base, base_offset) => {
+                    ExpressionResult::SubObject(base, base_offset + offset)
+                }
+                ExpressionResult::DereferencedPointer(ptr) => {
+                    let pointer_type = Type::Pointer(Box::new(exp_type));
+                    ExpressionResult::DereferencedPointer(emit_offset_pointer(
+                        ptr,
+                        offset,
+                        &pointer_type,
+                        instructions,
+                        symbol_table,
+                    ))
+                }
+            };
+        }
+        parser::ExpressionKind::Arrow(pointer, member) => {
+            let offset = match pointer.get_type() {
+                Type::Pointer(referenced) => get_member_offset(referenced, &member, symbol_table),
+                _ => panic!("Arrow applied to a non-pointer"),
+            };
+            let ptr = emit_tacky_value(*pointer, instructions, symbol_table);
+            let pointer_type = Type::Pointer(Box::new(exp_type));
+            return ExpressionResult::DereferencedPointer(emit_offset_pointer(
+                ptr,
+                offset,
+                &pointer_type,
+                instructions,
+                symbol_table,
+            ));
+        }
+        parser::ExpressionKind::Subscript(expression1, expression2) => {
+            let (pointer, index) = match expression1.get_type() {
+                Type::Pointer(_) => (expression1, expression2),
+                _ => (expression2, expression1),
+            };
+            let pointer_type = pointer.get_type().clone();
+            let pointer = emit_tacky_value(*pointer, instructions, symbol_table);
+            let index = emit_tacky_value(*index, instructions, symbol_table);
+            let dst = make_tacky_variable(&pointer_type, symbol_table);
+            instructions.push(Instruction::AddPtr(
+                pointer,
+                index,
+                referenced_size(&pointer_type, symbol_table),
+                dst.clone(),
+            ));
+            return ExpressionResult::DereferencedPointer(dst);
+        }
+        parser::ExpressionKind::Constant(value) => Val::Constant(value),
+        parser::ExpressionKind::String(_) => {
+            unreachable!("String literals are replaced by the type checker")
+        }
+        parser::ExpressionKind::SizeOf(_) | parser::ExpressionKind::SizeOfType(_) => {
+            unreachable!("sizeof is folded by the type checker")
+        }
+        parser::ExpressionKind::VaStart(va_list) => {
+            let va_list = emit_tacky_value(*va_list, instructions, symbol_table);
+            instructions.push(Instruction::VaStart(va_list));
+            Val::Constant(Const::Int(0))
+        }
+        parser::ExpressionKind::VaArg(va_list, _) => {
+            let va_list = emit_tacky_value(*va_list, instructions, symbol_table);
+            let dst = make_tacky_variable(&exp_type, symbol_table);
+            instructions.push(Instruction::VaArg(va_list, dst.clone()));
+            dst
+        }
+        // Nothing was allocated by va_start
+        parser::ExpressionKind::VaEnd(va_list) => {
+            emit_tacky_value(*va_list, instructions, symbol_table);
+            Val::Constant(Const::Int(0))
+        }
+        parser::ExpressionKind::VaCopy(destination, source) => {
+            let destination = emit_tacky_value(*destination, instructions, symbol_table);
+            let source = emit_tacky_value(*source, instructions, symbol_table);
+            let va_list_pointer = Type::Pointer(Box::new(Type::ULong));
+            for i in 0..3 {
+                let src_ptr = make_tacky_variable(&va_list_pointer, symbol_table);
+                instructions.push(Instruction::AddPtr(
+                    source.clone(),
+                    Val::Constant(Const::Long(i)),
+                    8,
+                    src_ptr.clone(),
+                ));
+                let eightbyte = make_tacky_variable(&Type::ULong, symbol_table);
+                instructions.push(Instruction::Load(src_ptr, eightbyte.clone()));
+                let dst_ptr = make_tacky_variable(&va_list_pointer, symbol_table);
+                instructions.push(Instruction::AddPtr(
+                    destination.clone(),
+                    Val::Constant(Const::Long(i)),
+                    8,
+                    dst_ptr.clone(),
+                ));
+                instructions.push(Instruction::Store(eightbyte, dst_ptr));
+            }
+            Val::Constant(Const::Int(0))
+        }
+        // The value is discarded, only the side effects are left
+        parser::ExpressionKind::Cast(Type::Void, expression) => {
+            emit_tacky_value(*expression, instructions, symbol_table);
+            Val::Constant(Const::Int(0))
+        }
+        parser::ExpressionKind::Cast(target_type, expression) => {
+            let inner_type = expression.get_type().clone();
+            let result = emit_tacky_value(*expression, instructions, symbol_table);
+            emit_conversion(result, &inner_type, &target_type, instructions, symbol_table)
+        }
+        parser::ExpressionKind::Unary(operator, expression) => {
+            let src = emit_tacky_value(*expression, instructions, symbol_table);
+            let dst = make_tacky_variable(&exp_type, symbol_table);
             let operator = match operator {
                 parser::UnaryOperator::Negate => UnaryOperator::Negate,
                 parser::UnaryOperator::Complement => UnaryOperator::Complement,
@@ -257,102 +618,404 @@ fn emit_tacky_value(expression: parser::Expression, instructions: &mut Vec<Instr
             instructions.push(Instruction::Unary(operator, src, dst.clone()));
             dst
         }
-        parser::Expression::Binary(parser::BinaryOperator::LAnd, operand1, operand2) => {
-            let result = Val::Var(make_temp_name());
+        parser::ExpressionKind::Binary(parser::BinaryOperator::LAnd, operand1, operand2) => {
+            let result = make_tacky_variable(&exp_type, symbol_table);
             let false_label = make_label_name("false");
             let end_label = make_label_name("and_end");
 
-            let evaluation1 = emit_tacky_value(*operand1, instructions);
+            let evaluation1 = emit_tacky_value(*operand1, instructions, symbol_table);
             instructions.push(Instruction::JumpIfZero(evaluation1, false_label.clone()));
-            let evaluation2 = emit_tacky_value(*operand2, instructions);
+            let evaluation2 = emit_tacky_value(*operand2, instructions, symbol_table);
             instructions.push(Instruction::JumpIfZero(evaluation2, false_label.clone()));
 
-            instructions.push(Instruction::Copy(Val::Constant(1), result.clone()));
+            instructions.push(Instruction::Copy(Val::Constant(Const::Int(1)), result.clone()));
             instructions.push(Instruction::Jump(end_label.clone()));
             instructions.push(Instruction::Label(false_label));
-            instructions.push(Instruction::Copy(Val::Constant(0), result.clone()));
+            instructions.push(Instruction::Copy(Val::Constant(Const::Int(0)), result.clone()));
             instructions.push(Instruction::Label(end_label));
 
             result
         }
-        parser::Expression::Binary(parser::BinaryOperator::LOr, operand1, operand2) => {
-            let result = Val::Var(make_temp_name());
+        parser::ExpressionKind::Binary(parser::BinaryOperator::LOr, operand1, operand2) => {
+            let result = make_tacky_variable(&exp_type, symbol_table);
             let true_label = make_label_name("true");
             let end_label = make_label_name("or_end");
 
-            let evaluation1 = emit_tacky_value(*operand1, instructions);
+            let evaluation1 = emit_tacky_value(*operand1, instructions, symbol_table);
             instructions.push(Instruction::JumpIfNotZero(evaluation1, true_label.clone()));
-            let evaluation2 = emit_tacky_value(*operand2, instructions);
+            let evaluation2 = emit_tacky_value(*operand2, instructions, symbol_table);
             instructions.push(Instruction::JumpIfNotZero(evaluation2, true_label.clone()));
 
-            instructions.push(Instruction::Copy(Val::Constant(0), result.clone()));
+            instructions.push(Instruction::Copy(Val::Constant(Const::Int(0)), result.clone()));
             instructions.push(Instruction::Jump(end_label.clone()));
             instructions.push(Instruction::Label(true_label));
-            instructions.push(Instruction::Copy(Val::Constant(1), result.clone()));
+            instructions.push(Instruction::Copy(Val::Constant(Const::Int(1)), result.clone()));
             instructions.push(Instruction::Label(end_label));
 
             result
         }
-        parser::Expression::Binary(operator, operand1, operand2) => {
-            let src1 = emit_tacky_value(*operand1, instructions);
-            let src2 = emit_tacky_value(*operand2, instructions);
-            let dst = Val::Var(make_temp_name());
+        parser::ExpressionKind::Binary(
+            operator @ (parser::BinaryOperator::Add | parser::BinaryOperator::Subtract),
+            operand1,
+            operand2,
+        ) if matches!(operand1.get_type(), Type::Pointer(_))
+            || matches!(operand2.get_type(), Type::Pointer(_)) =>
+        {
+            let type1 = operand1.get_type().clone();
+            let type2 = operand2.get_type().clone();
+            let src1 = emit_tacky_value(*operand1, instructions, symbol_table);
+            let src2 = emit_tacky_value(*operand2, instructions, symbol_table);
+            match (operator, type2) {
+                (parser::BinaryOperator::Subtract, Type::Pointer(_)) => {
+                    let dst = make_tacky_variable(&exp_type, symbol_table);
+                    let difference = make_tacky_variable(&Type::Long, symbol_table);
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::Subtract,
+                        src1,
+                        src2,
+                        difference.clone(),
+                    ));
+                    instructions.push(Instruction::Binary(
+                        BinaryOperator::Divide,
+                        difference,
+                        Val::Constant(Const::Long(referenced_size(&type1, symbol_table))),
+                        dst.clone(),
+                    ));
+                    dst
+                }
+                (operator, _) => emit_pointer_addition(
+                    operator,
+                    src1,
+                    &type1,
+                    src2,
+                    &exp_type,
+                    instructions,
+                    symbol_table,
+                ),
+            }
+        }
+        parser::ExpressionKind::Binary(operator, operand1, operand2) => {
+            let src1 = emit_tacky_value(*operand1, instructions, symbol_table);
+            let src2 = emit_tacky_value(*operand2, instructions, symbol_table);
+            let dst = make_tacky_variable(&exp_type, symbol_table);
             let operator = convert_parser_bin_to_tacky(operator);
             instructions.push(Instruction::Binary(operator, src1, src2, dst.clone()));
             dst
         }
-        parser::Expression::Var(var) => Val::Var(var),
-        parser::Expression::Assignment(op, exp1, exp2) => {
-            if let parser::Expression::Var(var) = *exp1 {
-                let right_result = emit_tacky_value(*exp2, instructions);
+        parser::ExpressionKind::Var(var) => Val::Var(var),
+        parser::ExpressionKind::Assignment(op, exp1, exp2) => {
+            let lvalue = emit_tacky_expression(*exp1, instructions, symbol_table);
+            let right_type = exp2.get_type().clone();
+            let right_result = emit_tacky_value(*exp2, instructions, symbol_table);
 
-                if let Some(op) = op {
-                    let left_result = Val::Var(var.clone());
-                    let temp_result = Val::Var(make_temp_name());
-                    instructions.push(Instruction::Binary(
-                        convert_parser_bin_to_tacky(op),
-                        left_result,
-                        right_result,
-                        temp_result.clone(),
-                    ));
-                    instructions.push(Instruction::Copy(temp_result, Val::Var(var.clone())));
-                } else {
-                    instructions.push(Instruction::Copy(right_result, Val::Var(var.clone())));
-                }
-                Val::Var(var)
+            let result = if let Some(op) = op {
+                let left_value = emit_lvalue_read(&lvalue, &exp_type, instructions, symbol_table);
+                emit_compound_operation(
+                    op,
+                    left_value,
+                    &exp_type,
+                    right_result,
+                    &right_type,
+                    instructions,
+                    symbol_table,
+                )
             } else {
-                panic!("Shouldn't have an invalid lvalue at this point");
-            }
+                right_result
+            };
+            emit_lvalue_write(lvalue, result, instructions)
+        }
+        // The lvalue is evaluated once, the value it held before the update is the result
+        parser::ExpressionKind::Postfix(op, inner) => {
+            let lvalue = emit_tacky_expression(*inner, instructions, symbol_table);
+            let old_value = make_tacky_variable(&exp_type, symbol_table);
+            let current = emit_lvalue_read(&lvalue, &exp_type, instructions, symbol_table);
+            instructions.push(Instruction::Copy(current, old_value.clone()));
+            let (one, one_type) = match exp_type {
+                Type::Pointer(_) => (Const::Long(1), Type::Long),
+                Type::Double => (Const::Double(1.0), Type::Double),
+                _ => (make_constant(1, &exp_type.promote()), exp_type.promote()),
+            };
+            let result = emit_compound_operation(
+                op,
+                old_value.clone(),
+                &exp_type,
+                Val::Constant(one),
+                &one_type,
+                instructions,
+                symbol_table,
+            );
+            emit_lvalue_write(lvalue, result, instructions);
+            old_value
+        }
+        parser::ExpressionKind::Comma(left, right) => {
+            emit_tacky_value(*left, instructions, symbol_table);
+            emit_tacky_value(*right, instructions, symbol_table)
         }
-        parser::Expression::Conditional(left, middle, right) => {
-            let result = Val::Var(make_temp_name());
+        parser::ExpressionKind::Conditional(left, middle, right) => {
+            let result = match exp_type {
+                Type::Void => None,
+                _ => Some(make_tacky_variable(&exp_type, symbol_table)),
+            };
             let false_label = make_label_name("false");
             let end_label = make_label_name("cond_end");
 
-            let condition = emit_tacky_value(*left, instructions);
+            let condition = emit_tacky_value(*left, instructions, symbol_table);
             instructions.push(Instruction::JumpIfZero(condition, false_label.clone()));
-            let if_value = emit_tacky_value(*middle, instructions);
-            instructions.push(Instruction::Copy(if_value, result.clone()));
+            let if_value = emit_tacky_value(*middle, instructions, symbol_table);
+            if let Some(result) = &result {
+                instructions.push(Instruction::Copy(if_value, result.clone()));
+            }
             instructions.push(Instruction::Jump(end_label.clone()));
             instructions.push(Instruction::Label(false_label));
-            let else_value = emit_tacky_value(*right, instructions);
-            instructions.push(Instruction::Copy(else_value, result.clone()));
+            let else_value = emit_tacky_value(*right, instructions, symbol_table);
+            if let Some(result) = &result {
+                instructions.push(Instruction::Copy(else_value, result.clone()));
+            }
             instructions.push(Instruction::Label(end_label));
 
-            result
+            result.unwrap_or(Val::Constant(Const::Int(0)))
         }
-        parser::Expression::FunctionCall(name, args) => {
+        // Naming a function decays to its address, which is called directly
+        parser::ExpressionKind::FunctionCall(function, args) => {
+            let direct_name = match &function.kind {
+                parser::ExpressionKind::AddressOf(inner) => match &inner.kind {
+                    parser::ExpressionKind::Var(name)
+                        if matches!(inner.get_type(), Type::Function(..)) =>
+                    {
+                        Some(name.clone())
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+            let function_pointer = match direct_name {
+                Some(_) => None,
+                None => Some(emit_tacky_value(*function, instructions, symbol_table)),
+            };
             let mut arg_vals = Vec::new();
             for arg in args {
-                arg_vals.push(emit_tacky_value(arg, instructions));
+                arg_vals.push(emit_tacky_value(arg, instructions, symbol_table));
             }
-            let result = Val::Var(make_temp_name());
-            instructions.push(Instruction::FunctionCall(name, arg_vals, result.clone()));
+            let result = match exp_type {
+                Type::Void => None,
+                _ => Some(make_tacky_variable(&exp_type, symbol_table)),
+            };
+            instructions.push(match direct_name {
+                Some(name) => Instruction::FunctionCall(name, arg_vals, result.clone()),
+                None => {
+                    Instruction::IndirectCall(function_pointer.unwrap(), arg_vals, result.clone())
+                }
+            });
+            // A void result is never used
+            result.unwrap_or(Val::Constant(Const::Int(0)))
+        }
+    };
+    ExpressionResult::PlainOperand(val)
+}
+
+fn emit_lvalue_read(
+    lvalue: &ExpressionResult,
+    lvalue_type: &Type,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    match lvalue {
+        ExpressionResult::PlainOperand(val) => val.clone(),
+        ExpressionResult::SubObject(base, offset) => {
+            let dst = make_tacky_variable(lvalue_type, symbol_table);
+            instructions.push(Instruction::CopyFromOffset(
+                base.clone(),
+                *offset,
+                dst.clone(),
+            ));
+            dst
+        }
+        ExpressionResult::DereferencedPointer(ptr) => {
+            let dst = make_tacky_variable(lvalue_type, symbol_table);
+            instructions.push(Instruction::Load(ptr.clone(), dst.clone()));
+            dst
+        }
+    }
+}
+
+fn emit_lvalue_write(
+    lvalue: ExpressionResult,
+    result: Val,
+    instructions: &mut Vec<Instruction>,
+) -> Val {
+    match lvalue {
+        ExpressionResult::PlainOperand(var) => {
+            instructions.push(Instruction::Copy(result, var.clone()));
+            var
+        }
+        ExpressionResult::SubObject(base, offset) => {
+            instructions.push(Instruction::CopyToOffset(result.clone(), base, offset));
+            result
+        }
+        ExpressionResult::DereferencedPointer(ptr) => {
+            instructions.push(Instruction::Store(result.clone(), ptr));
             result
         }
     }
 }
 
+// Applies the operator of a compound assignment, the result has the type of the left operand
+fn emit_compound_operation(
+    op: parser::BinaryOperator,
+    left_value: Val,
+    left_type: &Type,
+    right_value: Val,
+    right_type: &Type,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    if let Type::Pointer(_) = left_type {
+        return emit_pointer_addition(
+            op,
+            left_value,
+            left_type,
+            right_value,
+            left_type,
+            instructions,
+            symbol_table,
+        );
+    }
+    // Shifts are done in the type of the left operand, everything else in the
+    // common type the type checker converted the right operand to
+    let op_type = match op {
+        parser::BinaryOperator::LeftShift | parser::BinaryOperator::RightShift => {
+            left_type.promote()
+        }
+        _ => right_type.clone(),
+    };
+    let left_result = emit_conversion(left_value, left_type, &op_type, instructions, symbol_table);
+    let temp_result = make_tacky_variable(&op_type, symbol_table);
+    instructions.push(Instruction::Binary(
+        convert_parser_bin_to_tacky(op),
+        left_result,
+        right_value,
+        temp_result.clone(),
+    ));
+    emit_conversion(temp_result, &op_type, left_type, instructions, symbol_table)
+}
+
+// Adds (or subtracts) an integer to a pointer, either operand order is accepted for addition
+fn emit_pointer_addition(
+    operator: parser::BinaryOperator,
+    src1: Val,
+    type1: &Type,
+    src2: Val,
+    pointer_type: &Type,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    let (pointer, index) = match type1 {
+        Type::Pointer(_) => (src1, src2),
+        _ => (src2, src1),
+    };
+    let index = match operator {
+        parser::BinaryOperator::Subtract => {
+            let negated = make_tacky_variable(&Type::Long, symbol_table);
+            instructions.push(Instruction::Unary(UnaryOperator::Negate, index, negated.clone()));
+            negated
+        }
+        _ => index,
+    };
+    let dst = make_tacky_variable(pointer_type, symbol_table);
+    instructions.push(Instruction::AddPtr(
+        pointer,
+        index,
+        referenced_size(pointer_type, symbol_table),
+        dst.clone(),
+    ));
+    dst
+}
+
+fn referenced_size(pointer_type: &Type, symbol_table: &SymbolTable) -> i64 {
+    match pointer_type {
+        Type::Pointer(referenced) => referenced.size(symbol_table),
+        _ => panic!("Expected a pointer type"),
+    }
+}
+
+fn get_member_offset(structure_type: &Type, member: &str, symbol_table: &SymbolTable) -> i64 {
+    match structure_type.unqualified() {
+        Type::Structure(tag) => {
+            get_struct_entry(tag, symbol_table)
+                .members
+                .iter()
+                .find(|entry| entry.name == member)
+                .expect("Member was checked by the type checker")
+                .offset
+        }
+        _ => panic!("Member access on a non-structure"),
+    }
+}
+
+fn emit_offset_pointer(
+    ptr: Val,
+    offset: i64,
+    pointer_type: &Type,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    if offset == 0 {
+        return ptr;
+    }
+    let dst = make_tacky_variable(pointer_type, symbol_table);
+    instructions.push(Instruction::AddPtr(
+        ptr,
+        Val::Constant(Const::Long(offset)),
+        1,
+        dst.clone(),
+    ));
+    dst
+}
+
+fn emit_conversion(
+    val: Val,
+    from: &Type,
+    to: &Type,
+    instructions: &mut Vec<Instruction>,
+    symbol_table: &mut SymbolTable,
+) -> Val {
+    if from == to {
+        return val;
+    }
+    let dst = make_tacky_variable(to, symbol_table);
+    if *to == Type::Double {
+        if from.is_signed() {
+            instructions.push(Instruction::IntToDouble(val, dst.clone()));
+        } else {
+            instructions.push(Instruction::UIntToDouble(val, dst.clone()));
+        }
+    } else if *from == Type::Double {
+        if to.is_signed() {
+            instructions.push(Instruction::DoubleToInt(val, dst.clone()));
+        } else {
+            instructions.push(Instruction::DoubleToUInt(val, dst.clone()));
+        }
+    } else if to.size(symbol_table) == from.size(symbol_table) {
+        instructions.push(Instruction::Copy(val, dst.clone()));
+    } else if to.size(symbol_table) < from.size(symbol_table) {
+        instructions.push(Instruction::Truncate(val, dst.clone()));
+    } else if from.is_signed() {
+        instructions.push(Instruction::SignExtend(val, dst.clone()));
+    } else {
+        instructions.push(Instruction::ZeroExtend(val, dst.clone()));
+    }
+    dst
+}
+
+fn make_constant(value: i64, const_type: &Type) -> Const {
+    match const_type {
+        Type::Long => Const::Long(value),
+        Type::UInt => Const::UInt(value as u32),
+        Type::ULong => Const::ULong(value as u64),
+        _ => Const::Int(value as i32),
+    }
+}
+
 fn convert_parser_bin_to_tacky(op: parser::BinaryOperator) -> BinaryOperator {
     match op {
         parser::BinaryOperator::Add => BinaryOperator::Add,
@@ -384,6 +1047,20 @@ fn make_temp_name() -> String {
     })
 }
 
+// Temporaries only hold values, which are never qualified
+fn make_tacky_variable(var_type: &Type, symbol_table: &mut SymbolTable) -> Val {
+    let name = make_temp_name();
+    symbol_table.insert(
+        name.clone(),
+        SymbolEntry {
+            sym_type: var_type.unqualified().clone(),
+            defined: true,
+            identifier_attrs: IdentifierAttr::LocalAttr,
+        },
+    );
+    Val::Var(name)
+}
+
 static mut LABEL_COUNTER: i64 = -1;
 
 fn make_label_name(prefix: &str) -> String {
@@ -1,62 +1,331 @@
+use crate::constant_evaluator::{convert_integer, evaluate_constant, evaluate_integer_constant};
 use crate::parser::*;
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq)]
-enum Type {
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Void,
+    Char,
+    SChar,
+    UChar,
     Int,
-    Function(usize), // param count
+    Long,
+    UInt,
+    ULong,
+    Double,
+    Pointer(Box<Type>),
+    Array(Box<Type>, i64), // element type, size
+    IncompleteArray(Box<Type>), // element type, the size isn't known yet
+    UnresolvedArray(Box<Type>, Box<Expression>), // element type, size the resolver evaluates
+    Function(Vec<Type>, Box<Type>, bool), // params, return, variadic
+    Structure(String),     // tag
+    Qualified(Box<Type>, bool, bool), // type, const, volatile
+}
+
+impl Type {
+    pub fn size(&self, symbol_table: &SymbolTable) -> i64 {
+        match self {
+            Type::Char | Type::SChar | Type::UChar => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+            Type::Array(element_type, size) => element_type.size(symbol_table) * size,
+            Type::Structure(tag) => get_struct_entry(tag, symbol_table).size,
+            Type::IncompleteArray(_) => panic!("Incomplete arrays don't have a size"),
+            Type::UnresolvedArray(..) => panic!("Array sizes are evaluated by the resolver"),
+            Type::Function(..) => panic!("Functions don't have a size"),
+            Type::Void => panic!("Void doesn't have a size"),
+            Type::Qualified(inner, ..) => inner.size(symbol_table),
+        }
+    }
+
+    pub fn alignment(&self, symbol_table: &SymbolTable) -> i64 {
+        match self {
+            Type::Array(element_type, _) | Type::IncompleteArray(element_type) => {
+                element_type.alignment(symbol_table)
+            }
+            Type::Structure(tag) => get_struct_entry(tag, symbol_table).alignment,
+            Type::Qualified(inner, ..) => inner.alignment(symbol_table),
+            scalar => scalar.size(symbol_table),
+        }
+    }
+
+    // Qualifying an array qualifies its elements, and qualifiers accumulate instead of nesting
+    pub fn qualify(self, is_const: bool, is_volatile: bool) -> Type {
+        match self {
+            unchanged if !is_const && !is_volatile => unchanged,
+            Type::Array(element_type, size) => {
+                Type::Array(Box::new(element_type.qualify(is_const, is_volatile)), size)
+            }
+            Type::IncompleteArray(element_type) => {
+                Type::IncompleteArray(Box::new(element_type.qualify(is_const, is_volatile)))
+            }
+            Type::UnresolvedArray(element_type, size) => {
+                Type::UnresolvedArray(Box::new(element_type.qualify(is_const, is_volatile)), size)
+            }
+            Type::Qualified(inner, was_const, was_volatile) => {
+                Type::Qualified(inner, was_const || is_const, was_volatile || is_volatile)
+            }
+            function_type @ Type::Function(..) => function_type,
+            other => Type::Qualified(Box::new(other), is_const, is_volatile),
+        }
+    }
+
+    pub fn unqualified(&self) -> &Type {
+        match self {
+            Type::Qualified(inner, ..) => inner,
+            other => other,
+        }
+    }
+
+    // const, volatile
+    pub fn qualifiers(&self) -> (bool, bool) {
+        match self {
+            Type::Qualified(_, is_const, is_volatile) => (*is_const, *is_volatile),
+            _ => (false, false),
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        match self {
+            Type::Array(element_type, _) | Type::IncompleteArray(element_type) => {
+                element_type.is_const()
+            }
+            other => other.qualifiers().0,
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || matches!(self.unqualified(), Type::Pointer(_))
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            Type::Char | Type::SChar | Type::Int | Type::Long
+        )
+    }
+
+    pub fn is_character(&self) -> bool {
+        matches!(self.unqualified(), Type::Char | Type::SChar | Type::UChar)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_character()
+            || matches!(
+                self.unqualified(),
+                Type::Int | Type::Long | Type::UInt | Type::ULong
+            )
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || *self.unqualified() == Type::Double
+    }
+
+    // Has the size and alignment of the System V __va_list_tag[1]: gp_offset and fp_offset in
+    // the first eightbyte, then the overflow area and the register save area
+    pub fn va_list() -> Type {
+        Type::Array(Box::new(Type::ULong), 3)
+    }
+
+    // Character types are promoted to int before any arithmetic, qualifiers don't carry over
+    pub fn promote(&self) -> Type {
+        if self.is_character() {
+            Type::Int
+        } else {
+            self.unqualified().clone()
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct SymbolEntry {
-    sym_type: Type,
-    defined: bool,
-    identifier_attrs: IdentifierAttr,
+    pub sym_type: Type,
+    pub defined: bool,
+    pub identifier_attrs: IdentifierAttr,
 }
 
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum IdentifierAttr {
     FunAttr(bool, bool),            // defined, global
     StaticAttr(InitialValue, bool), // init, global
+    ConstantAttr(StaticInit),
     LocalAttr,
+    StructAttr(StructEntry), // structure tags share the table, their unique names can't collide
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructEntry {
+    pub alignment: i64,
+    pub size: i64,
+    pub members: Vec<MemberEntry>,
+    pub is_union: bool,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+impl StructEntry {
+    // Only the first member of a union can be initialized
+    pub fn initialized_members(&self) -> &[MemberEntry] {
+        if self.is_union {
+            &self.members[..1]
+        } else {
+            &self.members
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MemberEntry {
+    pub name: String,
+    pub member_type: Type,
+    pub offset: i64,
+}
+
+pub fn get_struct_entry<'a>(tag: &str, symbol_table: &'a SymbolTable) -> &'a StructEntry {
+    match symbol_table.get(tag) {
+        Some(SymbolEntry {
+            identifier_attrs: IdentifierAttr::StructAttr(entry),
+            ..
+        }) => entry,
+        _ => panic!("Structure isn't defined: {}", tag),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum InitialValue {
     Tentative,
-    Initial(i64),
+    Initial(Vec<StaticInit>),
     NoInitializer,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum StaticInit {
+    CharInit(i8),
+    UCharInit(u8),
+    IntInit(i32),
+    LongInit(i64),
+    UIntInit(u32),
+    ULongInit(u64),
+    DoubleInit(f64),
+    ZeroInit(i64),               // bytes
+    StringInit(Vec<u8>, bool),   // bytes, null terminated
+    PointerInit(String, i64),    // static object, offset in bytes into it
+}
+
 pub type SymbolTable = HashMap<String, SymbolEntry>;
 
-pub fn check_types(program: &Program) -> Result<SymbolTable, String> {
+pub fn check_types(program: &mut Program) -> Result<SymbolTable, String> {
     let mut symbol_table: SymbolTable = HashMap::new();
-    for declaration in &program.declarations {
+    for declaration in &mut program.declarations {
         match declaration {
             Declaration::FuncDecl(function) => {
-                typecheck_function_declaration(&function, &mut symbol_table)?;
+                typecheck_function_declaration(function, &mut symbol_table)?;
             }
             Declaration::VarDecl(var) => {
                 typecheck_file_scope_variable_declaration(var, &mut symbol_table)?;
             }
+            Declaration::StructDecl(struct_declaration) => {
+                typecheck_struct_declaration(struct_declaration, &mut symbol_table)?;
+            }
+            // Enumerators were already replaced by constants
+            Declaration::EnumDecl(_) => {}
         }
     }
     Ok(symbol_table)
 }
 
+pub fn typecheck_struct_declaration(
+    struct_declaration: &StructDeclaration,
+    symbol_table: &mut SymbolTable,
+) -> Result<(), String> {
+    if struct_declaration.members.is_empty() {
+        return Ok(());
+    }
+    if symbol_table.contains_key(&struct_declaration.tag) {
+        return Err(format!(
+            "Structure defined more than once: {}",
+            struct_declaration.tag
+        ));
+    }
+
+    let mut members: Vec<MemberEntry> = Vec::new();
+    let mut size = 0;
+    let mut alignment = 1;
+    for member in &struct_declaration.members {
+        if members.iter().any(|entry| entry.name == member.name) {
+            return Err(format!("Duplicate structure member: {}", member.name));
+        }
+        if !is_complete(&member.member_type, symbol_table) {
+            return Err(format!("Structure member has incomplete type: {}", member.name));
+        }
+        // Union members all start at the beginning and overlap
+        let member_alignment = member.member_type.alignment(symbol_table);
+        let member_size = member.member_type.size(symbol_table);
+        let offset = if struct_declaration.is_union {
+            0
+        } else {
+            round_up(size, member_alignment)
+        };
+        members.push(MemberEntry {
+            name: member.name.clone(),
+            member_type: member.member_type.clone(),
+            offset,
+        });
+        size = size.max(offset + member_size);
+        alignment = alignment.max(member_alignment);
+    }
+
+    symbol_table.insert(
+        struct_declaration.tag.clone(),
+        SymbolEntry {
+            sym_type: Type::Structure(struct_declaration.tag.clone()),
+            defined: true,
+            identifier_attrs: IdentifierAttr::StructAttr(StructEntry {
+                alignment,
+                size: round_up(size, alignment),
+                members,
+                is_union: struct_declaration.is_union,
+            }),
+        },
+    );
+    Ok(())
+}
+
+fn round_up(value: i64, alignment: i64) -> i64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+// A structure type is incomplete until its members are declared, void never is complete
+pub fn is_complete(object_type: &Type, symbol_table: &SymbolTable) -> bool {
+    match object_type {
+        Type::Structure(tag) => symbol_table.contains_key(tag),
+        Type::Array(element_type, _) => is_complete(element_type, symbol_table),
+        Type::Qualified(inner, ..) => is_complete(inner, symbol_table),
+        Type::Void | Type::IncompleteArray(_) => false,
+        _ => true,
+    }
+}
+
 fn typecheck_function_declaration(
-    func_declaration: &FunctionDeclaration,
+    func_declaration: &mut FunctionDeclaration,
     symbol_table: &mut SymbolTable,
 ) -> Result<(), String> {
-    let func_type = Type::Function(func_declaration.params.len());
+    let func_type = func_declaration.fun_type.clone();
+    if let Type::Function(param_types, _, _) = &func_type {
+        if param_types.contains(&Type::Void) {
+            return Err(format!(
+                "Parameter can't have type void: {}",
+                func_declaration.name
+            ));
+        }
+    }
     let has_body = func_declaration.body.is_some();
     let mut already_defined = false;
     let mut global = func_declaration.storage_class != Some(StorageClass::Static);
 
     if symbol_table.contains_key(&func_declaration.name) {
         let old_decl = symbol_table.get(&func_declaration.name).unwrap();
-        if old_decl.sym_type != func_type {
+        if old_decl.sym_type != without_param_qualifiers(&func_type) {
             return Err(format!(
                 "Incompatible function declarations: {}",
                 func_declaration.name
@@ -71,62 +340,104 @@ fn typecheck_function_declaration(
             ));
         }
 
-        if (old_decl.identifier_attrs == IdentifierAttr::FunAttr(true, true)
-            || old_decl.identifier_attrs == IdentifierAttr::FunAttr(false, true))
-            && func_declaration.storage_class == Some(StorageClass::Static)
-        {
+        // A later declaration keeps the linkage of the first one
+        let old_global = matches!(old_decl.identifier_attrs, IdentifierAttr::FunAttr(_, true));
+        if old_global && func_declaration.storage_class == Some(StorageClass::Static) {
             return Err(format!(
                 "Static function declaration follows non-static: {}",
                 func_declaration.name
             ));
         }
-        global = true;
+        global = old_global;
     }
     let attrs = IdentifierAttr::FunAttr(already_defined || has_body, global);
     symbol_table.insert(
         func_declaration.name.clone(),
         SymbolEntry {
-            sym_type: func_type,
+            sym_type: without_param_qualifiers(&func_type),
             defined: already_defined || has_body,
             identifier_attrs: attrs,
         },
     );
-    if has_body {
-        for param in &func_declaration.params {
+    if let Some(body) = &mut func_declaration.body {
+        let (param_types, return_type) = match func_type {
+            Type::Function(param_types, return_type, _) => (param_types, return_type),
+            _ => return Err(format!("Not a function type: {}", func_declaration.name)),
+        };
+        if (*return_type != Type::Void && !is_complete(&return_type, symbol_table))
+            || param_types.iter().any(|param_type| !is_complete(param_type, symbol_table))
+        {
+            return Err(format!(
+                "Function defined with an incomplete type: {}",
+                func_declaration.name
+            ));
+        }
+        for (param, param_type) in func_declaration.params.iter().zip(param_types) {
             symbol_table.insert(
                 param.clone(),
                 SymbolEntry {
-                    sym_type: Type::Int,
+                    sym_type: param_type,
                     defined: false,
                     identifier_attrs: IdentifierAttr::LocalAttr,
                 },
             );
         }
-        typecheck_block(func_declaration.body.as_ref().unwrap(), symbol_table)?
+        typecheck_block(body, symbol_table, &return_type)?
     }
 
     Ok(())
 }
 
+// The qualifiers of a parameter only apply inside the body, they aren't part of the function type
+fn without_param_qualifiers(func_type: &Type) -> Type {
+    match func_type {
+        Type::Function(param_types, return_type, variadic) => Type::Function(
+            param_types
+                .iter()
+                .map(|param_type| param_type.unqualified().clone())
+                .collect(),
+            return_type.clone(),
+            *variadic,
+        ),
+        other => other.clone(),
+    }
+}
+
 fn typecheck_file_scope_variable_declaration(
-    var_declaration: &VariableDeclaration,
+    var_declaration: &mut VariableDeclaration,
     symbol_table: &mut SymbolTable,
 ) -> Result<(), String> {
-    let mut initial_value = match var_declaration.init {
-        Some(Expression::Constant(val)) => InitialValue::Initial(val),
+    complete_array_type(var_declaration);
+    if var_declaration.storage_class != Some(StorageClass::Extern)
+        && !is_complete(&var_declaration.var_type, symbol_table)
+    {
+        return Err(format!(
+            "Variable has an incomplete type: {}",
+            var_declaration.name
+        ));
+    }
+    let mut initial_value = match &var_declaration.init {
+        Some(initializer) => InitialValue::Initial(convert_initializer_to_static_init(
+            initializer,
+            &var_declaration.var_type,
+            symbol_table,
+        )?),
         None if var_declaration.storage_class == Some(StorageClass::Extern) => {
             InitialValue::NoInitializer
         }
         None => InitialValue::Tentative,
-        _ => return Err("Non-constant initializer".to_string()),
     };
 
     let mut global = var_declaration.storage_class != Some(StorageClass::Static);
 
     if let Some(old_decl) = symbol_table.get(&var_declaration.name) {
-        if old_decl.sym_type != Type::Int {
+        if let Type::Function(..) = old_decl.sym_type {
             return Err("Function redeclared as variable".to_string());
         }
+        var_declaration.var_type =
+            get_composite_type(&old_decl.sym_type, &var_declaration.var_type).ok_or_else(
+                || format!("Conflicting variable types: {}", var_declaration.name),
+            )?;
 
         match (&var_declaration.storage_class, &old_decl.identifier_attrs) {
             (Some(StorageClass::Extern), IdentifierAttr::StaticAttr(_, glob)) => {
@@ -138,19 +449,21 @@ fn typecheck_file_scope_variable_declaration(
             _ => {}
         }
 
-        match &old_decl.identifier_attrs {
-            IdentifierAttr::StaticAttr(InitialValue::Initial(_), _) => {
+        // An initializer wins over a tentative definition, which wins over no definition
+        match (&old_decl.identifier_attrs, &initial_value) {
+            (
+                IdentifierAttr::StaticAttr(InitialValue::Initial(_), _),
+                InitialValue::Initial(_),
+            ) => {
                 return Err("Conflicting file scope variable definition".to_string());
             }
-            IdentifierAttr::StaticAttr(init, _) => {
-                initial_value = *init;
+            (IdentifierAttr::StaticAttr(old_init @ InitialValue::Initial(_), _), _) => {
+                initial_value = old_init.clone();
             }
-            _ if matches!(initial_value, InitialValue::Initial(_))
-                && matches!(
-                    old_decl.identifier_attrs,
-                    IdentifierAttr::StaticAttr(InitialValue::Tentative, _)
-                ) =>
-            {
+            (
+                IdentifierAttr::StaticAttr(InitialValue::Tentative, _),
+                InitialValue::NoInitializer,
+            ) => {
                 initial_value = InitialValue::Tentative;
             }
             _ => {}
@@ -160,7 +473,7 @@ fn typecheck_file_scope_variable_declaration(
     symbol_table.insert(
         var_declaration.name.clone(),
         SymbolEntry {
-            sym_type: Type::Int,
+            sym_type: var_declaration.var_type.clone(),
             defined: true,
             identifier_attrs: IdentifierAttr::StaticAttr(initial_value, global),
         },
@@ -169,11 +482,50 @@ fn typecheck_file_scope_variable_declaration(
     Ok(())
 }
 
-fn typecheck_block(block: &Block, symbol_table: &mut SymbolTable) -> Result<(), String> {
+// An array without a size gets one from its initializer, a string literal includes its null byte
+fn complete_array_type(var_declaration: &mut VariableDeclaration) {
+    let element_type = match &var_declaration.var_type {
+        Type::IncompleteArray(element_type) => element_type,
+        _ => return,
+    };
+    let size = match &var_declaration.init {
+        Some(Initializer::Compound(initializers, _)) => initializers.len() as i64,
+        Some(Initializer::Single(Expression {
+            kind: ExpressionKind::String(bytes),
+            ..
+        })) if element_type.is_character() => bytes.len() as i64 + 1,
+        _ => return,
+    };
+    var_declaration.var_type = Type::Array(element_type.clone(), size);
+}
+
+// Declarations of a variable must agree on its type, but only one of them needs an array's size
+fn get_composite_type(old_type: &Type, new_type: &Type) -> Option<Type> {
+    match (old_type, new_type) {
+        (Type::IncompleteArray(old_element), Type::Array(new_element, _))
+            if old_element == new_element =>
+        {
+            Some(new_type.clone())
+        }
+        (Type::Array(old_element, _), Type::IncompleteArray(new_element))
+            if old_element == new_element =>
+        {
+            Some(old_type.clone())
+        }
+        _ if old_type == new_type => Some(new_type.clone()),
+        _ => None,
+    }
+}
+
+fn typecheck_block(
+    block: &mut Block,
+    symbol_table: &mut SymbolTable,
+    return_type: &Type,
+) -> Result<(), String> {
     for block_item in block {
         match block_item {
             BlockItem::D(declaration) => typecheck_declaration(declaration, symbol_table)?,
-            BlockItem::S(statement) => typecheck_statement(statement, symbol_table)?,
+            BlockItem::S(statement) => typecheck_statement(statement, symbol_table, return_type)?,
         }
     }
 
@@ -181,7 +533,7 @@ fn typecheck_block(block: &Block, symbol_table: &mut SymbolTable) -> Result<(),
 }
 
 fn typecheck_declaration(
-    declaration: &Declaration,
+    declaration: &mut Declaration,
     symbol_table: &mut SymbolTable,
 ) -> Result<(), String> {
     match declaration {
@@ -191,150 +543,1094 @@ fn typecheck_declaration(
         Declaration::FuncDecl(func_declaration) => {
             typecheck_function_declaration(func_declaration, symbol_table)
         }
+        Declaration::StructDecl(struct_declaration) => {
+            typecheck_struct_declaration(struct_declaration, symbol_table)
+        }
+        Declaration::EnumDecl(_) => Ok(()),
     }
 }
 
 fn typecheck_statement(
-    statement: &Statement,
+    statement: &mut Statement,
     symbol_table: &mut SymbolTable,
+    return_type: &Type,
 ) -> Result<(), String> {
     match statement {
-        Statement::Return(expression) => {
-            typecheck_expression(expression, symbol_table)?;
+        Statement::Return(Some(expression)) => {
+            if *return_type == Type::Void {
+                return Err("A void function can't return a value".to_string());
+            }
+            typecheck_and_convert(expression, symbol_table)?;
+            convert_by_assignment(expression, return_type)?;
+        }
+        Statement::Return(None) => {
+            if *return_type != Type::Void {
+                return Err("Missing return value".to_string());
+            }
         }
         Statement::Expression(expression) => {
-            typecheck_expression(expression, symbol_table)?;
+            typecheck_and_convert(expression, symbol_table)?;
         }
         Statement::Null => {}
         Statement::If(expression, statement1, statement2) => {
-            typecheck_expression(expression, symbol_table)?;
-            typecheck_statement(statement1, symbol_table)?;
+            typecheck_and_convert(expression, symbol_table)?;
+            check_scalar(expression)?;
+            typecheck_statement(statement1, symbol_table, return_type)?;
             if let Some(statement) = statement2 {
-                typecheck_statement(statement, symbol_table)?;
+                typecheck_statement(statement, symbol_table, return_type)?;
             }
         }
         Statement::Compound(vec) => {
-            typecheck_block(vec, symbol_table)?;
+            typecheck_block(vec, symbol_table, return_type)?;
         }
         Statement::Break(_) => {}
         Statement::Continue(_) => {}
+        Statement::Goto(_) => {}
+        Statement::Labeled(_, statement) => {
+            typecheck_statement(statement, symbol_table, return_type)?;
+        }
         Statement::While(expression, statement, _) => {
-            typecheck_expression(expression, symbol_table)?;
-            typecheck_statement(statement, symbol_table)?;
+            typecheck_and_convert(expression, symbol_table)?;
+            check_scalar(expression)?;
+            typecheck_statement(statement, symbol_table, return_type)?;
         }
         Statement::DoWhile(statement, expression, _) => {
-            typecheck_statement(statement, symbol_table)?;
-            typecheck_expression(expression, symbol_table)?;
+            typecheck_statement(statement, symbol_table, return_type)?;
+            typecheck_and_convert(expression, symbol_table)?;
+            check_scalar(expression)?;
         }
         Statement::For(for_init, expression1, expression2, statement, _) => {
             match for_init {
-                ForInit::InitDeclaration(declaration) => {
-                    if declaration.storage_class != None {
-                        return Err("Variable declaration in for initiation can't have a storage class".to_string());
+                ForInit::InitDeclaration(declarations) => {
+                    for declaration in declarations {
+                        if declaration.storage_class.is_some() {
+                            return Err("Variable declaration in for initiation can't have a \
+                                storage class"
+                                .to_string());
+                        }
+                        typecheck_local_var_declaration(declaration, symbol_table)?;
                     }
-                    typecheck_local_var_declaration(declaration, symbol_table)?;
                 }
                 ForInit::InitExpression(expression) => {
                     if let Some(expression) = expression {
-                        typecheck_expression(expression, symbol_table)?;
+                        typecheck_and_convert(expression, symbol_table)?;
                     }
                 }
             }
             if let Some(expression) = expression1 {
-                typecheck_expression(expression, symbol_table)?;
+                typecheck_and_convert(expression, symbol_table)?;
+                check_scalar(expression)?;
             }
             if let Some(expression) = expression2 {
-                typecheck_expression(expression, symbol_table)?;
+                typecheck_and_convert(expression, symbol_table)?;
             }
-            typecheck_statement(statement, symbol_table)?;
+            typecheck_statement(statement, symbol_table, return_type)?;
         }
-        Statement::Switch(expression, vec, statement, _) => {
-            typecheck_expression(expression, symbol_table)?;
-            for case in vec {
-                typecheck_statement(&case.body, symbol_table)?;
+        Statement::Switch(expression, statement, cases, _) => {
+            typecheck_and_convert(expression, symbol_table)?;
+            if !expression.get_type().is_integer() {
+                return Err("Switch controlling expression must have an integer type".to_string());
             }
-            if let Some(statement) = statement {
-                typecheck_statement(statement, symbol_table)?;
+            let promoted_type = expression.get_type().promote();
+            convert_to(expression, &promoted_type);
+            // Case values are converted to the promoted type before they're compared
+            let mut values: Vec<i64> = Vec::new();
+            for value in cases.iter_mut().filter_map(|case| case.value.as_mut()) {
+                *value = convert_integer(*value, &promoted_type);
+                if values.contains(value) {
+                    return Err(format!("Duplicate case value in switch: {}", value));
+                }
+                values.push(*value);
             }
+            typecheck_statement(statement, symbol_table, return_type)?;
+        }
+        Statement::Case(_, statement, _) | Statement::Default(statement, _) => {
+            typecheck_statement(statement, symbol_table, return_type)?;
         }
     }
 
     Ok(())
 }
 
+// Arrays decay to a pointer to their first element and functions to a pointer to the function
+// everywhere except as the operand of &. Any other value loses the qualifiers of its lvalue.
+fn typecheck_and_convert(
+    expression: &mut Expression,
+    symbol_table: &mut SymbolTable,
+) -> Result<(), String> {
+    typecheck_expression(expression, symbol_table)?;
+    if let Type::Qualified(inner, ..) = expression.get_type() {
+        expression.exp_type = Some(*inner.clone());
+    }
+    let pointer_type = match expression.get_type() {
+        Type::Array(element_type, _) | Type::IncompleteArray(element_type) => {
+            Some(Type::Pointer(element_type.clone()))
+        }
+        function_type @ Type::Function(..) => Some(Type::Pointer(Box::new(function_type.clone()))),
+        _ => None,
+    };
+    if let Some(pointer_type) = pointer_type {
+        let inner = std::mem::replace(
+            expression,
+            Expression::new(ExpressionKind::Constant(Const::Int(0))),
+        );
+        *expression = Expression {
+            kind: ExpressionKind::AddressOf(Box::new(inner)),
+            exp_type: Some(pointer_type),
+        };
+    }
+    Ok(())
+}
+
 fn typecheck_expression(
-    expression: &Expression,
+    expression: &mut Expression,
     symbol_table: &mut SymbolTable,
 ) -> Result<(), String> {
-    match expression {
-        Expression::FunctionCall(func_name, args) => {
-            if !symbol_table.contains_key(func_name) {
-                return Err(format!("Function not declared: {}", func_name));
-            }
-            let func_type = &symbol_table.get(func_name).unwrap().sym_type;
-            if *func_type == Type::Int {
-                return Err(format!("Variable used as function name: {}", func_name));
+    // String literals are replaced by a read only static array
+    if let ExpressionKind::String(bytes) = &expression.kind {
+        let string_type = Type::Array(Box::new(Type::Char), bytes.len() as i64 + 1);
+        let name = make_string_constant(bytes.clone(), symbol_table);
+        expression.kind = ExpressionKind::Var(name);
+        expression.exp_type = Some(string_type);
+        return Ok(());
+    }
+    // sizeof is folded to a constant, its operand is type checked but never evaluated
+    let measured_type = match &mut expression.kind {
+        ExpressionKind::SizeOfType(target_type) => Some(target_type.clone()),
+        ExpressionKind::SizeOf(inner) => {
+            typecheck_expression(inner, symbol_table)?;
+            Some(inner.get_type().clone())
+        }
+        _ => None,
+    };
+    if let Some(measured_type) = measured_type {
+        if let Type::Function(..) = measured_type {
+            return Err("Can't take the size of a function".to_string());
+        }
+        if !is_complete(&measured_type, symbol_table) {
+            return Err("Can't take the size of an incomplete type".to_string());
+        }
+        let size = measured_type.size(symbol_table) as u64;
+        expression.kind = ExpressionKind::Constant(Const::ULong(size));
+        expression.exp_type = Some(Type::ULong);
+        return Ok(());
+    }
+    let exp_type = match &mut expression.kind {
+        ExpressionKind::FunctionCall(function, args) => {
+            typecheck_and_convert(function, symbol_table)?;
+            let (param_types, return_type, variadic) = match function.get_type() {
+                Type::Pointer(referenced) => match referenced.as_ref() {
+                    Type::Function(param_types, return_type, variadic) => {
+                        (param_types.clone(), *return_type.clone(), *variadic)
+                    }
+                    _ => return Err("Called object isn't a function".to_string()),
+                },
+                _ => return Err("Called object isn't a function".to_string()),
+            };
+            if param_types.len() != args.len() && !(variadic && args.len() > param_types.len()) {
+                return Err("Function called with the wrong number of arguments".to_string());
             }
-            if *func_type != Type::Function(args.len()) {
-                return Err(format!(
-                    "Function called with the wrong number of arguments: {}",
-                    func_name
-                ));
+            for (arg, param_type) in args.iter_mut().zip(&param_types) {
+                typecheck_and_convert(arg, symbol_table)?;
+                convert_by_assignment(arg, param_type)?;
             }
-            for arg in args {
-                typecheck_expression(&arg, symbol_table)?;
+            // Arguments matching the ellipsis only get the default argument promotions
+            for arg in args.iter_mut().skip(param_types.len()) {
+                typecheck_and_convert(arg, symbol_table)?;
+                if *arg.get_type() == Type::Void {
+                    return Err("Void value passed as an argument".to_string());
+                }
+                let promoted_type = arg.get_type().promote();
+                convert_to(arg, &promoted_type);
             }
+            return_type
         }
-        Expression::Var(var_name) => {
+        ExpressionKind::Var(var_name) => {
             if !symbol_table.contains_key(var_name) {
                 return Err(format!("Variable not declared: {}", var_name));
             }
 
-            if symbol_table.get(var_name).unwrap().sym_type != Type::Int {
-                return Err(format!("Function used as variable name: {}", var_name));
+            symbol_table.get(var_name).unwrap().sym_type.clone()
+        }
+        ExpressionKind::Constant(Const::Int(_)) => Type::Int,
+        ExpressionKind::Constant(Const::Long(_)) => Type::Long,
+        ExpressionKind::Constant(Const::UInt(_)) => Type::UInt,
+        ExpressionKind::Constant(Const::ULong(_)) => Type::ULong,
+        ExpressionKind::Constant(Const::Double(_)) => Type::Double,
+        ExpressionKind::Constant(Const::Char(_)) => Type::Char,
+        ExpressionKind::Constant(Const::UChar(_)) => Type::UChar,
+        ExpressionKind::String(_) => unreachable!("String literals are replaced above"),
+        ExpressionKind::SizeOf(_) | ExpressionKind::SizeOfType(_) => {
+            unreachable!("sizeof is folded above")
+        }
+        ExpressionKind::VaStart(va_list) | ExpressionKind::VaEnd(va_list) => {
+            typecheck_and_convert(va_list, symbol_table)?;
+            check_va_list(va_list)?;
+            Type::Void
+        }
+        ExpressionKind::VaCopy(destination, source) => {
+            typecheck_and_convert(destination, symbol_table)?;
+            check_va_list(destination)?;
+            typecheck_and_convert(source, symbol_table)?;
+            check_va_list(source)?;
+            Type::Void
+        }
+        ExpressionKind::VaArg(va_list, arg_type) => {
+            typecheck_and_convert(va_list, symbol_table)?;
+            check_va_list(va_list)?;
+            if matches!(arg_type, Type::Array(_, _) | Type::Function(..))
+                || !is_complete(arg_type, symbol_table)
+            {
+                return Err(format!("Invalid type for va_arg: {:?}", arg_type));
+            }
+            arg_type.unqualified().clone()
+        }
+        // Any value can be discarded by a cast to void
+        ExpressionKind::Cast(target_type, expression)
+            if *target_type.unqualified() == Type::Void =>
+        {
+            *target_type = Type::Void;
+            typecheck_and_convert(expression, symbol_table)?;
+            Type::Void
+        }
+        ExpressionKind::Cast(target_type, expression) => {
+            *target_type = target_type.unqualified().clone();
+            typecheck_and_convert(expression, symbol_table)?;
+            let inner_type = expression.get_type();
+            if !target_type.is_scalar() || !inner_type.is_scalar() {
+                return Err(format!("Invalid cast to: {:?}", target_type));
+            }
+            if (*target_type == Type::Double && is_pointer(inner_type))
+                || (is_pointer(target_type) && *inner_type == Type::Double)
+            {
+                return Err("Can't cast between a pointer and a double".to_string());
+            }
+            target_type.clone()
+        }
+        ExpressionKind::Unary(UnaryOperator::Not, expression) => {
+            typecheck_and_convert(expression, symbol_table)?;
+            check_scalar(expression)?;
+            Type::Int
+        }
+        ExpressionKind::Unary(op, expression) => {
+            typecheck_and_convert(expression, symbol_table)?;
+            if *op == UnaryOperator::Complement && *expression.get_type() == Type::Double {
+                return Err("Can't take the bitwise complement of a double".to_string());
+            }
+            if !expression.get_type().is_arithmetic() {
+                return Err(format!("Invalid operand for {:?}", op));
+            }
+            let promoted_type = expression.get_type().promote();
+            convert_to(expression, &promoted_type);
+            promoted_type
+        }
+        ExpressionKind::Dereference(inner) => {
+            typecheck_and_convert(inner, symbol_table)?;
+            match inner.get_type() {
+                Type::Pointer(referenced) if *referenced.unqualified() == Type::Void => {
+                    return Err("Can't dereference a pointer to void".to_string())
+                }
+                Type::Pointer(referenced) => *referenced.clone(),
+                _ => return Err("Can't dereference a non-pointer".to_string()),
+            }
+        }
+        ExpressionKind::AddressOf(inner) => {
+            typecheck_expression(inner, symbol_table)?;
+            Type::Pointer(Box::new(inner.get_type().clone()))
+        }
+        ExpressionKind::Subscript(expression1, expression2) => {
+            typecheck_and_convert(expression1, symbol_table)?;
+            typecheck_and_convert(expression2, symbol_table)?;
+            let (pointer, index) = if expression2.get_type().is_integer() {
+                (expression1, expression2)
+            } else {
+                (expression2, expression1)
+            };
+            let referenced_type = match pointer.get_type() {
+                Type::Pointer(referenced)
+                    if index.get_type().is_integer() && is_object_pointer(pointer.get_type()) =>
+                {
+                    *referenced.clone()
+                }
+                _ => return Err("Subscript requires a pointer and an integer operand".to_string()),
+            };
+            convert_to(index, &Type::Long);
+            referenced_type
+        }
+        ExpressionKind::Binary(op, expression1, expression2) => {
+            typecheck_and_convert(expression1, symbol_table)?;
+            typecheck_and_convert(expression2, symbol_table)?;
+            check_double_operands(op, expression1, expression2)?;
+            let both_arithmetic =
+                expression1.get_type().is_arithmetic() && expression2.get_type().is_arithmetic();
+            let type1 = expression1.get_type().clone();
+            let type2 = expression2.get_type().clone();
+            match op {
+                BinaryOperator::LAnd | BinaryOperator::LOr => {
+                    check_scalar(expression1)?;
+                    check_scalar(expression2)?;
+                    Type::Int
+                }
+                BinaryOperator::EqualTo | BinaryOperator::NotEqualTo if !both_arithmetic => {
+                    let common_type = get_common_pointer_type(expression1, expression2)?;
+                    convert_to(expression1, &common_type);
+                    convert_to(expression2, &common_type);
+                    Type::Int
+                }
+                // Pointer arithmetic, the integer operand is scaled by the tacker
+                BinaryOperator::Add if is_object_pointer(&type1) && type2.is_integer() => {
+                    convert_to(expression2, &Type::Long);
+                    type1
+                }
+                BinaryOperator::Add if type1.is_integer() && is_object_pointer(&type2) => {
+                    convert_to(expression1, &Type::Long);
+                    type2
+                }
+                BinaryOperator::Subtract if is_object_pointer(&type1) && type2.is_integer() => {
+                    convert_to(expression2, &Type::Long);
+                    type1
+                }
+                BinaryOperator::Subtract
+                    if is_object_pointer(&type1) && same_referenced_type(&type1, &type2) =>
+                {
+                    Type::Long
+                }
+                BinaryOperator::LessThan
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::GreaterOrEqual
+                    if same_referenced_type(&type1, &type2) =>
+                {
+                    Type::Int
+                }
+                _ if !both_arithmetic => {
+                    return Err(format!("Invalid operand of pointer type for {:?}", op));
+                }
+                // The result of a shift has the type of its left operand
+                BinaryOperator::LeftShift | BinaryOperator::RightShift => {
+                    let promoted_type = expression1.get_type().promote();
+                    convert_to(expression1, &promoted_type);
+                    promoted_type
+                }
+                _ => {
+                    let common_type =
+                        get_common_type(expression1.get_type(), expression2.get_type());
+                    convert_to(expression1, &common_type);
+                    convert_to(expression2, &common_type);
+                    match op {
+                        BinaryOperator::Add
+                        | BinaryOperator::Subtract
+                        | BinaryOperator::Multiply
+                        | BinaryOperator::Divide
+                        | BinaryOperator::Modulo
+                        | BinaryOperator::And
+                        | BinaryOperator::Or
+                        | BinaryOperator::Xor => common_type,
+                        _ => Type::Int,
+                    }
+                }
             }
         }
-        Expression::Constant(_) => {}
-        Expression::Unary(_, expression) => {
-            typecheck_expression(&expression, symbol_table)?;
+        ExpressionKind::Assignment(op, expression1, expression2) => {
+            typecheck_expression(expression1, symbol_table)?;
+            typecheck_and_convert(expression2, symbol_table)?;
+            if expression1.get_type().is_const() {
+                return Err("Can't assign to a const lvalue".to_string());
+            }
+            let left_type = expression1.get_type().unqualified().clone();
+            if let Type::Array(_, _) | Type::IncompleteArray(_) = left_type {
+                return Err("Can't assign to an array".to_string());
+            }
+            if let Type::Function(..) = left_type {
+                return Err("Can't assign to a function".to_string());
+            }
+            if let Some(op) = op {
+                check_double_operands(op, expression1, expression2)?;
+                let pointer_arithmetic = matches!(op, BinaryOperator::Add | BinaryOperator::Subtract)
+                    && is_object_pointer(&left_type)
+                    && expression2.get_type().is_integer();
+                if !pointer_arithmetic
+                    && (!left_type.is_arithmetic() || !expression2.get_type().is_arithmetic())
+                {
+                    return Err(format!("Invalid operand of pointer type for {:?}", op));
+                }
+            }
+            match op {
+                None => convert_by_assignment(expression2, &left_type)?,
+                Some(_) if is_pointer(&left_type) => convert_to(expression2, &Type::Long),
+                Some(BinaryOperator::LeftShift | BinaryOperator::RightShift) => {}
+                // The operation itself is performed in the common type, the result is then
+                // converted back to the type of the left operand
+                Some(_) => {
+                    let common_type = get_common_type(&left_type, expression2.get_type());
+                    convert_to(expression2, &common_type);
+                }
+            }
+            left_type
         }
-        Expression::Binary(_, expression1, expression2) => {
-            typecheck_expression(&expression1, symbol_table)?;
-            typecheck_expression(&expression2, symbol_table)?;
+        ExpressionKind::Postfix(op, inner) => {
+            typecheck_expression(inner, symbol_table)?;
+            if inner.get_type().is_const() {
+                return Err("Can't assign to a const lvalue".to_string());
+            }
+            let inner_type = inner.get_type().unqualified().clone();
+            if !inner_type.is_arithmetic() && !is_object_pointer(&inner_type) {
+                return Err(format!("Invalid operand for postfix {:?}", op));
+            }
+            inner_type
         }
-        Expression::Assignment(_, expression1, expression2) => {
-            typecheck_expression(&expression1, symbol_table)?;
-            typecheck_expression(&expression2, symbol_table)?;
+        // A member of a qualified structure has the same qualifiers
+        ExpressionKind::Dot(structure, member) => {
+            typecheck_expression(structure, symbol_table)?;
+            let (is_const, is_volatile) = structure.get_type().qualifiers();
+            match structure.get_type().unqualified() {
+                Type::Structure(tag) => {
+                    get_member_type(tag, member, symbol_table)?.qualify(is_const, is_volatile)
+                }
+                _ => return Err(format!("Member access on a non-structure: {}", member)),
+            }
         }
-        Expression::Conditional(expression1, expression2, expression3) => {
-            typecheck_expression(&expression1, symbol_table)?;
-            typecheck_expression(&expression2, symbol_table)?;
-            typecheck_expression(&expression3, symbol_table)?;
+        ExpressionKind::Arrow(pointer, member) => {
+            typecheck_and_convert(pointer, symbol_table)?;
+            match pointer.get_type() {
+                Type::Pointer(referenced) => {
+                    let (is_const, is_volatile) = referenced.qualifiers();
+                    match referenced.unqualified() {
+                        Type::Structure(tag) => get_member_type(tag, member, symbol_table)?
+                            .qualify(is_const, is_volatile),
+                        _ => return Err(format!("Member access on a non-structure: {}", member)),
+                    }
+                }
+                _ => return Err(format!("Arrow applied to a non-pointer: {}", member)),
+            }
         }
+        // The left operand is only evaluated for its side effects
+        ExpressionKind::Comma(expression1, expression2) => {
+            typecheck_and_convert(expression1, symbol_table)?;
+            typecheck_and_convert(expression2, symbol_table)?;
+            expression2.get_type().clone()
+        }
+        ExpressionKind::Conditional(expression1, expression2, expression3) => {
+            typecheck_and_convert(expression1, symbol_table)?;
+            check_scalar(expression1)?;
+            typecheck_and_convert(expression2, symbol_table)?;
+            typecheck_and_convert(expression3, symbol_table)?;
+            let type2 = expression2.get_type();
+            let type3 = expression3.get_type();
+            let common_type = if type2.is_arithmetic() && type3.is_arithmetic() {
+                get_common_type(type2, type3)
+            } else if *type2 == Type::Void && *type3 == Type::Void {
+                Type::Void
+            } else if matches!(type2, Type::Structure(_)) && type2 == type3 {
+                type2.clone()
+            } else {
+                get_common_pointer_type(expression2, expression3)?
+            };
+            convert_to(expression2, &common_type);
+            convert_to(expression3, &common_type);
+            common_type
+        }
+    };
+    expression.exp_type = Some(exp_type);
+
+    Ok(())
+}
+
+fn get_member_type(tag: &str, member: &str, symbol_table: &SymbolTable) -> Result<Type, String> {
+    if !symbol_table.contains_key(tag) {
+        return Err(format!("Member access on an incomplete structure: {}", member));
+    }
+    match get_struct_entry(tag, symbol_table)
+        .members
+        .iter()
+        .find(|entry| entry.name == member)
+    {
+        Some(entry) => Ok(entry.member_type.clone()),
+        None => Err(format!("Structure has no member named: {}", member)),
     }
+}
+
+// A va_list decays to a pointer to its first element like any other array
+fn check_va_list(expression: &Expression) -> Result<(), String> {
+    match expression.get_type() {
+        Type::Pointer(referenced) if **referenced == Type::ULong => Ok(()),
+        other => Err(format!("Expected a va_list, got: {:?}", other)),
+    }
+}
 
+fn check_scalar(expression: &Expression) -> Result<(), String> {
+    if expression.get_type().is_scalar() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected an expression of scalar type, got: {:?}",
+            expression.get_type()
+        ))
+    }
+}
+
+fn check_double_operands(
+    op: &BinaryOperator,
+    expression1: &Expression,
+    expression2: &Expression,
+) -> Result<(), String> {
+    let integer_only = matches!(
+        op,
+        BinaryOperator::Modulo
+            | BinaryOperator::And
+            | BinaryOperator::Or
+            | BinaryOperator::Xor
+            | BinaryOperator::LeftShift
+            | BinaryOperator::RightShift
+    );
+    if integer_only
+        && (*expression1.get_type() == Type::Double || *expression2.get_type() == Type::Double)
+    {
+        return Err(format!("Invalid operand of type double for {:?}", op));
+    }
     Ok(())
 }
 
+pub fn get_common_type(type1: &Type, type2: &Type) -> Type {
+    let type1 = &type1.promote();
+    let type2 = &type2.promote();
+    if type1 == type2 {
+        type1.clone()
+    } else if *type1 == Type::Double || *type2 == Type::Double {
+        Type::Double
+    } else if arithmetic_size(type1) == arithmetic_size(type2) {
+        // Same rank, the unsigned type wins
+        if type1.is_signed() {
+            type2.clone()
+        } else {
+            type1.clone()
+        }
+    } else if arithmetic_size(type1) > arithmetic_size(type2) {
+        type1.clone()
+    } else {
+        type2.clone()
+    }
+}
+
+// Sizes of the promoted arithmetic types, which don't depend on any structure layout
+fn arithmetic_size(arithmetic_type: &Type) -> i64 {
+    match arithmetic_type {
+        Type::Int | Type::UInt => 4,
+        _ => 8,
+    }
+}
+
+fn is_pointer(exp_type: &Type) -> bool {
+    matches!(exp_type, Type::Pointer(_))
+}
+
+// Pointer arithmetic needs the size of the referenced type, which functions and void don't have
+fn is_object_pointer(exp_type: &Type) -> bool {
+    match exp_type {
+        Type::Pointer(referenced) => {
+            !matches!(referenced.unqualified(), Type::Function(..) | Type::Void)
+        }
+        _ => false,
+    }
+}
+
+fn is_void_pointer(exp_type: &Type) -> bool {
+    matches!(exp_type, Type::Pointer(referenced) if *referenced.unqualified() == Type::Void)
+}
+
+// Pointers to differently qualified versions of the same type
+fn same_referenced_type(type1: &Type, type2: &Type) -> bool {
+    match (type1, type2) {
+        (Type::Pointer(referenced1), Type::Pointer(referenced2)) => {
+            referenced1.unqualified() == referenced2.unqualified()
+        }
+        _ => false,
+    }
+}
+
+// The referenced type of a pointer conversion can gain qualifiers but never lose them
+fn keeps_qualifiers(from: &Type, to: &Type) -> bool {
+    match (from, to) {
+        (Type::Pointer(from_referenced), Type::Pointer(to_referenced)) => {
+            let (from_const, from_volatile) = from_referenced.qualifiers();
+            let (to_const, to_volatile) = to_referenced.qualifiers();
+            (to_const || !from_const) && (to_volatile || !from_volatile)
+        }
+        _ => true,
+    }
+}
+
+// A pointer to the referenced type of the first with the qualifiers of both
+fn merge_pointer_qualifiers(type1: &Type, type2: &Type) -> Type {
+    match (type1, type2) {
+        (Type::Pointer(referenced1), Type::Pointer(referenced2)) => {
+            let (is_const, is_volatile) = referenced2.qualifiers();
+            Type::Pointer(Box::new(referenced1.as_ref().clone().qualify(is_const, is_volatile)))
+        }
+        _ => type1.clone(),
+    }
+}
+
+fn is_null_pointer_constant(expression: &Expression) -> bool {
+    matches!(
+        expression.kind,
+        ExpressionKind::Constant(Const::Int(0) | Const::Long(0) | Const::UInt(0) | Const::ULong(0))
+    )
+}
+
+fn get_common_pointer_type(expression1: &Expression, expression2: &Expression) -> Result<Type, String> {
+    let type1 = expression1.get_type();
+    let type2 = expression2.get_type();
+    if is_pointer(type1) && is_null_pointer_constant(expression2) {
+        Ok(type1.clone())
+    } else if is_pointer(type2) && is_null_pointer_constant(expression1) {
+        Ok(type2.clone())
+    } else if same_referenced_type(type1, type2)
+        || (is_void_pointer(type1) && is_pointer(type2))
+    {
+        Ok(merge_pointer_qualifiers(type1, type2))
+    } else if is_pointer(type1) && is_void_pointer(type2) {
+        Ok(merge_pointer_qualifiers(type2, type1))
+    } else {
+        Err("Expressions have incompatible types".to_string())
+    }
+}
+
+// Implicit conversion as if by assignment, only arithmetic types, null pointers and pointers to
+// and from void convert
+fn convert_by_assignment(expression: &mut Expression, target_type: &Type) -> Result<(), String> {
+    let target_type = target_type.unqualified();
+    let exp_type = expression.get_type();
+    let pointer_conversion = same_referenced_type(exp_type, target_type)
+        || (is_void_pointer(target_type) && is_pointer(exp_type))
+        || (is_pointer(target_type) && is_void_pointer(exp_type));
+    if pointer_conversion && !keeps_qualifiers(exp_type, target_type) {
+        return Err(format!(
+            "Conversion from {:?} to {:?} discards qualifiers",
+            exp_type, target_type
+        ));
+    }
+    if (exp_type == target_type && *exp_type != Type::Void)
+        || (exp_type.is_arithmetic() && target_type.is_arithmetic())
+        || (matches!(target_type, Type::Pointer(_)) && is_null_pointer_constant(expression))
+        || pointer_conversion
+    {
+        convert_to(expression, target_type);
+        Ok(())
+    } else {
+        Err(format!(
+            "Can't convert {:?} to {:?} as if by assignment",
+            exp_type, target_type
+        ))
+    }
+}
+
+fn convert_to(expression: &mut Expression, target_type: &Type) {
+    let target_type = target_type.unqualified();
+    if expression.get_type() == target_type {
+        return;
+    }
+    let inner = std::mem::replace(
+        expression,
+        Expression::new(ExpressionKind::Constant(Const::Int(0))),
+    );
+    *expression = Expression {
+        kind: ExpressionKind::Cast(target_type.clone(), Box::new(inner)),
+        exp_type: Some(target_type.clone()),
+    };
+}
+
+fn convert_constant_to_static_init(
+    constant: &Const,
+    target_type: &Type,
+) -> Result<StaticInit, String> {
+    let target_type = target_type.unqualified();
+    if let Type::Pointer(_) = target_type {
+        return match constant {
+            Const::Int(0) | Const::Long(0) | Const::UInt(0) | Const::ULong(0) => {
+                Ok(StaticInit::ULongInit(0))
+            }
+            _ => Err("Pointers can only be statically initialized to null".to_string()),
+        };
+    }
+    if *target_type == Type::Double {
+        return Ok(StaticInit::DoubleInit(match *constant {
+            Const::Char(value) => value as f64,
+            Const::UChar(value) => value as f64,
+            Const::Int(value) => value as f64,
+            Const::Long(value) => value as f64,
+            Const::UInt(value) => value as f64,
+            Const::ULong(value) => value as f64,
+            Const::Double(value) => value,
+        }));
+    }
+    let value = match *constant {
+        Const::Char(value) => value as i64,
+        Const::UChar(value) => value as i64,
+        Const::Int(value) => value as i64,
+        Const::Long(value) => value,
+        Const::UInt(value) => value as i64,
+        Const::ULong(value) => value as i64,
+        // Out of range values are undefined behavior, saturate like Rust does
+        Const::Double(value) if target_type.is_signed() => value as i64,
+        Const::Double(value) => value as u64 as i64,
+    };
+    Ok(match target_type {
+        Type::Char | Type::SChar => StaticInit::CharInit(value as i8),
+        Type::UChar => StaticInit::UCharInit(value as u8),
+        Type::Long => StaticInit::LongInit(value),
+        Type::UInt => StaticInit::UIntInit(value as u32),
+        Type::ULong => StaticInit::ULongInit(value as u64),
+        _ => StaticInit::IntInit(value as i32),
+    })
+}
+
+fn convert_initializer_to_static_init(
+    initializer: &Initializer,
+    target_type: &Type,
+    symbol_table: &mut SymbolTable,
+) -> Result<Vec<StaticInit>, String> {
+    let target_type = target_type.unqualified();
+    match (initializer, target_type) {
+        (
+            Initializer::Single(Expression {
+                kind: ExpressionKind::String(bytes),
+                ..
+            }),
+            Type::Array(element_type, size),
+        ) if element_type.is_character() => {
+            let length = bytes.len() as i64;
+            if length > *size {
+                return Err("String literal is too long for its array".to_string());
+            }
+            let mut static_inits = vec![StaticInit::StringInit(bytes.clone(), length < *size)];
+            if *size > length + 1 {
+                static_inits.push(StaticInit::ZeroInit(*size - length - 1));
+            }
+            Ok(static_inits)
+        }
+        (
+            Initializer::Single(Expression {
+                kind: ExpressionKind::String(bytes),
+                ..
+            }),
+            Type::Pointer(referenced),
+        ) if *referenced.unqualified() == Type::Char => Ok(vec![StaticInit::PointerInit(
+            make_string_constant(bytes.clone(), symbol_table),
+            0,
+        )]),
+        (Initializer::Compound(initializers, _), Type::Array(element_type, size)) => {
+            if initializers.len() as i64 > *size {
+                return Err("Too many elements in array initializer".to_string());
+            }
+            let mut static_inits: Vec<StaticInit> = Vec::new();
+            for initializer in initializers {
+                static_inits.extend(convert_initializer_to_static_init(
+                    initializer,
+                    element_type,
+                    symbol_table,
+                )?);
+            }
+            let missing = *size - initializers.len() as i64;
+            if missing > 0 {
+                static_inits.push(StaticInit::ZeroInit(
+                    missing * element_type.size(symbol_table),
+                ));
+            }
+            Ok(static_inits)
+        }
+        (Initializer::Compound(initializers, _), Type::Structure(tag)) => {
+            let members: Vec<(Type, i64)> = get_struct_entry(tag, symbol_table)
+                .initialized_members()
+                .iter()
+                .map(|member| (member.member_type.clone(), member.offset))
+                .collect();
+            if initializers.len() > members.len() {
+                return Err("Too many elements in structure initializer".to_string());
+            }
+            // Padding between members and after the last one is zeroed
+            let mut static_inits: Vec<StaticInit> = Vec::new();
+            let mut current_offset = 0;
+            for (initializer, (member_type, offset)) in initializers.iter().zip(members) {
+                if offset > current_offset {
+                    static_inits.push(StaticInit::ZeroInit(offset - current_offset));
+                }
+                static_inits.extend(convert_initializer_to_static_init(
+                    initializer,
+                    &member_type,
+                    symbol_table,
+                )?);
+                current_offset = offset + member_type.size(symbol_table);
+            }
+            let struct_size = target_type.size(symbol_table);
+            if struct_size > current_offset {
+                static_inits.push(StaticInit::ZeroInit(struct_size - current_offset));
+            }
+            Ok(static_inits)
+        }
+        (Initializer::Compound(_, _), _) => {
+            Err("Compound initializer for a scalar object".to_string())
+        }
+        (Initializer::Single(_), Type::Array(_, _)) => {
+            Err("Arrays need a compound initializer".to_string())
+        }
+        (Initializer::Single(_), Type::Structure(_)) => {
+            Err("Structures need a compound initializer".to_string())
+        }
+        (
+            Initializer::Single(Expression {
+                kind: ExpressionKind::Constant(constant),
+                ..
+            }),
+            _,
+        ) => Ok(vec![convert_constant_to_static_init(constant, target_type)?]),
+        (Initializer::Single(expression), Type::Pointer(_)) => {
+            let mut expression = expression.clone();
+            typecheck_and_convert(&mut expression, symbol_table)?;
+            convert_by_assignment(&mut expression, target_type)?;
+            match get_static_address(&expression, symbol_table) {
+                Some((name, offset)) => Ok(vec![StaticInit::PointerInit(name, offset)]),
+                None => Err("Non-constant static initializer".to_string()),
+            }
+        }
+        // Typechecking a copy gives sizeof its operand type and adds the implicit conversions
+        (Initializer::Single(expression), _) if target_type.is_arithmetic() => {
+            let mut expression = expression.clone();
+            typecheck_and_convert(&mut expression, symbol_table)?;
+            let constant = evaluate_constant(&expression, symbol_table)
+                .map_err(|error| format!("Non-constant static initializer: {}", error))?;
+            Ok(vec![convert_constant_to_static_init(&constant, target_type)?])
+        }
+        (Initializer::Single(_), _) => Err("Non-constant static initializer".to_string()),
+    }
+}
+
+// The static object or function a type checked initializer points into and the offset in bytes,
+// arrays and functions have already decayed to their address
+fn get_static_address(
+    expression: &Expression,
+    symbol_table: &SymbolTable,
+) -> Option<(String, i64)> {
+    match &expression.kind {
+        ExpressionKind::AddressOf(inner) => get_static_object(inner, symbol_table),
+        ExpressionKind::Cast(Type::Pointer(_), inner) if is_pointer(inner.get_type()) => {
+            get_static_address(inner, symbol_table)
+        }
+        ExpressionKind::Binary(BinaryOperator::Add, pointer, index)
+        | ExpressionKind::Binary(BinaryOperator::Add, index, pointer)
+            if is_pointer(pointer.get_type()) =>
+        {
+            get_element_address(pointer, index, 1, symbol_table)
+        }
+        ExpressionKind::Binary(BinaryOperator::Subtract, pointer, index)
+            if is_pointer(pointer.get_type()) && index.get_type().is_integer() =>
+        {
+            get_element_address(pointer, index, -1, symbol_table)
+        }
+        _ => None,
+    }
+}
+
+// The static object an lvalue designates part of and the offset of that part
+fn get_static_object(expression: &Expression, symbol_table: &SymbolTable) -> Option<(String, i64)> {
+    match &expression.kind {
+        ExpressionKind::Var(name) => match symbol_table.get(name)?.identifier_attrs {
+            IdentifierAttr::FunAttr(..)
+            | IdentifierAttr::StaticAttr(..)
+            | IdentifierAttr::ConstantAttr(_) => Some((name.clone(), 0)),
+            _ => None,
+        },
+        ExpressionKind::Dereference(pointer) => get_static_address(pointer, symbol_table),
+        ExpressionKind::Subscript(pointer, index) | ExpressionKind::Subscript(index, pointer)
+            if is_pointer(pointer.get_type()) =>
+        {
+            get_element_address(pointer, index, 1, symbol_table)
+        }
+        ExpressionKind::Dot(structure, member) => {
+            let (name, offset) = get_static_object(structure, symbol_table)?;
+            Some((name, offset + get_member_offset(structure.get_type(), member, symbol_table)))
+        }
+        ExpressionKind::Arrow(pointer, member) => {
+            let (name, offset) = get_static_address(pointer, symbol_table)?;
+            match pointer.get_type() {
+                Type::Pointer(referenced) => {
+                    Some((name, offset + get_member_offset(referenced, member, symbol_table)))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Moves a static address by a constant number of elements
+fn get_element_address(
+    pointer: &Expression,
+    index: &Expression,
+    direction: i64,
+    symbol_table: &SymbolTable,
+) -> Option<(String, i64)> {
+    let (name, offset) = get_static_address(pointer, symbol_table)?;
+    let index = evaluate_integer_constant(index, symbol_table).ok()?;
+    match pointer.get_type() {
+        Type::Pointer(referenced) => {
+            Some((name, offset + direction * index * referenced.size(symbol_table)))
+        }
+        _ => None,
+    }
+}
+
+fn get_member_offset(structure_type: &Type, member: &str, symbol_table: &SymbolTable) -> i64 {
+    match structure_type.unqualified() {
+        Type::Structure(tag) => get_struct_entry(tag, symbol_table)
+            .members
+            .iter()
+            .find(|entry| entry.name == member)
+            .expect("Member access was type checked")
+            .offset,
+        _ => panic!("Member access was type checked"),
+    }
+}
+
+fn typecheck_initializer(
+    initializer: &mut Initializer,
+    target_type: &Type,
+    symbol_table: &mut SymbolTable,
+) -> Result<(), String> {
+    let target_type = target_type.unqualified();
+    match (initializer, target_type) {
+        (
+            Initializer::Single(
+                expression @ Expression {
+                    kind: ExpressionKind::String(_),
+                    ..
+                },
+            ),
+            Type::Array(element_type, size),
+        ) if element_type.is_character() => {
+            if let ExpressionKind::String(bytes) = &expression.kind {
+                if bytes.len() as i64 > *size {
+                    return Err("String literal is too long for its array".to_string());
+                }
+            }
+            expression.exp_type = Some(target_type.clone());
+            Ok(())
+        }
+        (Initializer::Compound(initializers, init_type), Type::Array(element_type, size)) => {
+            if initializers.len() as i64 > *size {
+                return Err("Too many elements in array initializer".to_string());
+            }
+            for initializer in initializers.iter_mut() {
+                typecheck_initializer(initializer, element_type, symbol_table)?;
+            }
+            while (initializers.len() as i64) < *size {
+                initializers.push(make_zero_initializer(element_type, symbol_table));
+            }
+            *init_type = Some(target_type.clone());
+            Ok(())
+        }
+        (Initializer::Compound(initializers, init_type), Type::Structure(tag)) => {
+            let member_types: Vec<Type> = get_struct_entry(tag, symbol_table)
+                .initialized_members()
+                .iter()
+                .map(|member| member.member_type.clone())
+                .collect();
+            if initializers.len() > member_types.len() {
+                return Err("Too many elements in structure initializer".to_string());
+            }
+            for (initializer, member_type) in initializers.iter_mut().zip(&member_types) {
+                typecheck_initializer(initializer, member_type, symbol_table)?;
+            }
+            for member_type in &member_types[initializers.len()..] {
+                initializers.push(make_zero_initializer(member_type, symbol_table));
+            }
+            *init_type = Some(target_type.clone());
+            Ok(())
+        }
+        (Initializer::Compound(_, _), _) => {
+            Err("Compound initializer for a scalar object".to_string())
+        }
+        (Initializer::Single(_), Type::Array(_, _)) => {
+            Err("Arrays need a compound initializer".to_string())
+        }
+        (Initializer::Single(expression), _) => {
+            typecheck_and_convert(expression, symbol_table)?;
+            convert_by_assignment(expression, target_type)
+        }
+    }
+}
+
+fn make_zero_initializer(target_type: &Type, symbol_table: &SymbolTable) -> Initializer {
+    let target_type = target_type.unqualified();
+    match target_type {
+        Type::Array(element_type, size) => Initializer::Compound(
+            (0..*size)
+                .map(|_| make_zero_initializer(element_type, symbol_table))
+                .collect(),
+            Some(target_type.clone()),
+        ),
+        Type::Structure(tag) => Initializer::Compound(
+            get_struct_entry(tag, symbol_table)
+                .initialized_members()
+                .iter()
+                .map(|member| make_zero_initializer(&member.member_type, symbol_table))
+                .collect(),
+            Some(target_type.clone()),
+        ),
+        _ => {
+            let constant = match target_type {
+                Type::Char | Type::SChar => Const::Char(0),
+                Type::UChar => Const::UChar(0),
+                Type::Int => Const::Int(0),
+                Type::Long => Const::Long(0),
+                Type::UInt => Const::UInt(0),
+                Type::Double => Const::Double(0.0),
+                _ => Const::ULong(0),
+            };
+            let mut expression = Expression::new(ExpressionKind::Constant(constant));
+            expression.exp_type = Some(match target_type {
+                Type::Pointer(_) => Type::ULong,
+                _ => target_type.clone(),
+            });
+            Initializer::Single(expression)
+        }
+    }
+}
+
 fn typecheck_local_var_declaration(
-    var_declaration: &VariableDeclaration,
+    var_declaration: &mut VariableDeclaration,
     symbol_table: &mut SymbolTable,
 ) -> Result<(), String> {
+    complete_array_type(var_declaration);
+    if var_declaration.storage_class != Some(StorageClass::Extern)
+        && !is_complete(&var_declaration.var_type, symbol_table)
+    {
+        return Err(format!(
+            "Variable has an incomplete type: {}",
+            var_declaration.name
+        ));
+    }
     if var_declaration.storage_class == Some(StorageClass::Extern) {
-        if var_declaration.init != None {
+        if var_declaration.init.is_some() {
             return Err("Initializer on local extern variable declaration".to_string());
         }
         if symbol_table.contains_key(&var_declaration.name) {
             let old_decl = symbol_table.get(&var_declaration.name).unwrap();
-            if old_decl.sym_type != Type::Int {
+            if let Type::Function(..) = old_decl.sym_type {
                 return Err("Function redeclared as variable".to_string());
             }
+            if get_composite_type(&old_decl.sym_type, &var_declaration.var_type).is_none() {
+                return Err(format!(
+                    "Conflicting variable types: {}",
+                    var_declaration.name
+                ));
+            }
             Ok(())
         } else {
             symbol_table.insert(
                 var_declaration.name.clone(),
                 SymbolEntry {
-                    sym_type: Type::Int,
+                    sym_type: var_declaration.var_type.clone(),
                     defined: true,
                     identifier_attrs: IdentifierAttr::StaticAttr(InitialValue::NoInitializer, true),
                 },
@@ -342,17 +1638,20 @@ fn typecheck_local_var_declaration(
             Ok(())
         }
     } else if var_declaration.storage_class == Some(StorageClass::Static) {
-        let initial_value = if let Some(Expression::Constant(val)) = var_declaration.init {
-            InitialValue::Initial(val)
-        } else if var_declaration.init == None {
-            InitialValue::Initial(0)
-        } else {
-            return Err("Non-constant initializer on local static variable".to_string());
+        let initial_value = match &var_declaration.init {
+            Some(initializer) => InitialValue::Initial(convert_initializer_to_static_init(
+                initializer,
+                &var_declaration.var_type,
+                symbol_table,
+            )?),
+            None => InitialValue::Initial(vec![StaticInit::ZeroInit(
+                var_declaration.var_type.size(symbol_table),
+            )]),
         };
         symbol_table.insert(
             var_declaration.name.clone(),
             SymbolEntry {
-                sym_type: Type::Int,
+                sym_type: var_declaration.var_type.clone(),
                 defined: true,
                 identifier_attrs: IdentifierAttr::StaticAttr(initial_value, false),
             },
@@ -362,14 +1661,32 @@ fn typecheck_local_var_declaration(
         symbol_table.insert(
             var_declaration.name.clone(),
             SymbolEntry {
-                sym_type: Type::Int,
+                sym_type: var_declaration.var_type.clone(),
                 defined: true,
                 identifier_attrs: IdentifierAttr::LocalAttr,
             },
         );
-        if let Some(init) = &var_declaration.init {
-            typecheck_expression(init, symbol_table)?
+        if let Some(init) = &mut var_declaration.init {
+            typecheck_initializer(init, &var_declaration.var_type, symbol_table)?;
         }
         Ok(())
     }
 }
+
+static mut STRING_COUNTER: i64 = -1;
+
+fn make_string_constant(bytes: Vec<u8>, symbol_table: &mut SymbolTable) -> String {
+    let name = format!(".Lstring.{}", unsafe {
+        STRING_COUNTER += 1;
+        STRING_COUNTER
+    });
+    symbol_table.insert(
+        name.clone(),
+        SymbolEntry {
+            sym_type: Type::Array(Box::new(Type::Char), bytes.len() as i64 + 1),
+            defined: true,
+            identifier_attrs: IdentifierAttr::ConstantAttr(StaticInit::StringInit(bytes, true)),
+        },
+    );
+    name
+}
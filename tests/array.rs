@@ -0,0 +1,67 @@
+mod common;
+
+use common::run_program;
+
+// The size can be left out when the initializer, another declaration or the parameter adjustment
+// makes up for it
+#[test]
+fn leaves_out_sizes() {
+    let (code, stdout) = run_program(
+        "unsized_arrays",
+        "int printf(const char *, ...);
+extern int shared[];
+static const int table[] = {10, 20, 30, 40,};
+int matrix[][2] = {{1, 2}, {3, 4}, {5, 6}};
+int sum(int a[], int count) {
+    int total = 0;
+    for (int i = 0; i < count; i++)
+        total += a[i];
+    return total;
+}
+int second(int (*rows)[2]) { return rows[1][1]; }
+int main(void) {
+    int a[] = {1, 2, 3};
+    extern int shared[];
+    printf(\"%d %d %d\\n\", (int)sizeof a, (int)sizeof table, (int)sizeof matrix);
+    return sum(a, 3) + sum((int *)table, 4) + second(matrix) + sum(shared, 2);
+}
+int shared[2] = {5, 6};
+",
+    );
+    assert_eq!(stdout, "12 16 24\n");
+    assert_eq!(code, Some(121));
+}
+
+// Subscripts and pointer arithmetic work on decayed arrays, missing elements are zero
+#[test]
+fn subscripts_arrays() {
+    let (code, _) = run_program(
+        "subscripts",
+        "long matrix_sum(long (*rows)[3], int count) {
+    long total = 0;
+    for (int i = 0; i < count; i = i + 1)
+        for (int j = 0; j < 3; j = j + 1)
+            total += rows[i][j] * (j + 1);
+    return total;
+}
+int main(void) {
+    int numbers[6] = {1, 2, 3};
+    long matrix[2][3] = {{1, 2, 3}, {4, 5}};
+    int *end = numbers + 6;
+    int *middle = &numbers[2];
+    double halves[3] = {0.5, 1.5};
+    for (int *p = numbers; p < end; p = p + 1)
+        *p *= 2;
+    middle[1] = 10;
+    if (numbers[0] != 2 || 2[numbers] != 6 || *(middle - 1) != 4 || end - numbers != 6)
+        return 1;
+    if (matrix_sum(matrix, 2) != 28 || matrix[1][2] != 0 || *matrix[1] != 4)
+        return 2;
+    if (halves[1] != 1.5 || halves[2] != 0.0 || &matrix[1][0] - &matrix[0][0] != 3)
+        return 3;
+    return numbers[5] + middle[1] + (&numbers[5] - middle);
+}
+",
+    );
+    assert_eq!(code, Some(13));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::run_program;
+
+// A character array without a size holds the literal and its null byte
+#[test]
+fn sizes_arrays_from_string_literals() {
+    let (code, stdout) = run_program(
+        "string_sizes",
+        "int printf(const char *, ...);
+char greeting[] = \"hello\";
+static const char empty[] = \"\";
+int main(void) {
+    char s[] = \"hi\";
+    const char msg[] = \"x\";
+    unsigned char bytes[] = \"\\x01\\x02\";
+    printf(\"%s %s %s\\n\", greeting, s, msg);
+    printf(\"%d %d %d %d %d\\n\", (int)sizeof greeting, (int)sizeof s, (int)sizeof msg,
+           (int)sizeof empty, (int)sizeof bytes);
+    return bytes[1] + s[2] + empty[0];
+}
+",
+    );
+    assert_eq!(stdout, "hello hi x\n6 3 2 1 3\n");
+    assert_eq!(code, Some(2));
+}
+
+// Characters are promoted to int, string literals are null terminated arrays
+#[test]
+fn handles_characters() {
+    let (code, stdout) = run_program(
+        "characters",
+        "int puts(char *s);
+unsigned long strlen(char *s);
+char upper(char c) { return c >= 'a' && c <= 'z' ? c - 'a' + 'A' : c; }
+int main(void) {
+    char word[8] = \"hello\";
+    signed char negative = -128;
+    unsigned char wrapped = 300;
+    char c = 'z' + 1;
+    char *literal = \"tab\\there \\\"quoted\\\" back\\\\slash\";
+    for (int i = 0; word[i]; i = i + 1)
+        word[i] = upper(word[i]);
+    puts(word);
+    puts(literal);
+    if (negative - 1 != -129 || wrapped != 44 || c != 123 || \"abc\"[1] != 'b' || '\\n' != 10)
+        return 1;
+    if (strlen(\"concatenated\" \" literals\") != 21 || word[6] != 0 || literal[3] != 9)
+        return 2;
+    return '\\0' + '\\a' + '\\x41' - '\\101';
+}
+",
+    );
+    assert_eq!(stdout, "HELLO\ntab\there \"quoted\" back\\slash\n");
+    assert_eq!(code, Some(7));
+}
+
+// Plain chars are signed, so constants above 127 are negative like the chars they compare to
+#[test]
+fn sign_extends_character_constants() {
+    let (code, _) = run_program(
+        "character_constants",
+        "int main(void) {
+    char c = '\\xff';
+    int result = 0;
+    if ('\\xff' == -1)
+        result = result + 1;
+    if (c == '\\xff' && '\\200' == -128 && '\\x7f' == 127)
+        result = result + 2;
+    return result;
+}
+",
+    );
+    assert_eq!(code, Some(3));
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::run_program;
+
+// The comma operator evaluates left to right and a declaration can declare several names
+#[test]
+fn evaluates_in_sequence() {
+    let (code, stdout) = run_program(
+        "comma",
+        "int printf(const char *format, ...);
+int next(int *calls), twice(int value);
+int next(int *calls) { return ++*calls; }
+int twice(int value) { return value * 2; }
+int main(void) {
+    int calls = 0;
+    int a = 1, *pa = &a, array[2] = {3, 4};
+    int i, j, total = 0;
+    long l = 5, *pl = &l;
+    for (i = 0, j = 10; i < j; i++, j--)
+        total += i * j;
+    int last = (next(&calls), next(&calls), next(&calls));
+    int x = 1, y = x + 1, z = y * 3;
+    printf(\"%d %d %d %d %d %ld\\n\", total, last, calls, z, *pa + array[1], *pl + twice(1));
+    return (x = 7, x + y);
+}
+",
+    );
+    assert_eq!(stdout, "70 3 3 6 5 7\n");
+    assert_eq!(code, Some(9));
+}
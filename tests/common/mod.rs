@@ -0,0 +1,47 @@
+// Every test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+pub fn make_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("compiler_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn write_source(dir: &Path, name: &str, source: &str) -> String {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, source).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+// The executable is written to dir/program
+pub fn compile(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg(dir.join("program"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Compiles a program from a single file, runs it and returns its exit code and what it printed
+pub fn run_program(name: &str, source: &str) -> (Option<i32>, String) {
+    let dir = make_dir(name);
+    let source_file = write_source(&dir, "main.c", source);
+    let output = compile(&dir, &[&source_file]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = Command::new(dir.join("program")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
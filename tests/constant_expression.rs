@@ -0,0 +1,102 @@
+mod common;
+
+use common::run_program;
+
+// Array sizes are evaluated after enumerators are resolved, sizeof a scalar is always known
+#[test]
+fn evaluates_array_sizes() {
+    let (code, stdout) = run_program(
+        "array_sizes",
+        "int printf(const char *, ...);
+enum { N = 4, C = sizeof(int), L = sizeof(long[3]) };
+int a[N];
+int b[sizeof(int)];
+long c[N * 2 + C];
+struct s {
+    int x[N];
+    char name[sizeof(double)];
+};
+int main(void) {
+    enum { M = 3 };
+    int local[M][N];
+    int (*p)[N] = local;
+    double d[L / 8];
+    printf(\"%d %d %d %d %d\\n\", (int)sizeof a, (int)sizeof b, (int)sizeof c, C, L);
+    printf(\"%d %d %d\\n\", (int)sizeof local, (int)sizeof(struct s), (int)sizeof d);
+    return sizeof(p[1]) + sizeof(int[M]);
+}
+",
+    );
+    assert_eq!(stdout, "16 16 96 4 24\n48 24 24\n");
+    assert_eq!(code, Some(28));
+}
+
+// Case labels, enumerators and array sizes are folded with the conversions of the running program
+#[test]
+fn folds_constant_expressions() {
+    let (code, stdout) = run_program(
+        "folded_constants",
+        "int printf(const char *format, ...);
+enum { KB = 1 << 10, MASK = ~0u >> 28, HALF = (int)(1 / 2.0 + 1.5), NEGATIVE = -(long)KB };
+int name(int value) {
+    switch (value) {
+    case KB:
+        return 1;
+    case 'a' + 1:
+        return 2;
+    case (int)(8L * 2):
+        return 3;
+    case -1 % 3 ? 100 : 200:
+        return 4;
+    case (5 > 3) + (2 && 0) - 2:
+        return 5;
+    }
+    return 0;
+}
+int main(void) {
+    long local[(1 << 2) + 1];
+    printf(\"%d %u %d %d %d\\n\", KB, MASK, HALF, NEGATIVE, (int)sizeof local);
+    printf(\"%d %d %d %d %d\\n\", name(1024), name('b'), name(16), name(100), name(-1));
+    return 0;
+}
+",
+    );
+    assert_eq!(stdout, "1024 15 2 -1024 40\n1 2 3 4 5\n");
+    assert_eq!(code, Some(0));
+}
+
+// The size of a structure is known as soon as its declaration is complete
+#[test]
+fn evaluates_structure_sizes() {
+    let (code, stdout) = run_program(
+        "structure_sizes",
+        "int printf(const char *, ...);
+struct s {
+    int a;
+    long b;
+};
+enum { WORDS = sizeof(struct s) / 4 };
+struct s copies[sizeof(struct s) / 8];
+int classify(int n) {
+    switch (n) {
+    case sizeof(struct s) / 4:
+        return 1;
+    case sizeof(struct s[2]):
+        return 2;
+    default:
+        return 0;
+    }
+}
+int main(void) {
+    struct t {
+        char c[3];
+    };
+    char buffer[sizeof(struct t) + WORDS];
+    printf(\"%d %d %d\\n\", WORDS, (int)sizeof copies, (int)sizeof buffer);
+    return classify(4) + classify(32) * 10;
+}
+",
+    );
+    assert_eq!(stdout, "4 32 7\n");
+    assert_eq!(code, Some(21));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::run_program;
+
+// Comparisons with NaN are unordered, only != holds and NaN isn't zero
+#[test]
+fn compares_nan() {
+    let (code, stdout) = run_program(
+        "nan",
+        "int printf(const char *fmt, ...);
+int main(void) {
+    double zero = 0.0;
+    double n = 0.0 / zero;
+    int taken = 0;
+    printf(\"%d %d %d %d %d %d %d %d\\n\", n == n, n != n, n < 1, n <= 1, n > 1, n >= 1, !n,
+           n ? 1 : 0);
+    printf(\"%d %d %d %d\\n\", 1 < n, 1 <= n, 1 > n, 1 >= n);
+    printf(\"%d %d %d %d\\n\", n && 1, n || 0, zero && 1, zero || 0);
+    if (n)
+        taken += 1;
+    while (n) {
+        taken += 2;
+        break;
+    }
+    if (!n)
+        taken += 4;
+    return taken;
+}
+",
+    );
+    assert_eq!(code, Some(3));
+    assert_eq!(stdout, "0 1 0 0 0 0 0 1\n0 0 0 0\n1 1 0 0\n");
+}
+
+// Doubles are passed in SSE registers and on the stack, conversions to integers truncate
+#[test]
+fn converts_doubles() {
+    let (code, _) = run_program(
+        "double_conversions",
+        "double sqrt(double x);
+double average(int a, long b, unsigned int c, unsigned long d, double e, double f, double g,
+               double h, double i, double j, double k) {
+    return (a + b + c + d + e + f + g + h + i + j + k) / 11;
+}
+int main(void) {
+    double third = 1.0 / 3;
+    double big = 18446744073709551615UL;
+    unsigned long back = 9223372036854775808.0;
+    unsigned int small = 4294967295.0;
+    long truncated = -2.9;
+    int halved = -7.5 / 2;
+    double negative_zero = -0.0;
+    if (third * 3 != 1.0 || big != 18446744073709551616.0 || back != 9223372036854775808UL)
+        return 1;
+    if (small != 4294967295U || truncated != -2 || halved != -3)
+        return 2;
+    if (average(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11) != 6.0 || sqrt(2.0) * sqrt(2.0) < 1.99999)
+        return 3;
+    if (1.5e300 * 1e8 != 1.5e308 || 1 / negative_zero > -1e308 || negative_zero != 0)
+        return 4;
+    return 0.1 + 0.2 != 0.3 ? 7 : 0;
+}
+",
+    );
+    assert_eq!(code, Some(7));
+}
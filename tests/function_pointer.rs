@@ -0,0 +1,53 @@
+mod common;
+
+use common::run_program;
+
+// libc calls back through the pointer, its prototype leaves the parameters unnamed
+#[test]
+fn passes_comparison_to_qsort() {
+    let (code, stdout) = run_program(
+        "qsort",
+        "void qsort(void *, unsigned long, unsigned long, int (*)(const void *, const void *));
+int printf(const char *, ...);
+int compare(const void *a, const void *b) {
+    return *(const int *)a - *(const int *)b;
+}
+int main(void) {
+    int numbers[5] = {5, 3, 4, 1, 2};
+    qsort(numbers, 5, sizeof(int), compare);
+    for (int i = 0; i < 5; i++)
+        printf(\"%d \", numbers[i]);
+    return 0;
+}
+",
+    );
+    assert_eq!(stdout, "1 2 3 4 5 ");
+    assert_eq!(code, Some(0));
+}
+
+// Functions are called through pointers held in variables, parameters, arrays and structures
+#[test]
+fn calls_through_pointers() {
+    let (code, _) = run_program(
+        "indirect_calls",
+        "int add(int, int);
+int subtract(int a, int b) { return a - b; }
+int add(int a, int b) { return a + b; }
+int apply(int (*operation)(int, int), int a, int b) { return operation(a, b); }
+int (*pick(int which))(int, int) { return which ? add : &subtract; }
+struct calculator {
+    int (*operation)(int a, int b);
+};
+int main(void) {
+    int (*operations[2])(int, int) = {add, subtract};
+    struct calculator calculator = {subtract};
+    int (*operation)(int, int) = pick(1);
+    if (operation != add || pick(0) != subtract)
+        return 1;
+    return apply(add, 30, 2) + operations[1](20, 10) + (*calculator.operation)(3, 1)
+        + (*pick(0))(0, 0);
+}
+",
+    );
+    assert_eq!(code, Some(44));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::run_program;
+
+// Jumps go forward, backward and out of nested loops to labels anywhere in the function
+#[test]
+fn jumps_to_labels() {
+    let (code, _) = run_program(
+        "goto",
+        "int find(int (*grid)[3], int target) {
+    for (int i = 0; i < 3; i = i + 1)
+        for (int j = 0; j < 3; j = j + 1)
+            if (grid[i][j] == target)
+                goto found;
+    return -1;
+found:
+    return target;
+}
+int main(void) {
+    int grid[3][3] = {{1, 2, 3}, {4, 5, 6}, {7, 8, 9}};
+    int count = 0;
+again:
+    count = count + 1;
+    if (count < 5)
+        goto again;
+    goto skip;
+    count = 100;
+skip:
+    if (count != 5 || find(grid, 8) != 8 || find(grid, 10) != -1)
+        return 1;
+    {
+    inner:
+        count -= 1;
+        if (count > 2)
+            goto inner;
+    }
+end:
+    return count;
+}
+",
+    );
+    assert_eq!(code, Some(2));
+}
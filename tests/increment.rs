@@ -0,0 +1,38 @@
+mod common;
+
+use common::run_program;
+
+// Prefix operators give the new value and postfix ones the old, pointers move by whole elements
+#[test]
+fn increments_lvalues() {
+    let (code, _) = run_program(
+        "increments",
+        "int main(void) {
+    int i = 5;
+    long l = -1;
+    unsigned char c = 255;
+    double d = 0.5;
+    int array[3] = {10, 20, 30};
+    int *p = array;
+    char *s = \"abc\";
+    int a = i++;
+    int b = ++i;
+    int old = i--;
+    c++;
+    d++;
+    if (a != 5 || b != 7 || old != 7 || i != 6 || ++l != 0 || c != 0 || d != 1.5)
+        return 1;
+    int first = *p++;
+    int third = *++p;
+    int decremented = (*p)--;
+    if (first != 10 || third != 30 || decremented != 30 || *++s != 'b')
+        return 2;
+    int second = *--p;
+    if (array[2] != 29 || second != 20 || p - array != 1 || --d != 0.5 || d-- != 0.5)
+        return 3;
+    return i;
+}
+",
+    );
+    assert_eq!(code, Some(6));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{compile, make_dir, run_program, write_source};
+use std::fs;
+
+// Values past the range of int keep their upper bits through arithmetic, calls and conversions
+#[test]
+fn computes_with_longs() {
+    let (code, _) = run_program(
+        "long_arithmetic",
+        "long add(long a, long b) { return a + b; }
+int truncate(long value) { return value; }
+int main(void) {
+    long big = 4294967296;
+    long product = 3000000L * 3000000L;
+    int i = -5;
+    long widened = i;
+    long mixed = 2147483647 + 1L;
+    if (product != 9000000000000 || add(big, big) != 8589934592L)
+        return 1;
+    if (widened != -5 || mixed != 2147483648)
+        return 2;
+    if (truncate(big + 7) != 7 || truncate(-1L) != -1 || big >> 16 << 1 != 131072)
+        return 3;
+    return product / 1000000000000L == 9 && big % 10 == 6 && (widened & 255) == 251 ? 42 : 4;
+}
+",
+    );
+    assert_eq!(code, Some(42));
+}
+
+// A literal that doesn't fit in 64 bits is reported instead of wrapping around
+#[test]
+fn rejects_too_large_constants() {
+    let dir = make_dir("too_large_constant");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "int main(void) {
+    return 18446744073709551616 > 0;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Integer constant is too large: 18446744073709551616\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{compile, make_dir, write_source};
+use std::fs;
+use std::process::Command;
+
+// Each file has its own static helper and counter, only the non-static names are shared
+#[test]
+fn static_functions_dont_clash_across_files() {
+    let dir = make_dir("multi_file");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "static int counter = 1;
+static int helper(void) {
+    return 40 + counter;
+}
+int other(void);
+int shared = 100;
+int main(void) {
+    return helper() + other() - shared;
+}
+",
+    );
+    let other_file = write_source(
+        &dir,
+        "other.c",
+        "static int counter = 2;
+static int helper(void) {
+    return counter * 50;
+}
+extern int shared;
+int other(void) {
+    return helper() + shared / 100 - 1;
+}
+",
+    );
+    let output = compile(&dir, &[&main_file, &other_file]);
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(41));
+
+    let assembly = fs::read_to_string(dir.join("other.s")).unwrap();
+    assert!(assembly.contains(".globl other"));
+    assert!(!assembly.contains(".globl helper"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
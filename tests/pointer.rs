@@ -0,0 +1,38 @@
+mod common;
+
+use common::run_program;
+
+// Objects are read and written through pointers, pointers to pointers and returned pointers
+#[test]
+fn dereferences_pointers() {
+    let (code, _) = run_program(
+        "pointers",
+        "int swap(int *a, int *b) {
+    int temp = *a;
+    *a = *b;
+    *b = temp;
+    return 0;
+}
+int *larger(int *a, int *b) { return *a > *b ? a : b; }
+int main(void) {
+    int x = 3;
+    int y = 4;
+    int *p = &x;
+    int **pp = &p;
+    double d = 1.5;
+    double *pd = &d;
+    swap(&x, &y);
+    **pp += 10;
+    *pd *= 2;
+    *larger(&x, &y) = 0;
+    int *null = 0;
+    if (x != 0 || y != 3 || d != 3.0)
+        return 1;
+    if (p != &x || *pp != &x || null != 0)
+        return 2;
+    return *p + (p != 0) + !null;
+}
+",
+    );
+    assert_eq!(code, Some(2));
+}
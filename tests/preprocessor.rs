@@ -0,0 +1,283 @@
+mod common;
+
+use common::{compile, make_dir, write_source};
+use std::fs;
+use std::process::Command;
+
+// Headers included twice are skipped through their guard or #pragma once
+#[test]
+fn includes_headers_once() {
+    let dir = make_dir("include");
+    write_source(
+        &dir,
+        "twice.h",
+        "// guarded
+#ifndef TWICE_H
+#define TWICE_H
+int twice(int x);
+#endif
+",
+    );
+    write_source(
+        &dir,
+        "include/add.h",
+        "#pragma once
+/* a comment
+   over two lines */ int add(int a, \\
+                             int b);
+",
+    );
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#include \"twice.h\"
+#include \"twice.h\"
+#include <add.h>
+#include \"include/add.h\"
+int twice(int x) { return x * 2; }
+int add(int a, int b) { return a + b; }
+int main(void) { return add(twice(20), 2); }
+",
+    );
+    let include_dir = dir.join("include");
+
+    let output = compile(&dir, &[&main_file, "-I", include_dir.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A header that starts like a guard but has no #define on its second line is included every time
+#[test]
+fn includes_headers_without_guard() {
+    let dir = make_dir("no_guard");
+    write_source(
+        &dir,
+        "g.h",
+        "#ifndef G_H
+int
+helper(void);
+#endif
+",
+    );
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#include \"g.h\"
+#include \"g.h\"
+int helper(void) { return 42; }
+int main(void) { return helper(); }
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// stddef.h and stdarg.h come with the compiler
+#[test]
+fn includes_compiler_headers() {
+    let dir = make_dir("compiler_headers");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#include <stddef.h>
+#include <stdarg.h>
+struct point {
+    int x;
+    long y;
+};
+int sum(int count, ...) {
+    va_list args;
+    va_start(args, count);
+    int total = 0;
+    for (int i = 0; i < count; i++)
+        total += va_arg(args, int);
+    va_end(args);
+    return total;
+}
+int main(void) {
+    size_t size = sizeof(struct point);
+    ptrdiff_t difference = &size - &size;
+    int *nothing = NULL;
+    if (size != 16 || difference != 0 || nothing != 0)
+        return 1;
+    return offsetof(struct point, y) + sum(3, 10, 20, 4);
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Macros from the source and the command line, with # and ## and variadic arguments
+#[test]
+fn expands_macros() {
+    let dir = make_dir("macros");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#define SQUARE(x) ((x) * (x))
+#define STR(x) #x
+#define CAT(a, b) a##b
+#define FIRST(first, ...) first
+#define SUM(...) sum(__VA_ARGS__)
+int strcmp(const char *a, const char *b);
+int sum(int a, int b) { return a + b; }
+int SELF = 1;
+#define SELF (SELF + 1)
+int main(void) {
+    int value12 = 5;
+    if (strcmp(STR(a \"b\"), \"a \\\"b\\\"\") != 0) return 1;
+    if (__LINE__ != 13 || !__STDC__) return 2;
+    if (SELF != 2 || REMOVED) return 3;
+    return SQUARE(OFFSET + 1) + CAT(value, 12) + FIRST(1, 2, 3) + SUM(1, 2);
+}
+",
+    );
+
+    let output = compile(
+        &dir,
+        &[
+            &main_file,
+            "-DOFFSET=2",
+            "-D",
+            "REMOVED",
+            "-UREMOVED",
+            "-DREMOVED=0",
+        ],
+    );
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(18));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Only the groups whose conditions hold are compiled
+#[test]
+fn includes_conditional_groups() {
+    let dir = make_dir("conditionals");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#define VERSION 3
+#if VERSION > 2 && defined(FEATURE)
+int value = 1;
+#elif VERSION == 3 && !defined FEATURE
+int value = 2;
+#else
+#error unreachable
+#endif
+#ifdef __x86_64__
+# if 0x10 == 16 && -1 > 0u && (0 && 1 / 0) == 0
+int other = 40;
+# endif
+#endif
+#ifndef VERSION
+don't compile this
+#endif
+int main(void) {
+    return value + other;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(output.status.success());
+
+    let status = Command::new(dir.join("program")).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_error_directive() {
+    let dir = make_dir("error_directive");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "#warning not ported yet
+#ifndef __linux__
+#error unsupported platform
+#elif !defined(FEATURE)
+#error FEATURE is required
+#endif
+int main(void) {
+    return 0;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = format!(
+        "{0}:1: warning: #warning not ported yet\n{0}:5: #error FEATURE is required\n",
+        main_file
+    );
+    assert_eq!(stderr, expected);
+
+    let output = compile(&dir, &[&main_file, "-DFEATURE"]);
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        format!("{}:1: warning: #warning not ported yet\n", main_file)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// The error points at the directive and at every file on the include stack, one per line
+#[test]
+fn reports_include_stack() {
+    let dir = make_dir("include_stack");
+    write_source(&dir, "outer.h", "int x;\n#include \"missing.h\"\n");
+    let main_file = write_source(&dir, "main.c", "#include \"outer.h\"\nint main(void) {}\n");
+
+    let output = compile(&dir, &[&main_file]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = format!(
+        "{}/outer.h:2: Can't find include file: missing.h\n    included from {}:1\n",
+        dir.display(),
+        main_file
+    );
+    assert_eq!(stderr, expected);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_missing_source_file() {
+    let dir = make_dir("missing_source");
+    let missing = dir.join("missing.c");
+
+    let output = compile(&dir, &[missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with(&format!("Can't read {}: ", missing.display())));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
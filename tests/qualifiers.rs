@@ -0,0 +1,123 @@
+mod common;
+
+use common::{compile, make_dir, run_program, write_source};
+use std::fs;
+
+// Qualified objects are read like any other and pointers can gain qualifiers
+#[test]
+fn reads_qualified_objects() {
+    let (code, stdout) = run_program(
+        "qualified_objects",
+        "int printf(const char *format, ...);
+struct config {
+    const int id;
+    volatile long ticks;
+};
+int sum(const int *values, int count) {
+    int total = 0;
+    for (int i = 0; i < count; i++)
+        total += values[i];
+    return total;
+}
+int main(void) {
+    const int limit = 3;
+    const char *const names[3] = {\"zero\", \"one\", \"two\"};
+    const double scale[2] = {0.5, 2.0};
+    int values[3] = {1, 2, 3};
+    volatile int spin = 0;
+    struct config config = {7, 0};
+    const int *read_only = values;
+    int *const fixed = values;
+    while (spin < limit)
+        spin++;
+    config.ticks += spin;
+    *fixed = 10;
+    printf(\"%s %d %d %f %ld\\n\", names[limit - 1], sum(read_only, 3), config.id, scale[1],
+           config.ticks);
+    return *read_only + spin;
+}
+",
+    );
+    assert_eq!(stdout, "two 15 7 2.000000 3\n");
+    assert_eq!(code, Some(13));
+}
+
+// Const objects can't be modified and conversions can't drop the qualifiers of what they point to
+#[test]
+fn enforces_const() {
+    let dir = make_dir("const_violations");
+    let sources = [
+        (
+            "const int x = 1; int main(void) { x = 2; return 0; }",
+            "Can't assign to a const lvalue",
+        ),
+        (
+            "int main(void) { int a = 1; const int *p = &a; *p = 2; return 0; }",
+            "Can't assign to a const lvalue",
+        ),
+        (
+            "struct s { const int id; }; int main(void) { struct s v = {1}; v.id++; return 0; }",
+            "Can't assign to a const lvalue",
+        ),
+        (
+            "int main(void) { const int a = 1; int *p = &a; return *p; }",
+            "discards qualifiers",
+        ),
+    ];
+    for (source, error) in sources {
+        let main_file = write_source(&dir, "main.c", source);
+        let output = compile(&dir, &[&main_file]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(error));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A volatile object is read even when the value of the expression is discarded
+#[test]
+fn reads_discarded_volatile_objects() {
+    let dir = make_dir("discarded_volatile");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "int main(void) {
+    volatile int x = 3;
+    x;
+    return 0;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(output.status.success());
+
+    let assembly = fs::read_to_string(dir.join("main.s")).unwrap();
+    assert!(assembly.contains("\tmovl -4(%rbp), %r10d\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Qualifiers on a parameter itself don't change the function type
+#[test]
+fn ignores_parameter_qualifiers() {
+    let (code, _) = run_program(
+        "parameter_qualifiers",
+        "int scale(const int value, int factor);
+int scale(int value, volatile const int factor);
+int scale(int value, int factor) {
+    value = value * factor;
+    return value;
+}
+int first(const int *const values);
+int first(const int *values) {
+    return values[0];
+}
+int main(void) {
+    int values[1] = {2};
+    return scale(20, 2) + first(values);
+}
+",
+    );
+    assert_eq!(code, Some(42));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::run_program;
+
+// sizeof doesn't evaluate its operand, casts truncate, extend and reinterpret pointers
+#[test]
+fn measures_and_casts() {
+    let (code, _) = run_program(
+        "sizeof_casts",
+        "struct padded {
+    char c;
+    long l;
+    int i;
+};
+int count(int *calls) { return ++*calls; }
+int main(void) {
+    int calls = 0;
+    long big = 4294967297;
+    double d = -3.75;
+    int array[5];
+    int *p = array;
+    unsigned char *bytes = (unsigned char *)&big;
+    if (sizeof(char) != 1 || sizeof(long) != 8 || sizeof array != 20 || sizeof p != 8)
+        return 1;
+    if (sizeof(struct padded) != 24 || sizeof(int[2][3]) != 24 || sizeof(double *) != 8)
+        return 2;
+    if (sizeof(count(&calls)) != 4 || calls != 0)
+        return 3;
+    if ((int)big != 1 || (int)d != -3 || (unsigned int)-1 != 4294967295 || (char)300 != 44)
+        return 4;
+    if (bytes[4] != 1 || (double)big != 4294967297.0 || (long)(unsigned int)-1 != 4294967295)
+        return 5;
+    if ((int)(long)p != (int)(long)array || (unsigned long)(char *)(long *)p != (unsigned long)p)
+        return 6;
+    return (int)sizeof 1 + (int)sizeof 1.0 + (int)sizeof 'a';
+}
+",
+    );
+    assert_eq!(code, Some(16));
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{compile, make_dir, run_program, write_source};
+use std::fs;
+use std::process::Command;
+
+// Static pointers can point into the middle of an array, a structure or a string literal
+#[test]
+fn initializes_pointers_with_offsets() {
+    let (code, stdout) = run_program(
+        "pointer_offsets",
+        "int printf(const char *, ...);
+int arr[5] = {10, 20, 30, 40, 50};
+struct point {
+    int x;
+    long y;
+};
+struct point points[3] = {{1, 2}, {3, 4}, {5, 6}};
+int *q = &arr[2];
+int *r = arr + 1;
+int *s = 3 + arr;
+int *t = &arr[4] - 2;
+long *v = &points[1].y;
+long *w = &(points + 2)->y;
+char *x = \"hello\" + 1;
+char *y = (char *)&arr[1];
+int main(void) {
+    static int *inner = &2[arr];
+    printf(\"%d %d %d %d %d\\n\", *q, *r, *s, *t, *inner);
+    printf(\"%ld %ld %s %d\\n\", *v, *w, x, *y);
+    return 0;
+}
+",
+    );
+    assert_eq!(stdout, "30 20 40 30 30\n4 6 ello 20\n");
+    assert_eq!(code, Some(0));
+}
+
+// Zeroed variables go to .bss, initialized ones to .data and const ones to .rodata, static locals
+// keep their value between calls and initializers are folded at compile time
+#[test]
+fn emits_static_variables() {
+    let dir = make_dir("static_variables");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "int printf(const char *format, ...);
+int zeroed[1000];
+int tentative;
+int tentative;
+long initialized = 1L << 40;
+const double pi = 3.25;
+double ratio = 1 / 2.0 + (double)3 / 2;
+unsigned char truncated = 256 + 7;
+static char message[7] = \"static\";
+int counter(void) {
+    static int count;
+    static int step = sizeof(int) / 2;
+    count += step;
+    return count;
+}
+int main(void) {
+    counter();
+    counter();
+    zeroed[999] = counter();
+    tentative++;
+    printf(\"%d %d %ld %f %f %d %s\\n\", zeroed[999], tentative, initialized, pi, ratio, truncated,
+           message);
+    return zeroed[0] + zeroed[500];
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(output.status.success());
+
+    let output = Command::new(dir.join("program")).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "6 1 1099511627776 3.250000 2.000000 7 static\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    let assembly = fs::read_to_string(dir.join("main.s")).unwrap();
+    assert!(assembly.contains("\t.bss\n\t.balign 16\nzeroed:\n\t.zero 4000\n"));
+    assert!(assembly.contains("\t.bss\n\t.balign 4\ntentative:\n\t.zero 4\n"));
+    assert!(assembly.contains("\t.data\n\t.balign 8\ninitialized:\n"));
+    assert!(assembly.contains("\t.section .rodata\n\t.balign 8\npi:\n"));
+    assert!(assembly.contains("truncated:\n\t.byte 7\n"));
+    assert!(!assembly.contains(".globl message"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A static structure can only be initialized from a brace enclosed list
+#[test]
+fn rejects_scalar_structure_initializers() {
+    let dir = make_dir("scalar_structure_initializer");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "struct s {
+    int a;
+    long b;
+};
+static struct s value = 0;
+int main(void) {
+    return value.a;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Structures need a compound initializer")
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use common::{compile, make_dir, run_program, write_source};
+use std::fs;
+use std::process::Command;
+
+// Members are reached through nested structures, pointers and copies passed by value
+#[test]
+fn accesses_members() {
+    let (code, _) = run_program(
+        "members",
+        "struct node {
+    int value;
+    struct node *next;
+};
+struct inner {
+    char tag;
+    double weight;
+};
+struct outer {
+    int id;
+    struct inner inner;
+    long values[2];
+};
+struct outer bump(struct outer o) {
+    o.id = o.id + 1;
+    o.inner.weight *= 2;
+    return o;
+}
+int main(void) {
+    struct node nodes[3];
+    struct node *list = 0;
+    for (int i = 0; i < 3; i = i + 1) {
+        struct node *node = &nodes[i];
+        node->value = (i + 1) * 10;
+        node->next = list;
+        list = node;
+    }
+    int total = 0;
+    for (struct node *n = list; n; n = n->next)
+        total += n->value;
+    struct outer first = {1, {'a', 1.5}, {7, 8}};
+    struct outer copy = bump(first);
+    struct outer *pointer = &copy;
+    if (total != 60 || pointer->id != 2 || copy.inner.tag != 'a' || first.id != 1)
+        return 1;
+    if ((&copy)->inner.weight != 3.0 || first.inner.weight != 1.5 || first.values[1] != 8)
+        return 2;
+    return list->next->value;
+}
+",
+    );
+    assert_eq!(code, Some(20));
+}
+
+// Structures are classified like the System V ABI does, so they can be passed to and returned from
+// code gcc compiled in both directions
+#[test]
+fn passes_structures_like_gcc() {
+    let dir = make_dir("structure_abi");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "struct pair { double x; double y; };
+struct mixed { int i; double d; };
+struct seven { char c[7]; };
+struct twelve { int a; int b; int c; };
+struct big { long a; long b; long c; };
+struct pair make_pair(double x);
+struct mixed make_mixed(int i);
+struct seven make_seven(void);
+struct big add_big(long a, struct big b);
+long sum_all(long a, long b, long c, long d, long e, struct twelve t, struct seven s,
+             struct mixed m, struct pair p);
+int call_back(void);
+struct twelve our_twelve(int a, struct seven s) {
+    struct twelve t = {a, s.c[0], s.c[6]};
+    return t;
+}
+struct big our_big(struct mixed m, double d, struct big b) {
+    b.a += m.i;
+    b.c = b.c * d + m.d;
+    return b;
+}
+int main(void) {
+    struct pair p = make_pair(1.25);
+    struct mixed m = make_mixed(9);
+    struct seven s = make_seven();
+    struct twelve t = our_twelve(4, s);
+    struct big b = {10, 20, 30};
+    b = add_big(5, b);
+    if (p.x != 1.25 || p.y != 2.5 || m.i != 9 || m.d != 4.5 || s.c[0] != 1 || s.c[6] != 7)
+        return 1;
+    if (b.a != 15 || b.b != 20 || b.c != 30 || sum_all(1, 2, 3, 4, 5, t, s, m, p) != 184)
+        return 2;
+    return call_back();
+}
+",
+    );
+    let library_file = write_source(
+        &dir,
+        "library.c",
+        "#include <stdio.h>
+struct pair { double x; double y; };
+struct mixed { int i; double d; };
+struct seven { char c[7]; };
+struct twelve { int a; int b; int c; };
+struct big { long a; long b; long c; };
+struct pair make_pair(double x) { struct pair p = {x, x * 2}; return p; }
+struct mixed make_mixed(int i) { struct mixed m = {i, i / 2.0}; return m; }
+struct seven make_seven(void) { struct seven s = {{1, 2, 3, 4, 5, 6, 7}}; return s; }
+struct big add_big(long a, struct big b) { b.a += a; return b; }
+long sum_all(long a, long b, long c, long d, long e, struct twelve t, struct seven s,
+             struct mixed m, struct pair p) {
+    long sum = a + b + c + d + e + t.a + t.b + t.c + m.i + (long)(m.d + p.x + p.y);
+    for (int i = 0; i < 7; i++)
+        sum += s.c[i] * (i + 1);
+    return sum;
+}
+struct twelve our_twelve(int a, struct seven s);
+struct big our_big(struct mixed m, double d, struct big b);
+int call_back(void) {
+    struct seven s = {{9, 8, 7, 6, 5, 4, 3}};
+    struct twelve t = our_twelve(5, s);
+    struct mixed m = {2, 0.5};
+    struct big b = {1, 2, 3};
+    b = our_big(m, 2.5, b);
+    printf(\"%d %d %d %ld %ld %ld\\n\", t.a, t.b, t.c, b.a, b.b, b.c);
+    return 0;
+}
+",
+    );
+    let library_object = dir.join("library.o");
+    let status = Command::new("gcc")
+        .args(["-c", &library_file, "-o", library_object.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = compile(&dir, &[&main_file, "-c"]);
+    assert!(output.status.success());
+
+    let executable = dir.join("linked");
+    let status = Command::new("gcc")
+        .arg(dir.join("program"))
+        .arg(&library_object)
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "5 9 3 3 2 8\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Naming a structure before it's declared declares an incomplete type that can be completed later
+#[test]
+fn declares_unknown_tags() {
+    let (code, _) = run_program(
+        "unknown_tags",
+        "int get(struct counter *c);
+struct counter *last;
+struct counter {
+    int value;
+};
+int get(struct counter *c) {
+    return c->value;
+}
+int main(void) {
+    struct counter c = {42};
+    last = &c;
+    return get(last);
+}
+",
+    );
+    assert_eq!(code, Some(42));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::{compile, make_dir, run_program, write_source};
+use std::fs;
+
+// Case values are converted to the promoted type of the controlling expression
+#[test]
+fn converts_case_values() {
+    let (code, _) = run_program(
+        "case_conversions",
+        "int main(void) {
+    int x = 1;
+    unsigned char c = 255;
+    long l = 4294967297L;
+    int result = 0;
+    switch (x) {
+    case 4294967297L:
+        result += 1;
+        break;
+    case 2:
+        result += 100;
+    }
+    switch (c) {
+    case -1:
+        result += 100;
+        break;
+    case 255:
+        result += 10;
+    }
+    switch (l) {
+    case 1:
+        result += 100;
+        break;
+    case 4294967297L:
+        result += 20;
+    }
+    return result;
+}
+",
+    );
+    assert_eq!(code, Some(31));
+}
+
+#[test]
+fn rejects_duplicate_converted_cases() {
+    let dir = make_dir("duplicate_cases");
+    let main_file = write_source(
+        &dir,
+        "main.c",
+        "int main(void) {
+    int x = 1;
+    switch (x) {
+    case 1:;
+    case 4294967297L:;
+    }
+    return 0;
+}
+",
+    );
+
+    let output = compile(&dir, &[&main_file]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Duplicate case value in switch: 1"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Case labels can sit inside nested statements of the body, like in Duff's device
+#[test]
+fn jumps_into_nested_statements() {
+    let (code, stdout) = run_program(
+        "case_placement",
+        "int printf(const char *format, ...);
+void copy(char *to, const char *from, int count) {
+    int n = (count + 3) / 4;
+    switch (count % 4) {
+    case 0:
+        do {
+            *to++ = *from++;
+        case 3:
+            *to++ = *from++;
+        case 2:
+            *to++ = *from++;
+        case 1:
+            *to++ = *from++;
+        } while (--n > 0);
+    }
+}
+int classify(int value) {
+    switch (value) {
+        int unused;
+    default:
+        return -1;
+    case 1:
+        if (value) {
+        case 2:
+            return 20;
+        }
+    case 3:
+        for (;;) {
+            break;
+        }
+        return 30;
+    }
+}
+int main(void) {
+    char buffer[16] = {0};
+    copy(buffer, \"duff's device\", 13);
+    int total = 0;
+    for (int i = 0; i < 5; i++) {
+        switch (i) {
+        case 1:
+            continue;
+        case 3:
+            total += 100;
+            break;
+        }
+        total += i;
+    }
+    printf(\"%s %d %d %d %d\\n\", buffer, classify(1), classify(2), classify(3), classify(9));
+    switch (0) {}
+    return total;
+}
+",
+    );
+    assert_eq!(stdout, "duff's device 20 20 30 -1\n");
+    assert_eq!(code, Some(109));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::run_program;
+
+// Typedef names stand for their type until a declaration in an inner scope hides them
+#[test]
+fn uses_typedef_names() {
+    let (code, _) = run_program(
+        "typedefs",
+        "typedef unsigned long size;
+typedef struct point {
+    int x;
+    int y;
+} point;
+typedef point *point_pointer;
+typedef int row[3];
+typedef row *rows;
+int add(int a, int b) { return a + b; }
+int apply(point p) { return add(p.x, p.y); }
+int main(void) {
+    point p = {3, 4};
+    point_pointer pp = &p;
+    row numbers = {1, 2, 3};
+    rows r = &numbers;
+    size s = -1;
+    int point = 10;
+    {
+        typedef long point;
+        point shadow = 4294967301;
+        if (shadow != 4294967301 || pp->y != 4)
+            return 1;
+    }
+    if (apply(p) != 7 || (*r)[2] != 3 || point != 10)
+        return 2;
+    return s > 0;
+}
+",
+    );
+    assert_eq!(code, Some(1));
+}
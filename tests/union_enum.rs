@@ -0,0 +1,46 @@
+mod common;
+
+use common::run_program;
+
+// Union members share their storage, enumerators are int constants numbered from the last value
+#[test]
+fn shares_union_storage() {
+    let (code, _) = run_program(
+        "unions_enums",
+        "enum color { RED, GREEN = 5, BLUE, LAST = 12 };
+union value {
+    long integer;
+    double real;
+    unsigned char bytes[8];
+};
+struct tagged {
+    enum color color;
+    union value value;
+};
+union value make_real(double real) {
+    union value value;
+    value.real = real;
+    return value;
+}
+int main(void) {
+    union value v = {258};
+    struct tagged tagged = {BLUE, {7}};
+    enum color c = GREEN;
+    union value real = make_real(1.0);
+    if (RED != 0 || c != 5 || BLUE != 6 || LAST != 12)
+        return 1;
+    if (v.bytes[0] != 2 || v.bytes[1] != 1 || tagged.value.integer != 7 || tagged.color != BLUE)
+        return 2;
+    if (real.bytes[7] != 63 || real.integer != 4607182418800017408)
+        return 3;
+    switch (c) {
+    case GREEN:
+        return 4;
+    default:
+        return 0;
+    }
+}
+",
+    );
+    assert_eq!(code, Some(4));
+}
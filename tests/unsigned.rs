@@ -0,0 +1,34 @@
+mod common;
+
+use common::run_program;
+
+// Unsigned values wrap around and mixed operands go through the usual arithmetic conversions,
+// which make -1 the largest unsigned value
+#[test]
+fn converts_unsigned_values() {
+    let (code, _) = run_program(
+        "unsigned_arithmetic",
+        "unsigned int wrap(unsigned int value) { return value + 1; }
+int main(void) {
+    unsigned int max = 4294967295U;
+    unsigned long ul = 18446744073709551615UL;
+    int negative = -1;
+    unsigned int u = 10;
+    long sign_extended = negative;
+    unsigned int reinterpreted = negative;
+    unsigned long zero_extended = reinterpreted;
+    unsigned int truncated = 4294967296;
+    if (wrap(max) != 0 || ul / 3 != 6148914691236517205UL || max / 7 != 613566756)
+        return 1;
+    if (zero_extended != 4294967295 || sign_extended != -1 || u - 20 != 4294967286U)
+        return 2;
+    if (!(negative > u) || !(negative < 10L) || !(-1L < 4294967295U))
+        return 3;
+    if (max >> 28 != 15 || ul >> 60 != 15 || truncated + 3u != 3)
+        return 4;
+    return (u - 11) / 1000000000u;
+}
+",
+    );
+    assert_eq!(code, Some(4));
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::run_program;
+
+// printf is declared exactly as libc declares it
+#[test]
+fn calls_printf() {
+    let (code, stdout) = run_program(
+        "printf",
+        "int printf(const char *, ...);
+int main(void) {
+    long big = 1234567890123;
+    unsigned char c = 200;
+    printf(\"%s %d %c %u\\n\", \"hello\", 42, 'A', c);
+    printf(\"%ld %f %.3f\\n\", big, 2.5, 5.0);
+    printf(\"%d %f %d %f %d %f %d %f %d %f %d %f %d %f %d %f %d %f\\n\", 1, 1.5, 2, 2.5, 3,
+           3.5, 4, 4.5, 5, 5.5, 6, 6.5, 7, 7.5, 8, 8.5, 9, 9.5);
+    return printf(\"no arguments\\n\");
+}
+",
+    );
+    assert_eq!(
+        stdout,
+        "hello 42 A 200
+1234567890123 2.500000 5.000
+1 1.500000 2 2.500000 3 3.500000 4 4.500000 5 5.500000 6 6.500000 7 7.500000 8 8.500000 \
+9 9.500000
+no arguments
+"
+    );
+    assert_eq!(code, Some(13));
+}
+
+// Arguments past the registers go on the stack, characters are promoted to int
+#[test]
+fn passes_extra_arguments_on_the_stack() {
+    let (code, stdout) = run_program(
+        "variadic_stack",
+        "int printf(char *format, ...);
+int snprintf(char *buffer, unsigned long size, char *format, ...);
+int main(void) {
+    char buffer[64];
+    char c = 'x';
+    unsigned char u = 250;
+    int length = snprintf(buffer, sizeof buffer, \"%d|%d|%d|%d|%d|%d|%d|%d|%c|%d|%f|%s\", 1, 2, 3,
+                          4, 5, 6, 7, 8, c, u, 0.25, \"end\");
+    printf(\"%s %d\\n\", buffer, length);
+    return snprintf(0, 0, \"%ld\", 1234567890123L);
+}
+",
+    );
+    assert_eq!(stdout, "1|2|3|4|5|6|7|8|x|250|0.250000|end 34\n");
+    assert_eq!(code, Some(13));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::run_program;
+
+// va_arg reads integers, doubles and structures from the registers and then the stack
+#[test]
+fn reads_variadic_arguments() {
+    let (code, stdout) = run_program(
+        "va_arg",
+        "int printf(char *format, ...);
+int vsnprintf(char *buffer, unsigned long size, char *format, va_list args);
+struct pair {
+    long a;
+    long b;
+};
+double mean(int count, ...) {
+    va_list args;
+    va_start(args, count);
+    double total = 0;
+    for (int i = 0; i < count; i++)
+        total += va_arg(args, double);
+    va_end(args);
+    return total / count;
+}
+long mixed(int count, ...) {
+    va_list args;
+    va_list copy;
+    va_start(args, count);
+    va_copy(copy, args);
+    long total = 0;
+    for (int i = 0; i < count; i++) {
+        total += va_arg(args, int);
+        total += (long)va_arg(args, double);
+        total += *va_arg(args, long *);
+    }
+    struct pair p = va_arg(args, struct pair);
+    total += p.a * p.b + va_arg(copy, int);
+    va_end(copy);
+    va_end(args);
+    return total;
+}
+int log_line(char *format, ...) {
+    char buffer[64];
+    va_list args;
+    va_start(args, format);
+    int length = vsnprintf(buffer, sizeof buffer, format, args);
+    va_end(args);
+    printf(\"[log] %s\\n\", buffer);
+    return length;
+}
+int main(void) {
+    long value = 100;
+    struct pair p = {6, 7};
+    printf(\"%f %f\\n\", mean(3, 1.0, 2.0, 4.5), mean(9, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0,
+                                                    9.0));
+    printf(\"%ld\\n\", mixed(4, 1, 1.5, &value, 2, 2.5, &value, 3, 3.5, &value, 4, 4.5, &value, p));
+    return log_line(\"%s %d %f\", \"args\", 42, 0.5);
+}
+",
+    );
+    assert_eq!(stdout, "2.500000 5.000000\n463\n[log] args 42 0.500000\n");
+    assert_eq!(code, Some(16));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::run_program;
+
+// Void functions return nothing, void pointers convert to and from other object pointers
+#[test]
+fn uses_void() {
+    let (code, stdout) = run_program(
+        "void",
+        "int printf(char *format, ...);
+void *malloc(unsigned long size);
+void free(void *pointer);
+void increment(int *value) {
+    if (*value > 100)
+        return;
+    (*value)++;
+}
+void nothing(void) {}
+void chain(void) {
+    nothing();
+    return;
+}
+int main(void) {
+    int counter = 0;
+    void *memory = malloc(sizeof(long));
+    long *l = memory;
+    *l = 5;
+    increment(&counter);
+    (void)increment(&counter);
+    chain();
+    counter ? increment(&counter) : nothing();
+    int *same = (void *)l;
+    printf(\"%d %ld %d\\n\", counter, *(long *)memory, same == (int *)memory);
+    free(memory);
+    return counter;
+}
+",
+    );
+    assert_eq!(stdout, "3 5 1\n");
+    assert_eq!(code, Some(3));
+}